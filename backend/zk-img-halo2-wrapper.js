@@ -5,21 +5,49 @@
 
 const { spawn } = require('child_process');
 const fs = require('fs').promises;
+const os = require('os');
 const path = require('path');
-const crypto = require('crypto');
 
 class ZKIMGHalo2Wrapper {
     constructor() {
         this.cargoPath = path.join(__dirname, 'zk-img-halo2');
+        this.executable = path.join(this.cargoPath, 'target', 'release', 'zk-img-halo2');
+        // Circuit size override (2^k rows); the binary defaults to k=17, or with
+        // size classes to the smallest k the image's class and chain fit
+        this.k = process.env.ZK_IMG_HALO2_K;
+        // IPA params are generated once into this directory and reused by every run
+        this.paramsDir = process.env.ZK_IMG_HALO2_PARAMS_DIR || path.join(this.cargoPath, 'params');
+        // Optional SHA-256 (hex) the params file must have
+        this.paramsHash = process.env.ZK_IMG_HALO2_PARAMS_HASH;
+        // When set, only proofs of circuits registered here by `setup` verify
+        this.vkRegistry = process.env.ZK_IMG_HALO2_VK_REGISTRY;
+        // Pad uploads to standard size classes so a few keys cover every image size
+        this.sizeClasses = process.env.ZK_IMG_HALO2_SIZE_CLASSES === '1';
         this.isCompiled = false;
     }
 
+    /**
+     * Run the Rust binary, resolving with its exit code and output
+     */
+    runBinary(args) {
+        return new Promise((resolve, reject) => {
+            const child = spawn(this.executable, args, { cwd: this.cargoPath });
+            let stdout = '';
+            let stderr = '';
+
+            child.stdout.on('data', data => { stdout += data; });
+            child.stderr.on('data', data => { stderr += data; });
+            child.on('error', reject);
+            child.on('close', code => resolve({ code, stdout, stderr }));
+        });
+    }
+
     /**
      * Check if Rust code is compiled
      */
     async checkCompilation() {
         try {
-            await fs.access(path.join(this.cargoPath, 'target', 'release', 'zk-img-halo2'));
+            await fs.access(this.executable);
             this.isCompiled = true;
             return true;
         } catch {
@@ -29,29 +57,37 @@ class ZKIMGHalo2Wrapper {
 
     /**
      * Compile the Rust Halo2 implementation
-     * Note: Currently using simulation until Rust dependencies are resolved
+     * Throws if cargo is unavailable or the build fails
      */
     async compile() {
-        console.log('🔨 Initializing ZK-IMG Halo2 implementation...');
+        console.log('🔨 Compiling ZK-IMG Halo2 implementation...');
 
-        // For now, simulate compilation success
-        // TODO: Fix Rust dependency versions and enable actual compilation
-        await new Promise(resolve => setTimeout(resolve, 100));
+        const code = await new Promise(resolve => {
+            const cargo = spawn('cargo', ['build', '--release'], { cwd: this.cargoPath, stdio: 'inherit' });
+            cargo.on('error', () => resolve(-1));
+            cargo.on('close', resolve);
+        });
 
-        this.isCompiled = true;
-        console.log('✅ Halo2 implementation ready (simulation mode)');
-        console.log('📝 Note: Using optimized simulation until Rust dependencies are resolved');
+        if (code !== 0 || !(await this.checkCompilation())) {
+            throw new Error(`Halo2 build failed with code ${code}`);
+        }
+        console.log('✅ Halo2 implementation ready');
+    }
 
-        return Promise.resolve();
+    /**
+     * Make sure the Rust binary exists, building it if needed
+     */
+    async ensureCompiled() {
+        if (!this.isCompiled && !(await this.checkCompilation())) {
+            await this.compile();
+        }
     }
 
     /**
      * Generate ZK proof for image transformation
      */
     async generateProof(imageBuffer, transformations) {
-        if (!this.isCompiled) {
-            await this.compile();
-        }
+        await this.ensureCompiled();
 
         console.log('🚀 Generating Halo2 proof...');
 
@@ -62,74 +98,77 @@ class ZKIMGHalo2Wrapper {
             timestamp: Date.now()
         };
 
-        // Write input to temporary file
-        const inputPath = path.join(this.cargoPath, 'input.json');
-        await fs.writeFile(inputPath, JSON.stringify(inputData, null, 2));
+        // Each call gets its own directory so concurrent proofs never share files
+        const workDir = await fs.mkdtemp(path.join(os.tmpdir(), 'zk-img-halo2-'));
+        try {
+            const inputPath = path.join(workDir, 'input.json');
+            const outputPath = path.join(workDir, 'proof.json');
+            await fs.writeFile(inputPath, JSON.stringify(inputData, null, 2));
 
-        return new Promise((resolve, reject) => {
-            const executable = path.join(this.cargoPath, 'target', 'release', 'zk-img-halo2');
-
-            // For now, we'll simulate the proof generation
-            // In real implementation, this would call the actual Rust binary
-            setTimeout(async () => {
-                try {
-                    // Generate mock proof data (replace with actual Rust call)
-                    const proof = {
-                        proof_bytes: crypto.randomBytes(256).toString('hex'),
-                        public_inputs: [
-                            crypto.createHash('sha256').update(imageBuffer).digest('hex').slice(0, 64),
-                            crypto.createHash('sha256').update(JSON.stringify(transformations)).digest('hex').slice(0, 64)
-                        ],
-                        transformation_chain: transformations,
-                        input_hash: crypto.createHash('sha256').update(imageBuffer).digest('hex'),
-                        output_hash: crypto.createHash('sha256').update(imageBuffer.slice(100, 200)).digest('hex'),
-                        verification_key: crypto.randomBytes(128).toString('hex'),
-                        performance: {
-                            generation_time_ms: Math.random() * 50 + 10, // 10-60ms
-                            proof_size_kb: Math.random() * 10 + 40, // 40-50KB
-                            verification_time_ms: Math.random() * 2 + 3 // 3-5ms
-                        }
-                    };
-
-                    // Clean up input file
-                    await fs.unlink(inputPath).catch(() => {});
-
-                    console.log(`✅ Halo2 proof generated in ${proof.performance.generation_time_ms.toFixed(1)}ms`);
-                    console.log(`📊 Proof size: ${proof.performance.proof_size_kb.toFixed(1)}KB`);
-
-                    resolve(proof);
-                } catch (error) {
-                    reject(error);
-                }
-            }, 100 + Math.random() * 200); // Simulate 100-300ms processing
-        });
+            const args = ['prove', inputPath, '--output', outputPath];
+            if (this.k) {
+                args.push('--k', String(this.k));
+            }
+            args.push('--params-dir', this.paramsDir);
+            if (this.paramsHash) {
+                args.push('--params-hash', this.paramsHash);
+            }
+            if (this.sizeClasses) {
+                args.push('--size-classes');
+            }
+
+            const { code, stderr } = await this.runBinary(args);
+            if (code !== 0) {
+                throw new Error(`Halo2 prover exited with code ${code}: ${stderr.trim().split('\n').pop()}`);
+            }
+
+            const proof = JSON.parse(await fs.readFile(outputPath, 'utf8'));
+            proof.performance = {
+                generation_time_ms: proof.metrics.setup_time_ms + proof.metrics.proving_time_ms,
+                proof_size_kb: proof.metrics.proof_size_bytes / 1024,
+                verification_time_ms: proof.metrics.verification_time_ms
+            };
+
+            console.log(`✅ Halo2 proof generated in ${proof.performance.generation_time_ms.toFixed(1)}ms`);
+            console.log(`📊 Proof size: ${proof.performance.proof_size_kb.toFixed(1)}KB`);
+            return proof;
+        } finally {
+            await fs.rm(workDir, { recursive: true, force: true }).catch(() => {});
+        }
     }
 
     /**
      * Verify ZK proof
      */
     async verifyProof(proof, publicInputs) {
-        if (!this.isCompiled) {
-            await this.compile();
-        }
+        await this.ensureCompiled();
 
         console.log('🔍 Verifying Halo2 proof...');
 
-        // Simulate verification (in real implementation, call Rust binary)
-        return new Promise((resolve) => {
-            setTimeout(() => {
-                // Mock verification - in real implementation, this would actually verify
-                const isValid = Math.random() > 0.05; // 95% success rate
+        const workDir = await fs.mkdtemp(path.join(os.tmpdir(), 'zk-img-halo2-'));
+        try {
+            const proofPath = path.join(workDir, 'proof.json');
+            await fs.writeFile(proofPath, JSON.stringify({ ...proof, public_inputs: publicInputs }));
 
-                if (isValid) {
-                    console.log('✅ Halo2 proof verification successful');
-                } else {
-                    console.log('❌ Halo2 proof verification failed');
-                }
+            const args = ['verify', proofPath, '--params-dir', this.paramsDir];
+            if (this.paramsHash) {
+                args.push('--params-hash', this.paramsHash);
+            }
+            if (this.vkRegistry) {
+                args.push('--vk-registry', this.vkRegistry);
+            }
 
-                resolve(isValid);
-            }, 5 + Math.random() * 10); // Simulate 5-15ms verification
-        });
+            const { code, stderr } = await this.runBinary(args);
+            if (code === 2) {
+                throw new Error(`Halo2 verifier failed: ${stderr.trim().split('\n').pop()}`);
+            }
+
+            const isValid = code === 0;
+            console.log(isValid ? '✅ Halo2 proof verification successful' : '❌ Halo2 proof verification failed');
+            return isValid;
+        } finally {
+            await fs.rm(workDir, { recursive: true, force: true }).catch(() => {});
+        }
     }
 
     /**
@@ -161,7 +200,6 @@ class ZKIMGHalo2Wrapper {
                 'Poseidon hashing for privacy',
                 'Operation fusion for efficiency',
                 'HD image tiling support',
-                'Recursive proof chains',
                 'Parallel proof generation',
                 'Memory-efficient field operations'
             ],
@@ -0,0 +1,65 @@
+//! Constraint cost of fused operations against the chains they replace
+//!
+//! Run with `cargo bench --bench fusion`. Costs come from laying the circuits
+//! out, not from proving, so the numbers are exact and the run is quick.
+
+use zk_img_halo2::{
+    circuits::{FusedOperation, FusedOperationCircuit},
+    cost::CircuitCost,
+    transforms::{Rational, ResizeFilter},
+};
+
+const SIZES: [(usize, usize); 3] = [(16, 16), (32, 32), (64, 48)];
+
+fn operations(width: usize, height: usize) -> Vec<(&'static str, Vec<FusedOperation>)> {
+    let (w, h) = (width as u32, height as u32);
+    let crop_resize = FusedOperation::CropResize {
+        crop_x: w / 4,
+        crop_y: h / 4,
+        crop_w: w / 2,
+        crop_h: h / 2,
+        resize_w: w / 3,
+        resize_h: h / 3,
+        filter: ResizeFilter::Bilinear,
+    };
+    let grayscale_contrast = FusedOperation::GrayscaleContrast {
+        contrast: Rational::new(3, 2).unwrap(),
+    };
+
+    vec![
+        ("crop+resize", vec![crop_resize.clone()]),
+        ("grayscale+contrast", vec![grayscale_contrast.clone()]),
+        ("both", vec![crop_resize, grayscale_contrast]),
+    ]
+}
+
+fn row(label: &str, cost: &CircuitCost) {
+    println!(
+        "  {:<10} {:>9} {:>10} {:>12} {:>9} {:>4}",
+        label, cost.rows, cost.gate_rows, cost.advice_cells, cost.copies, cost.min_k
+    );
+}
+
+fn main() {
+    println!("📊 Fused vs unfused constraint cost");
+
+    for (width, height) in SIZES {
+        for (name, ops) in operations(width, height) {
+            let circuit = FusedOperationCircuit::blank(width, height, ops);
+            let (fused, unfused) = circuit.cost_comparison().expect("circuit lays out");
+
+            println!("\n{}x{} {}", width, height, name);
+            println!(
+                "  {:<10} {:>9} {:>10} {:>12} {:>9} {:>4}",
+                "", "rows", "gate rows", "advice cells", "copies", "k"
+            );
+            row("unfused", &unfused);
+            row("fused", &fused);
+            println!(
+                "  saved      {:>8.1}% of gate rows, {:.1}% of advice cells",
+                100.0 * (1.0 - fused.gate_rows as f64 / unfused.gate_rows as f64),
+                100.0 * (1.0 - fused.advice_cells as f64 / unfused.advice_cells as f64)
+            );
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! IPA accumulation (Halo, Section 8.3 of the paper)
+//!
+//! Verifying a halo2 proof over the Pasta curves is cheap except for one
+//! step: checking that the point `G` the inner product argument ends with is
+//! the commitment `⟨s(u), params.g⟩` to the polynomial
+//! `g_u(X) = Π (1 + u_{k-1-i} X^{2^i})` of its round challenges `u`, a
+//! multiexponentiation of size `2^k`. Accumulation defers that check:
+//!
+//! - `AccumulatorStrategy` verifies a proof against a prover-supplied `G`,
+//!   leaving the claim `(G, u)` as an `IpaAccumulator`.
+//! - `fold` merges accumulators into one: the prover commits to
+//!   `Σ α^i g_{u_i}(X)`, which is `Σ α^i G_i` by linearity, and opens it at a
+//!   random `z` where the verifier can evaluate every `g_{u_i}` in `O(k)`.
+//!   That opening proof ends with a new `(G, u)` claim, which replaces all
+//!   the folded ones.
+//! - `IpaAccumulator::decide` finally checks the one remaining claim with a
+//!   single multiexponentiation.
+//!
+//! All transcripts are Blake2b, like the proofs themselves.
+
+use ff::Field;
+use group::{Curve, Group};
+use halo2_proofs::{
+    arithmetic::best_multiexp,
+    pasta::{Eq, EqAffine, Fp},
+    plonk::{verify_proof, Error, VerificationStrategy, VerifyingKey},
+    poly::{
+        commitment::{self, Blind, Guard, Params, MSM},
+        EvaluationDomain,
+    },
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, Transcript},
+};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+/// Deferred claim that `g` commits to `g_u(X)` for the `challenges` u
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpaAccumulator {
+    pub g: EqAffine,
+    pub challenges: Vec<Fp>,
+}
+
+impl IpaAccumulator {
+    fn from_guard<E: EncodedChallenge<EqAffine>>(
+        guard: Guard<'_, EqAffine, E>,
+        g: Option<EqAffine>,
+    ) -> Result<Self, Error> {
+        let g = g.unwrap_or_else(|| guard.compute_g());
+        let (msm, accumulator) = guard.use_g(g);
+        if !msm.eval() {
+            return Err(Error::ConstraintSystemFailure);
+        }
+
+        Ok(Self {
+            g,
+            challenges: accumulator.u_packed.iter().map(|u| *u.as_challenge_scalar::<()>()).collect(),
+        })
+    }
+
+    /// Check the claim directly, the one linear-time step of verification
+    pub fn decide(&self, params: &Params<EqAffine>) -> bool {
+        if self.challenges.len() != params.k() as usize {
+            return false;
+        }
+        best_multiexp(&s_coefficients(&self.challenges), &params.get_g()).to_affine() == self.g
+    }
+
+    /// `g_u(z)` in `O(k)`
+    fn evaluate(&self, z: Fp) -> Fp {
+        let mut value = Fp::ONE;
+        let mut power = z;
+        for u in self.challenges.iter().rev() {
+            value *= Fp::ONE + *u * power;
+            power = power.square();
+        }
+        value
+    }
+}
+
+/// Verification strategy leaving the final IPA check as an `IpaAccumulator`
+///
+/// Without a `g` it computes the point itself, which is what the prover does
+/// to produce the `g` a verifier is later given.
+#[derive(Debug)]
+pub struct AccumulatorStrategy<'params> {
+    msm: MSM<'params, EqAffine>,
+    g: Option<EqAffine>,
+}
+
+impl<'params> AccumulatorStrategy<'params> {
+    pub fn new(params: &'params Params<EqAffine>, g: Option<EqAffine>) -> Self {
+        Self {
+            msm: params.empty_msm(),
+            g,
+        }
+    }
+}
+
+impl<'params> VerificationStrategy<'params, EqAffine> for AccumulatorStrategy<'params> {
+    type Output = IpaAccumulator;
+
+    fn process<E: EncodedChallenge<EqAffine>>(
+        self,
+        f: impl FnOnce(MSM<'params, EqAffine>) -> Result<Guard<'params, EqAffine, E>, Error>,
+    ) -> Result<Self::Output, Error> {
+        IpaAccumulator::from_guard(f(self.msm)?, self.g)
+    }
+}
+
+/// Verify `proof` up to its inner product check, returned as an accumulator
+///
+/// With `g` the proof is checked against that claimed point, as a verifier
+/// does; without it the point is computed, as the prover does.
+pub fn accumulate(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    public_inputs: &[Fp],
+    g: Option<EqAffine>,
+) -> Result<IpaAccumulator, Error> {
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+    let strategy = AccumulatorStrategy::new(params, g);
+    verify_proof(params, vk, strategy, &[&[public_inputs]], &mut transcript)
+}
+
+/// Opening proof merging several accumulators into `accumulator`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FoldProof {
+    pub proof_bytes: Vec<u8>,
+    pub accumulator: IpaAccumulator,
+}
+
+/// Fold `accumulators` into one, failing if any of them does not hold
+pub fn fold(params: &Params<EqAffine>, accumulators: &[IpaAccumulator]) -> Result<FoldProof, Error> {
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    let (alpha, z, _, _) = fold_claim(&mut transcript, accumulators)?;
+
+    // Σ α^i g_{u_i}(X), whose commitment is `combined` if every claim holds
+    let n = 1usize << params.k();
+    let mut coefficients = vec![Fp::ZERO; n];
+    let mut scale = Fp::ONE;
+    for accumulator in accumulators {
+        for (coefficient, s) in coefficients.iter_mut().zip(s_coefficients(&accumulator.challenges)) {
+            *coefficient += scale * s;
+        }
+        scale *= alpha;
+    }
+
+    let domain = EvaluationDomain::new(1, params.k());
+    commitment::create_proof(params, OsRng, &mut transcript, &domain.coeff_from_vec(coefficients), Blind(Fp::ZERO), z)
+        .map_err(|_| Error::Synthesis)?;
+    let proof_bytes = transcript.finalize();
+
+    let accumulator = open_fold(params, accumulators, &proof_bytes, None)?;
+    Ok(FoldProof { proof_bytes, accumulator })
+}
+
+/// Check a fold of `accumulators`, returning the accumulator it produced
+pub fn verify_fold(
+    params: &Params<EqAffine>,
+    accumulators: &[IpaAccumulator],
+    proof: &FoldProof,
+) -> Result<IpaAccumulator, Error> {
+    let accumulator = open_fold(params, accumulators, &proof.proof_bytes, Some(proof.accumulator.g))?;
+    if accumulator != proof.accumulator {
+        return Err(Error::ConstraintSystemFailure);
+    }
+    Ok(accumulator)
+}
+
+fn open_fold(
+    params: &Params<EqAffine>,
+    accumulators: &[IpaAccumulator],
+    proof_bytes: &[u8],
+    g: Option<EqAffine>,
+) -> Result<IpaAccumulator, Error> {
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof_bytes);
+    let (_, z, combined, value) = fold_claim(&mut transcript, accumulators)?;
+
+    let mut msm = params.empty_msm();
+    msm.append_term(Fp::ONE, combined);
+    let guard = commitment::verify_proof(params, msm, &mut transcript, z, value)
+        .map_err(|_| Error::ConstraintSystemFailure)?;
+    IpaAccumulator::from_guard(guard, g)
+}
+
+/// Absorb the accumulators and derive `(α, z, Σ α^i G_i, Σ α^i g_{u_i}(z))`
+fn fold_claim<T: Transcript<EqAffine, Challenge255<EqAffine>>>(
+    transcript: &mut T,
+    accumulators: &[IpaAccumulator],
+) -> Result<(Fp, Fp, EqAffine, Fp), Error> {
+    if accumulators.is_empty() {
+        return Err(Error::Synthesis);
+    }
+    for accumulator in accumulators {
+        transcript.common_point(accumulator.g)?;
+        for &u in &accumulator.challenges {
+            transcript.common_scalar(u)?;
+        }
+    }
+    let alpha = *transcript.squeeze_challenge_scalar::<()>();
+    let z = *transcript.squeeze_challenge_scalar::<()>();
+
+    let mut scale = Fp::ONE;
+    let mut combined = Eq::identity();
+    let mut value = Fp::ZERO;
+    for accumulator in accumulators {
+        combined += accumulator.g * scale;
+        value += scale * accumulator.evaluate(z);
+        scale *= alpha;
+    }
+    transcript.common_scalar(value)?;
+
+    Ok((alpha, z, combined.to_affine(), value))
+}
+
+/// Coefficients of `g_u(X) = Π (1 + u_{k-1-i} X^{2^i})`
+fn s_coefficients(challenges: &[Fp]) -> Vec<Fp> {
+    let mut s = vec![Fp::ZERO; 1 << challenges.len()];
+    s[0] = Fp::ONE;
+    for (i, u) in challenges.iter().rev().enumerate() {
+        let len = 1 << i;
+        let (low, high) = s.split_at_mut(len);
+        for (high, low) in high[..len].iter_mut().zip(low.iter()) {
+            *high = *low * u;
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn random_accumulator(params: &Params<EqAffine>, rng: &mut StdRng) -> IpaAccumulator {
+        let challenges: Vec<Fp> = (0..params.k()).map(|_| Fp::random(&mut *rng)).collect();
+        let g = best_multiexp(&s_coefficients(&challenges), &params.get_g()).to_affine();
+        IpaAccumulator { g, challenges }
+    }
+
+    #[test]
+    fn s_coefficients_evaluate_to_g_u() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let challenges: Vec<Fp> = (0..4).map(|_| Fp::random(&mut rng)).collect();
+        let accumulator = IpaAccumulator { g: EqAffine::default(), challenges };
+
+        let z = Fp::random(&mut rng);
+        let direct = s_coefficients(&accumulator.challenges)
+            .iter()
+            .rev()
+            .fold(Fp::ZERO, |value, coefficient| value * z + coefficient);
+        assert_eq!(direct, accumulator.evaluate(z));
+    }
+
+    #[test]
+    fn folded_accumulators_decide_together() {
+        let params = Params::<EqAffine>::new(4);
+        let mut rng = StdRng::seed_from_u64(1);
+        let accumulators: Vec<_> = (0..3).map(|_| random_accumulator(&params, &mut rng)).collect();
+
+        let proof = fold(&params, &accumulators).unwrap();
+        assert_eq!(verify_fold(&params, &accumulators, &proof).unwrap(), proof.accumulator);
+        assert!(proof.accumulator.decide(&params));
+
+        // A folded claim that does not hold cannot be folded into one that does
+        let mut forged = accumulators.clone();
+        forged[1].g = accumulators[0].g;
+        assert!(verify_fold(&params, &forged, &proof).is_err());
+        assert!(fold(&params, &forged).is_err());
+    }
+}
@@ -0,0 +1,473 @@
+//! Brightness, contrast and white-balance gadgets
+//!
+//! Brightness, contrast and tone maps are per-channel affine maps with
+//! constant weights, proven by `LinearChip` including the clamp.
+//!
+//! White balance is data dependent, so every intermediate value is proven:
+//! the channel averages are `LinearChip::average`s of the channel cells,
+//! their sum `total` is constrained by a gate, and each gain is proven by
+//!
+//! ```text
+//!   | avg  | total | gain | rem |   (q_gain)
+//!   | zero | inv   | gap  |     |
+//! ```
+//!
+//! `zero` is 1 exactly when `avg` is 0 (`avg * inv = 1 - zero`,
+//! `avg * zero = 0`), and
+//! `256 * (zero ? 1 : total) = gain * (3 * avg + zero) + rem` with `rem` and
+//! `gap = 3 * avg + zero - 1 - rem` range-checked, matching
+//! `filters::white_balance_gains`. Pixels are then scaled by their gain with
+//! `LinearChip::scale`.
+
+use ff::Field;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::{
+    linear::{LinearChip, LinearConfig},
+    range::{low_u64, RangeCheckChip, RangeCheckConfig, U32_BOUND},
+    AssignedImage,
+};
+use crate::transforms::{
+    filters::{self, WHITE_BALANCE_GAIN_BITS},
+    IntegerWeights, Rational, ToneMap,
+};
+
+#[derive(Clone, Debug)]
+pub struct AdjustConfig {
+    advice: [Column<Advice>; 4],
+    q_total: Selector,
+    q_gain: Selector,
+    linear: LinearConfig,
+    range: RangeCheckConfig,
+}
+
+/// Chip applying brightness, contrast and white balance to an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct AdjustChip {
+    config: AdjustConfig,
+}
+
+impl AdjustChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        advice: [Column<Advice>; 4],
+        linear: LinearConfig,
+        range: RangeCheckConfig,
+    ) -> AdjustConfig {
+        for column in advice {
+            meta.enable_equality(column);
+        }
+
+        let q_total = meta.selector();
+        let q_gain = meta.selector();
+        let [a0, a1, a2, a3] = advice;
+
+        meta.create_gate("sum of averages", |meta| {
+            let q = meta.query_selector(q_total);
+            let averages = [a0, a1, a2].map(|column| meta.query_advice(column, Rotation::cur()));
+            let total = meta.query_advice(a3, Rotation::cur());
+
+            let [r, g, b] = averages;
+            Constraints::with_selector(q, Some(total - (r + g + b)))
+        });
+
+        meta.create_gate("white balance gain", |meta| {
+            let q = meta.query_selector(q_gain);
+            let avg = meta.query_advice(a0, Rotation::cur());
+            let total = meta.query_advice(a1, Rotation::cur());
+            let gain = meta.query_advice(a2, Rotation::cur());
+            let rem = meta.query_advice(a3, Rotation::cur());
+            let zero = meta.query_advice(a0, Rotation::next());
+            let inv = meta.query_advice(a1, Rotation::next());
+            let gap = meta.query_advice(a2, Rotation::next());
+
+            let one = Expression::Constant(Fp::one());
+            let scale = Expression::Constant(Fp::from(1 << WHITE_BALANCE_GAIN_BITS));
+            let divisor = avg.clone() * Expression::Constant(Fp::from(3)) + zero.clone();
+            let numerator = scale * (total.clone() + zero.clone() * (one.clone() - total));
+
+            Constraints::with_selector(
+                q,
+                [
+                    ("inverse", avg.clone() * inv - (one.clone() - zero.clone())),
+                    ("zero flag", avg * zero),
+                    ("division", numerator - (gain * divisor.clone() + rem.clone())),
+                    ("remainder below divisor", gap - (divisor - one - rem)),
+                ],
+            )
+        });
+
+        AdjustConfig {
+            advice,
+            q_total,
+            q_gain,
+            linear,
+            range,
+        }
+    }
+
+    pub fn construct(config: AdjustConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn brightness(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        brightness: Rational,
+    ) -> Result<AssignedImage, Error> {
+        self.map_channels(layouter, image, filters::brightness_weights(brightness))
+    }
+
+    pub fn contrast(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        contrast: Rational,
+    ) -> Result<AssignedImage, Error> {
+        self.map_channels(layouter, image, filters::contrast_weights(contrast))
+    }
+
+    pub fn tone_map(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        tone: &ToneMap,
+    ) -> Result<AssignedImage, Error> {
+        if !tone.is_valid() {
+            return Err(Error::Synthesis);
+        }
+        self.map_channels(layouter, image, tone.integer_weights())
+    }
+
+    /// Gray-world white balance; images may have at most 2^32 pixels
+    pub fn white_balance(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+    ) -> Result<AssignedImage, Error> {
+        if image.pixels.is_empty() {
+            return Ok(image.clone());
+        }
+        if image.pixels.len() as u64 > U32_BOUND {
+            return Err(Error::Synthesis);
+        }
+
+        let linear = LinearChip::construct(self.config.linear.clone());
+        let mut averages = Vec::with_capacity(3);
+        for c in 0..3 {
+            let channel: Vec<_> = image.pixels.iter().map(|pixel| pixel[c].clone()).collect();
+            averages.push(linear.average(layouter.namespace(|| format!("average {}", c)), &channel)?);
+        }
+
+        let gains = self.gains(layouter.namespace(|| "gains"), &averages)?;
+        let jobs: Vec<_> = image
+            .pixels
+            .iter()
+            .flat_map(|pixel| [0, 1, 2].map(|c| [pixel[c].clone(), gains[c].clone()]))
+            .collect();
+        // Only the weight depends on the gain, which here is a cell
+        let rounding = filters::white_balance_weights(0);
+        let outputs = linear.scale(layouter.namespace(|| "apply gains"), &jobs, rounding.bias, rounding.divisor)?;
+
+        Ok(Self::regroup(image, outputs))
+    }
+
+    /// Prove the per-channel gains from the channel averages
+    fn gains(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        averages: &[AssignedCell<Fp, Fp>],
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let config = &self.config;
+
+        let (gains, checked) = layouter.assign_region(
+            || "white balance gains",
+            |mut region| {
+                config.q_total.enable(&mut region, 0)?;
+                let mut total = Value::known(Fp::zero());
+                for (column, average) in config.advice.iter().zip(averages) {
+                    average.copy_advice(|| "average", &mut region, *column, 0)?;
+                    total = total + average.value().copied();
+                }
+                let total = region.assign_advice(|| "total", config.advice[3], 0, || total)?;
+
+                let mut gains = Vec::with_capacity(3);
+                let mut checked = Vec::with_capacity(9);
+                for (c, average) in averages.iter().enumerate() {
+                    let row = 1 + 2 * c;
+                    config.q_gain.enable(&mut region, row)?;
+                    average.copy_advice(|| "average", &mut region, config.advice[0], row)?;
+                    total.copy_advice(|| "total", &mut region, config.advice[1], row)?;
+
+                    let avg = average.value().map(low_u64);
+                    let sum = total.value().map(low_u64);
+                    let divisor = avg.map(|avg| if avg == 0 { 1 } else { 3 * avg });
+                    let gain = avg.zip(sum).map(|(avg, sum)| filters::white_balance_gain(avg, sum));
+                    let numerator = avg.zip(sum).map(|(avg, sum)| if avg == 0 { 1 } else { sum } << WHITE_BALANCE_GAIN_BITS);
+                    let rem = numerator.zip(divisor).map(|(numerator, divisor)| numerator % divisor);
+
+                    let mut assign = |name: &'static str, column: usize, row: usize, value: Value<Fp>| {
+                        region.assign_advice(|| name, config.advice[column], row, || value)
+                    };
+                    let gain = assign("gain", 2, row, gain.map(Fp::from))?;
+                    let rem_cell = assign("remainder", 3, row, rem.map(Fp::from))?;
+                    assign("zero", 0, row + 1, avg.map(|avg| Fp::from((avg == 0) as u64)))?;
+                    assign(
+                        "inverse",
+                        1,
+                        row + 1,
+                        average.value().map(|avg| avg.invert().unwrap_or(Fp::zero())),
+                    )?;
+                    let gap = assign(
+                        "gap",
+                        2,
+                        row + 1,
+                        divisor.zip(rem).map(|(divisor, rem)| Fp::from(divisor - 1 - rem)),
+                    )?;
+
+                    checked.extend([gain.clone(), rem_cell, gap]);
+                    gains.push(gain);
+                }
+                Ok((gains, checked))
+            },
+        )?;
+
+        RangeCheckChip::construct(config.range.clone()).check_u16(layouter.namespace(|| "gain range"), &checked)?;
+
+        Ok(gains)
+    }
+
+    fn map_channels(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        weights: IntegerWeights<1>,
+    ) -> Result<AssignedImage, Error> {
+        let jobs: Vec<_> = image
+            .channels()
+            .map(|channel| ([channel.clone()], weights))
+            .collect();
+        let outputs = LinearChip::construct(self.config.linear.clone()).evaluate(layouter, &jobs)?;
+
+        Ok(Self::regroup(image, outputs))
+    }
+
+    fn regroup(image: &AssignedImage, channels: Vec<AssignedCell<Fp, Fp>>) -> AssignedImage {
+        let pixels = channels
+            .chunks(3)
+            .map(|pixel| [pixel[0].clone(), pixel[1].clone(), pixel[2].clone()])
+            .collect();
+
+        AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    #[derive(Clone, Copy)]
+    enum Adjustment {
+        Brightness(Rational),
+        Contrast(Rational),
+        Tone(ToneMap),
+        WhiteBalance,
+        /// Hand-assigned gain row for `(avg, total)` claiming `gain`
+        ForgedGain { avg: u64, total: u64, gain: u64 },
+    }
+
+    #[derive(Clone)]
+    struct AdjustTestCircuit {
+        source: RgbImage,
+        adjustment: Adjustment,
+    }
+
+    #[derive(Clone, Debug)]
+    struct AdjustTestConfig {
+        advice: [Column<Advice>; 4],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        adjust: AdjustConfig,
+    }
+
+    impl Circuit<Fp> for AdjustTestCircuit {
+        type Config = AdjustTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, [advice[0], advice[1], advice[2]], bytes);
+            let linear = LinearChip::configure(meta, advice, range.clone());
+            let adjust = AdjustChip::configure(meta, advice, linear, range);
+
+            AdjustTestConfig {
+                advice,
+                instance,
+                bytes,
+                adjust,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let chip = AdjustChip::construct(config.adjust.clone());
+
+            if let Adjustment::ForgedGain { avg, total, gain } = self.adjustment {
+                let divisor = if avg == 0 { 1 } else { 3 * avg };
+                let numerator = (if avg == 0 { 1 } else { total }) << WHITE_BALANCE_GAIN_BITS;
+                let rem = Fp::from(numerator) - Fp::from(gain * divisor);
+
+                let gain = layouter.assign_region(
+                    || "forged gain",
+                    |mut region| {
+                        config.adjust.q_gain.enable(&mut region, 0)?;
+                        let inv = Fp::from(avg).invert().unwrap_or(Fp::zero());
+                        let values = [
+                            Fp::from(avg),
+                            Fp::from(total),
+                            Fp::from(gain),
+                            rem,
+                            Fp::from((avg == 0) as u64),
+                            inv,
+                            Fp::from(divisor) - Fp::one() - rem,
+                            Fp::zero(),
+                        ];
+                        let mut cells = Vec::new();
+                        for (i, value) in values.into_iter().enumerate() {
+                            cells.push(region.assign_advice(|| "forged", config.advice[i % 4], i / 4, || Value::known(value))?);
+                        }
+                        Ok(cells)
+                    },
+                )?;
+                RangeCheckChip::construct(config.adjust.range.clone())
+                    .check_u16(layouter.namespace(|| "forged range"), &[gain[2].clone(), gain[3].clone(), gain[6].clone()])?;
+                return layouter.constrain_instance(gain[2].cell(), config.instance, 0);
+            }
+
+            let columns = [config.advice[0], config.advice[1], config.advice[2]];
+            let image = assign_image(&mut layouter, columns, &self.source)?;
+            let adjusted = match self.adjustment {
+                Adjustment::Brightness(value) => chip.brightness(layouter.namespace(|| "brightness"), &image, value)?,
+                Adjustment::Contrast(value) => chip.contrast(layouter.namespace(|| "contrast"), &image, value)?,
+                Adjustment::Tone(tone) => chip.tone_map(layouter.namespace(|| "tone map"), &image, &tone)?,
+                Adjustment::WhiteBalance => chip.white_balance(layouter.namespace(|| "white balance"), &image)?,
+                Adjustment::ForgedGain { .. } => unreachable!(),
+            };
+
+            for (row, cell) in adjusted.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn prove(source: &RgbImage, adjustment: Adjustment, expected: Vec<Fp>) -> bool {
+        let circuit = AdjustTestCircuit {
+            source: source.clone(),
+            adjustment,
+        };
+        MockProver::run(10, &circuit, vec![expected]).unwrap().verify().is_ok()
+    }
+
+    fn native(source: &RgbImage, adjustment: Adjustment) -> Vec<Fp> {
+        let image = DynamicImage::ImageRgb8(source.clone());
+        let adjusted = match adjustment {
+            Adjustment::Brightness(value) => filters::brightness(&image, value),
+            Adjustment::Contrast(value) => filters::contrast(&image, value),
+            Adjustment::Tone(tone) => filters::tone_map(&image, &tone),
+            Adjustment::WhiteBalance => filters::white_balance(&image),
+            Adjustment::ForgedGain { .. } => unreachable!(),
+        };
+        channels(&adjusted.to_rgb8())
+    }
+
+    fn ratio(numerator: i64, denominator: i64) -> Rational {
+        Rational::new(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn affine_adjustments_match_native() {
+        let source = test_image(4, 3);
+        let adjustments = [
+            Adjustment::Brightness(ratio(1, 5)),
+            Adjustment::Brightness(ratio(-3, 7)),
+            Adjustment::Contrast(ratio(3, 2)),
+            Adjustment::Contrast(ratio(1, 3)),
+            Adjustment::Contrast(ratio(-1, 1)),
+            Adjustment::Tone(ToneMap {
+                shift: -40,
+                contrast: ratio(-7, 3),
+                offset: 255,
+            }),
+        ];
+        for adjustment in adjustments {
+            assert!(prove(&source, adjustment, native(&source, adjustment)));
+        }
+    }
+
+    #[test]
+    fn white_balance_matches_native() {
+        let tinted = RgbImage::from_fn(4, 3, |x, y| image::Rgb([200 + (x * 10) as u8, 90 + (y * 20) as u8, 40]));
+        // The blue channel averages to zero and must be left unchanged
+        let no_blue = RgbImage::from_fn(3, 3, |x, y| image::Rgb([(x * 80) as u8, (y * 70) as u8, (x == 2 && y == 2) as u8]));
+
+        for source in [test_image(4, 3), tinted, no_blue] {
+            assert!(prove(&source, Adjustment::WhiteBalance, native(&source, Adjustment::WhiteBalance)));
+        }
+    }
+
+    #[test]
+    fn wrong_adjusted_pixel_is_rejected() {
+        let source = test_image(3, 3);
+        let adjustment = Adjustment::Contrast(ratio(5, 4));
+        let mut expected = native(&source, adjustment);
+        expected[4] += Fp::one();
+
+        assert!(!prove(&source, adjustment, expected));
+    }
+
+    #[test]
+    fn forged_gain_is_rejected() {
+        let (avg, total) = (100, 330);
+        let gain = filters::white_balance_gain(avg, total);
+        let forged = |gain| {
+            let circuit = AdjustTestCircuit {
+                source: RgbImage::new(0, 0),
+                adjustment: Adjustment::ForgedGain { avg, total, gain },
+            };
+            MockProver::run(10, &circuit, vec![vec![Fp::from(gain)]]).unwrap().verify().is_ok()
+        };
+
+        assert!(forged(gain));
+        assert!(!forged(gain + 1));
+        assert!(!forged(gain - 1));
+    }
+}
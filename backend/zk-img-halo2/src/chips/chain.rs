@@ -0,0 +1,78 @@
+//! Transformation-chain digest
+//!
+//! The canonical field encoding of a chain (see `Transformation::encode`) is
+//! absorbed one element at a time into a Poseidon chain:
+//!
+//! ```text
+//!   acc_0 = Poseidon(CHAIN_ENCODING_VERSION, n)
+//!   acc_i = Poseidon(acc_{i-1}, e_i)        for the n encoded elements e_i
+//! ```
+//!
+//! Starting from the length makes the digest injective over encodings of
+//! different sizes. `native_chain_digest` computes the same value outside the
+//! circuit.
+
+use halo2_gadgets::poseidon::Pow5Config;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    pasta::Fp,
+    plonk::{Advice, Column, Error},
+};
+
+use super::commitment::{hash_pair, native_hash_pair};
+
+/// Version of the transformation encoding, bumped whenever it changes
+pub const CHAIN_ENCODING_VERSION: u64 = 1;
+
+/// Chip hashing encoded transformation cells into a single digest
+#[derive(Clone, Debug)]
+pub struct ChainDigestChip {
+    poseidon: Pow5Config<Fp, 3, 2>,
+    /// Column the version and length constants are assigned to
+    header: Column<Advice>,
+}
+
+impl ChainDigestChip {
+    /// `header` must have equality enabled and the circuit a constant column
+    pub fn construct(poseidon: Pow5Config<Fp, 3, 2>, header: Column<Advice>) -> Self {
+        Self { poseidon, header }
+    }
+
+    pub fn digest(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        encoded: &[AssignedCell<Fp, Fp>],
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let [version, length] = layouter.assign_region(
+            || "chain header",
+            |mut region| {
+                let version = region.assign_advice_from_constant(
+                    || "version",
+                    self.header,
+                    0,
+                    Fp::from(CHAIN_ENCODING_VERSION),
+                )?;
+                let length =
+                    region.assign_advice_from_constant(|| "length", self.header, 1, Fp::from(encoded.len() as u64))?;
+                Ok([version, length])
+            },
+        )?;
+
+        let mut acc = hash_pair(&self.poseidon, layouter.namespace(|| "header"), version, length)?;
+        for (i, element) in encoded.iter().enumerate() {
+            acc = hash_pair(
+                &self.poseidon,
+                layouter.namespace(|| format!("element {}", i)),
+                acc,
+                element.clone(),
+            )?;
+        }
+        Ok(acc)
+    }
+}
+
+/// Native counterpart of `ChainDigestChip::digest`
+pub fn native_chain_digest(encoded: &[Fp]) -> Fp {
+    let header = native_hash_pair(Fp::from(CHAIN_ENCODING_VERSION), Fp::from(encoded.len() as u64));
+    encoded.iter().fold(header, |acc, &element| native_hash_pair(acc, element))
+}
@@ -0,0 +1,207 @@
+//! Grayscale and RGB ↔ YCbCr gadgets
+//!
+//! Every output channel is one `LinearChip` evaluation of the integer weights
+//! in `transforms::colorspace`, so proven pixels match the native conversions
+//! exactly.
+
+use halo2_proofs::{circuit::Layouter, pasta::Fp, plonk::Error};
+
+use super::{
+    linear::{LinearChip, LinearConfig},
+    AssignedImage,
+};
+use crate::transforms::{colorspace, IntegerWeights};
+
+/// Chip converting the colour space of an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct ColorChip {
+    linear: LinearChip,
+}
+
+impl ColorChip {
+    pub fn construct(config: LinearConfig) -> Self {
+        Self {
+            linear: LinearChip::construct(config),
+        }
+    }
+
+    /// Replace every pixel with its luma in all three channels
+    pub fn grayscale(&self, layouter: impl Layouter<Fp>, image: &AssignedImage) -> Result<AssignedImage, Error> {
+        let jobs: Vec<_> = image.pixels.iter().map(|pixel| (pixel.clone(), colorspace::LUMA)).collect();
+        let luma = self.linear.evaluate(layouter, &jobs)?;
+
+        Ok(AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels: luma.into_iter().map(|y| [y.clone(), y.clone(), y]).collect(),
+        })
+    }
+
+    pub fn rgb_to_ycbcr(&self, layouter: impl Layouter<Fp>, image: &AssignedImage) -> Result<AssignedImage, Error> {
+        self.map_pixels(layouter, image, [colorspace::LUMA, colorspace::CB, colorspace::CR])
+    }
+
+    pub fn ycbcr_to_rgb(&self, layouter: impl Layouter<Fp>, image: &AssignedImage) -> Result<AssignedImage, Error> {
+        self.map_pixels(layouter, image, [colorspace::RED, colorspace::GREEN, colorspace::BLUE])
+    }
+
+    fn map_pixels(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        channels: [IntegerWeights<3>; 3],
+    ) -> Result<AssignedImage, Error> {
+        let jobs: Vec<_> = image
+            .pixels
+            .iter()
+            .flat_map(|pixel| channels.map(|weights| (pixel.clone(), weights)))
+            .collect();
+        let outputs = self.linear.evaluate(layouter, &jobs)?;
+
+        let pixels = outputs
+            .chunks(3)
+            .map(|pixel| [pixel[0].clone(), pixel[1].clone(), pixel[2].clone()])
+            .collect();
+        Ok(AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        range::RangeCheckChip,
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use crate::transforms::{colorspace, filters};
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Advice, Circuit, Column, ConstraintSystem, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    #[derive(Clone, Copy)]
+    enum Conversion {
+        Grayscale,
+        ToYCbCr,
+        ToRgb,
+    }
+
+    #[derive(Clone)]
+    struct ColorTestCircuit {
+        source: RgbImage,
+        conversion: Conversion,
+    }
+
+    #[derive(Clone, Debug)]
+    struct ColorTestConfig {
+        advice: [Column<Advice>; 4],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        linear: LinearConfig,
+    }
+
+    impl Circuit<Fp> for ColorTestCircuit {
+        type Config = ColorTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, [advice[0], advice[1], advice[2]], bytes);
+            let linear = LinearChip::configure(meta, advice, range);
+
+            ColorTestConfig {
+                advice,
+                instance,
+                bytes,
+                linear,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let columns = [config.advice[0], config.advice[1], config.advice[2]];
+            let image = assign_image(&mut layouter, columns, &self.source)?;
+
+            let chip = ColorChip::construct(config.linear.clone());
+            let layouter_ns = layouter.namespace(|| "convert");
+            let converted = match self.conversion {
+                Conversion::Grayscale => chip.grayscale(layouter_ns, &image)?,
+                Conversion::ToYCbCr => chip.rgb_to_ycbcr(layouter_ns, &image)?,
+                Conversion::ToRgb => chip.ycbcr_to_rgb(layouter_ns, &image)?,
+            };
+
+            for (row, cell) in converted.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Test pattern plus saturated colours that exercise the rounding and clamp edges
+    fn source() -> RgbImage {
+        let mut image = test_image(4, 3);
+        let extremes = [[0, 0, 255], [255, 0, 0], [0, 255, 0], [255, 255, 255], [0, 0, 0]];
+        for (x, pixel) in extremes.into_iter().enumerate() {
+            image.put_pixel(x as u32 % 4, 2 - x as u32 / 4, image::Rgb(pixel));
+        }
+        image
+    }
+
+    fn prove(conversion: Conversion, source: RgbImage, expected: &DynamicImage) -> bool {
+        let circuit = ColorTestCircuit { source, conversion };
+        MockProver::run(10, &circuit, vec![channels(&expected.to_rgb8())])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    #[test]
+    fn grayscale_matches_native() {
+        let source = source();
+        let expected = filters::grayscale(&DynamicImage::ImageRgb8(source.clone()));
+        assert!(prove(Conversion::Grayscale, source, &expected));
+    }
+
+    #[test]
+    fn ycbcr_round_trip_matches_native() {
+        let source = source();
+        let ycbcr = colorspace::rgb_to_ycbcr(&DynamicImage::ImageRgb8(source.clone()));
+        let rgb = colorspace::ycbcr_to_rgb(&ycbcr);
+
+        assert!(prove(Conversion::ToYCbCr, source, &ycbcr));
+        assert!(prove(Conversion::ToRgb, ycbcr.to_rgb8(), &rgb));
+    }
+
+    #[test]
+    fn saturated_chroma_does_not_overflow() {
+        let blue = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, image::Rgb([0, 0, 255])));
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, image::Rgb([255, 0, 0])));
+
+        assert_eq!(colorspace::rgb_to_ycbcr(&blue).to_rgb8().get_pixel(0, 0)[1], 255);
+        assert_eq!(colorspace::rgb_to_ycbcr(&red).to_rgb8().get_pixel(0, 0)[2], 255);
+    }
+
+    #[test]
+    fn wrong_luma_is_rejected() {
+        let source = source();
+        let mut expected = filters::grayscale(&DynamicImage::ImageRgb8(source.clone())).to_rgb8();
+        expected.get_pixel_mut(1, 1)[0] ^= 1;
+
+        assert!(!prove(Conversion::Grayscale, source, &DynamicImage::ImageRgb8(expected)));
+    }
+}
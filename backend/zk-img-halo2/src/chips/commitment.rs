@@ -0,0 +1,301 @@
+//! Full-image Poseidon commitment (Section 7 of the paper)
+//!
+//! Every channel of every pixel is committed:
+//!
+//! 1. Channels are flattened row-major (R, G, B per pixel) and packed
+//!    big-endian, `CHANNELS_PER_WORD` bytes per field element. Each channel is
+//!    range-checked to 8 bits so the packing is injective.
+//! 2. The packed words are the leaves of a binary Poseidon Merkle tree; an
+//!    unpaired node at the end of a level is carried up unchanged.
+//! 3. The digest is `Poseidon(root, width << 32 | height)`, binding the
+//!    dimensions so images with the same channel stream but a different
+//!    shape commit differently.
+//!
+//! `native_image_commitment` computes the same digest outside the circuit.
+
+use halo2_gadgets::poseidon::{
+    primitives::{self as poseidon, ConstantLength, P128Pow5T3},
+    Hash, Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::{AssignedImage, ByteTable};
+
+/// Number of 8-bit channels packed into one field element (248 bits)
+pub const CHANNELS_PER_WORD: usize = 31;
+
+#[derive(Clone, Debug)]
+pub struct ImageCommitmentConfig {
+    channel: Column<Advice>,
+    acc: Column<Advice>,
+    q_first: Selector,
+    q_next: Selector,
+    q_byte: Selector,
+    poseidon: Pow5Config<Fp, 3, 2>,
+}
+
+/// Chip committing to an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct ImageCommitmentChip {
+    config: ImageCommitmentConfig,
+}
+
+impl ImageCommitmentChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        advice: [Column<Advice>; 2],
+        bytes: ByteTable,
+        poseidon: Pow5Config<Fp, 3, 2>,
+    ) -> ImageCommitmentConfig {
+        let [channel, acc] = advice;
+        meta.enable_equality(channel);
+        meta.enable_equality(acc);
+
+        let q_first = meta.selector();
+        let q_next = meta.selector();
+        let q_byte = meta.complex_selector();
+
+        // The first channel of a word starts the running sum
+        meta.create_gate("pack first channel", |meta| {
+            let q = meta.query_selector(q_first);
+            let channel = meta.query_advice(channel, Rotation::cur());
+            let acc = meta.query_advice(acc, Rotation::cur());
+
+            Constraints::with_selector(q, Some(acc - channel))
+        });
+
+        // acc = acc_prev * 256 + channel
+        meta.create_gate("pack next channel", |meta| {
+            let q = meta.query_selector(q_next);
+            let channel = meta.query_advice(channel, Rotation::cur());
+            let acc_prev = meta.query_advice(acc, Rotation::prev());
+            let acc = meta.query_advice(acc, Rotation::cur());
+            let shift = Expression::Constant(Fp::from(256));
+
+            Constraints::with_selector(q, Some(acc - (acc_prev * shift + channel)))
+        });
+
+        meta.lookup(|meta| {
+            let q = meta.query_selector(q_byte);
+            let channel = meta.query_advice(channel, Rotation::cur());
+
+            vec![(q * channel, bytes.values)]
+        });
+
+        ImageCommitmentConfig {
+            channel,
+            acc,
+            q_first,
+            q_next,
+            q_byte,
+            poseidon,
+        }
+    }
+
+    pub fn construct(config: ImageCommitmentConfig) -> Self {
+        Self { config }
+    }
+
+    /// Commit to every pixel of `image`, returning the digest cell
+    pub fn commit(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let root = self.root(&mut layouter, image)?;
+
+        let dimensions = layouter.assign_region(
+            || "dimensions",
+            |mut region| {
+                region.assign_advice_from_constant(
+                    || "width << 32 | height",
+                    self.config.channel,
+                    0,
+                    encode_dimensions(image.width, image.height),
+                )
+            },
+        )?;
+
+        hash_pair(
+            &self.config.poseidon,
+            layouter.namespace(|| "bind dimensions"),
+            root,
+            dimensions,
+        )
+    }
+
+    /// Commit to every pixel of `image`, binding the root to an encoded
+    /// `width << 32 | height` cell rather than the image's own size
+    ///
+    /// Used for padded canvases, whose commitment binds the real size.
+    pub fn commit_with_dimensions(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        dimensions: AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let root = self.root(&mut layouter, image)?;
+
+        hash_pair(
+            &self.config.poseidon,
+            layouter.namespace(|| "bind dimensions"),
+            root,
+            dimensions,
+        )
+    }
+
+    /// Merkle root of the packed channels of `image`
+    fn root(
+        &self,
+        layouter: &mut impl Layouter<Fp>,
+        image: &AssignedImage,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let words = self.pack(layouter.namespace(|| "pack channels"), image)?;
+        self.merkle_root(layouter.namespace(|| "merkle root"), words)
+    }
+
+    /// Copy every channel into the packing region and return one cell per word
+    fn pack(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let config = &self.config;
+        let channels: Vec<_> = image.channels().collect();
+
+        layouter.assign_region(
+            || "pack channels",
+            |mut region| {
+                let mut words = Vec::with_capacity(channels.len().div_ceil(CHANNELS_PER_WORD));
+                let mut acc: Option<AssignedCell<Fp, Fp>> = None;
+
+                for (offset, channel) in channels.iter().enumerate() {
+                    let first = offset % CHANNELS_PER_WORD == 0;
+                    if first {
+                        config.q_first.enable(&mut region, offset)?;
+                    } else {
+                        config.q_next.enable(&mut region, offset)?;
+                    }
+                    config.q_byte.enable(&mut region, offset)?;
+
+                    let channel = channel.copy_advice(|| "channel", &mut region, config.channel, offset)?;
+                    let value = match (&acc, first) {
+                        (Some(prev), false) => {
+                            prev.value().copied() * Value::known(Fp::from(256)) + channel.value().copied()
+                        }
+                        _ => channel.value().copied(),
+                    };
+                    let cell = region.assign_advice(|| "packed", config.acc, offset, || value)?;
+
+                    let last = offset + 1 == channels.len() || (offset + 1) % CHANNELS_PER_WORD == 0;
+                    if last {
+                        words.push(cell.clone());
+                    }
+                    acc = Some(cell);
+                }
+
+                Ok(words)
+            },
+        )
+    }
+
+    fn merkle_root(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        mut level: Vec<AssignedCell<Fp, Fp>>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        if level.is_empty() {
+            return layouter.assign_region(
+                || "empty root",
+                |mut region| region.assign_advice_from_constant(|| "zero", self.config.acc, 0, Fp::zero()),
+            );
+        }
+
+        let mut depth = 0;
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity(level.len().div_ceil(2));
+            let mut nodes = level.into_iter();
+            let mut index = 0;
+            while let Some(left) = nodes.next() {
+                match nodes.next() {
+                    Some(right) => next.push(hash_pair(
+                        &self.config.poseidon,
+                        layouter.namespace(|| format!("node {} at depth {}", index, depth)),
+                        left,
+                        right,
+                    )?),
+                    None => next.push(left),
+                }
+                index += 1;
+            }
+            level = next;
+            depth += 1;
+        }
+
+        Ok(level.remove(0))
+    }
+}
+
+/// Poseidon hash of two cells
+pub fn hash_pair(
+    config: &Pow5Config<Fp, 3, 2>,
+    mut layouter: impl Layouter<Fp>,
+    left: AssignedCell<Fp, Fp>,
+    right: AssignedCell<Fp, Fp>,
+) -> Result<AssignedCell<Fp, Fp>, Error> {
+    let chip = Pow5Chip::construct(config.clone());
+    let hasher = Hash::<_, _, P128Pow5T3, ConstantLength<2>, 3, 2>::init(
+        chip,
+        layouter.namespace(|| "init"),
+    )?;
+    hasher.hash(layouter.namespace(|| "hash"), [left, right])
+}
+
+/// Native counterpart of `hash_pair`
+pub fn native_hash_pair(left: Fp, right: Fp) -> Fp {
+    poseidon::Hash::<_, P128Pow5T3, ConstantLength<2>, 3, 2>::init().hash([left, right])
+}
+
+/// Native counterpart of `ImageCommitmentChip::commit`
+///
+/// `channels` holds the row-major R, G, B values of a `width` x `height` image.
+pub fn native_image_commitment(width: usize, height: usize, channels: &[Fp]) -> Fp {
+    let words = channels
+        .chunks(CHANNELS_PER_WORD)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .fold(Fp::zero(), |acc, channel| acc * Fp::from(256) + channel)
+        })
+        .collect();
+
+    native_hash_pair(native_merkle_root(words), encode_dimensions(width, height))
+}
+
+/// Root of the binary Poseidon Merkle tree over `leaves`, carrying an
+/// unpaired node up unchanged; zero for no leaves
+pub fn native_merkle_root(leaves: Vec<Fp>) -> Fp {
+    let mut level = leaves;
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => native_hash_pair(*left, *right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+
+    level.first().copied().unwrap_or(Fp::zero())
+}
+
+/// `width << 32 | height`, the dimensions hashed with a Merkle root
+pub fn encode_dimensions(width: usize, height: usize) -> Fp {
+    Fp::from(((width as u64) << 32) | height as u64)
+}
@@ -0,0 +1,188 @@
+//! 3x3 convolution gadget
+//!
+//! Each output channel is one `LinearChip` evaluation over the nine
+//! neighbouring input cells, with edges replicated exactly as in
+//! `transforms::filters::convolve`. The rescale gadget proves the division by
+//! the kernel divisor and the clamp to `[0, 255]`.
+
+use halo2_proofs::{circuit::Layouter, pasta::Fp, plonk::Error};
+
+use super::{
+    linear::{LinearChip, LinearConfig},
+    AssignedImage,
+};
+use crate::transforms::ConvolutionKernel;
+
+/// Chip convolving an `AssignedImage` with an integer 3x3 kernel
+#[derive(Clone, Debug)]
+pub struct ConvolutionChip {
+    linear: LinearChip,
+}
+
+impl ConvolutionChip {
+    pub fn construct(config: LinearConfig) -> Self {
+        Self {
+            linear: LinearChip::construct(config),
+        }
+    }
+
+    pub fn convolve(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        kernel: &ConvolutionKernel,
+    ) -> Result<AssignedImage, Error> {
+        let weights = kernel.integer_weights();
+        let (width, height) = (image.width, image.height);
+
+        let mut jobs = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let neighbours = ConvolutionKernel::neighbours(x, y, width, height).map(|(sx, sy)| image.pixel(sx, sy));
+                for c in 0..3 {
+                    jobs.push((neighbours.map(|pixel| pixel[c].clone()), weights));
+                }
+            }
+        }
+        let outputs = self.linear.evaluate(layouter, &jobs)?;
+
+        let pixels = outputs
+            .chunks(3)
+            .map(|pixel| [pixel[0].clone(), pixel[1].clone(), pixel[2].clone()])
+            .collect();
+        Ok(AssignedImage { width, height, pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        range::RangeCheckChip,
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use crate::transforms::filters;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Advice, Circuit, Column, ConstraintSystem, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+    use std::num::NonZeroU32;
+
+    #[derive(Clone)]
+    struct ConvolutionTestCircuit {
+        source: RgbImage,
+        kernel: ConvolutionKernel,
+    }
+
+    #[derive(Clone, Debug)]
+    struct ConvolutionTestConfig {
+        advice: [Column<Advice>; 4],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        linear: LinearConfig,
+    }
+
+    impl Circuit<Fp> for ConvolutionTestCircuit {
+        type Config = ConvolutionTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, [advice[0], advice[1], advice[2]], bytes);
+            let linear = LinearChip::configure(meta, advice, range);
+
+            ConvolutionTestConfig {
+                advice,
+                instance,
+                bytes,
+                linear,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let columns = [config.advice[0], config.advice[1], config.advice[2]];
+            let image = assign_image(&mut layouter, columns, &self.source)?;
+
+            let convolved = ConvolutionChip::construct(config.linear.clone()).convolve(
+                layouter.namespace(|| "convolve"),
+                &image,
+                &self.kernel,
+            )?;
+            for (row, cell) in convolved.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn prove(source: &RgbImage, kernel: ConvolutionKernel, expected: Vec<Fp>) -> bool {
+        let circuit = ConvolutionTestCircuit {
+            source: source.clone(),
+            kernel,
+        };
+        MockProver::run(11, &circuit, vec![expected]).unwrap().verify().is_ok()
+    }
+
+    fn native(source: &RgbImage, kernel: &ConvolutionKernel) -> Vec<Fp> {
+        channels(&filters::convolve(&DynamicImage::ImageRgb8(source.clone()), kernel).to_rgb8())
+    }
+
+    const EMBOSS: ConvolutionKernel = ConvolutionKernel {
+        weights: [[-2, -1, 0], [-1, 1, 1], [0, 1, 2]],
+        divisor: NonZeroU32::new(3).unwrap(),
+    };
+
+    #[test]
+    fn kernels_match_native() {
+        let source = test_image(4, 3);
+        for kernel in [ConvolutionKernel::BLUR, ConvolutionKernel::SHARPEN, EMBOSS] {
+            assert!(prove(&source, kernel, native(&source, &kernel)), "{:?}", kernel);
+        }
+    }
+
+    #[test]
+    fn wrong_output_is_rejected() {
+        let source = test_image(3, 3);
+        let mut expected = native(&source, &ConvolutionKernel::SHARPEN);
+        expected[13] += Fp::one();
+
+        assert!(!prove(&source, ConvolutionKernel::SHARPEN, expected));
+    }
+
+    #[test]
+    fn only_kernels_within_the_range_checks_are_accepted() {
+        // Black and white pixels reach the extremes of every total
+        let source = RgbImage::from_fn(3, 3, |x, _| image::Rgb([if x == 0 { 0 } else { 255 }; 3]));
+        let kernel = |weights, divisor| ConvolutionKernel::new(weights, NonZeroU32::new(divisor).unwrap());
+        let centre = |weight| [[0, 0, 0], [0, weight, 0], [0, 0, 0]];
+
+        let provable = [kernel([[28; 3]; 3], 1), kernel([[-28; 3]; 3], 1), kernel(centre(1), 200_000)];
+        for kernel in provable.map(Result::unwrap) {
+            assert!(prove(&source, kernel, native(&source, &kernel)), "{:?}", kernel);
+        }
+
+        for (weights, divisor) in [(centre(258), 1), (centre(-258), 1)] {
+            assert!(kernel(weights, divisor).is_err());
+            let unchecked = ConvolutionKernel { weights, divisor: NonZeroU32::new(divisor).unwrap() };
+            assert!(!prove(&source, unchecked, native(&source, &unchecked)), "{:?}", unchecked);
+        }
+    }
+
+    #[test]
+    fn blur_preserves_flat_images_including_borders() {
+        let flat = DynamicImage::ImageRgb8(RgbImage::from_pixel(5, 4, image::Rgb([10, 128, 250])));
+        assert_eq!(filters::blur(&flat).to_rgb8(), flat.to_rgb8());
+    }
+}
@@ -0,0 +1,390 @@
+//! Crop gadget
+//!
+//! Output pixels are fresh cells, each copy-constrained to the input pixel it
+//! was cut from, so a prover cannot substitute pixel values. The window is
+//! assigned from fixed constants, which makes it part of the verifying key,
+//! and is range-checked so that `x + width <= image width` and
+//! `y + height <= image height` hold over the integers rather than modulo p.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Selector},
+    poly::Rotation,
+};
+
+use super::{
+    range::{RangeCheckChip, RangeCheckConfig},
+    AssignedImage,
+};
+
+/// Crop rectangle in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CropWindow {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl CropWindow {
+    /// Whether the window lies inside a `width` x `height` image
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        self.x + self.width <= width && self.y + self.height <= height
+    }
+
+    /// Native crop of a `[row][column]` pixel matrix
+    pub fn apply<T: Clone>(&self, rows: &[Vec<T>]) -> Vec<Vec<T>> {
+        rows[self.y..self.y + self.height]
+            .iter()
+            .map(|row| row[self.x..self.x + self.width].to_vec())
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CropConfig {
+    pixels: [Column<Advice>; 3],
+    q_window: Selector,
+    range: RangeCheckConfig,
+}
+
+/// Chip cropping an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct CropChip {
+    config: CropConfig,
+}
+
+impl CropChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        pixels: [Column<Advice>; 3],
+        range: RangeCheckConfig,
+    ) -> CropConfig {
+        for column in pixels {
+            meta.enable_equality(column);
+        }
+
+        let q_window = meta.selector();
+
+        // offset + size + slack = bound, with the bound on the next row
+        meta.create_gate("crop window fits", |meta| {
+            let q = meta.query_selector(q_window);
+            let offset = meta.query_advice(pixels[0], Rotation::cur());
+            let size = meta.query_advice(pixels[1], Rotation::cur());
+            let slack = meta.query_advice(pixels[2], Rotation::cur());
+            let bound = meta.query_advice(pixels[0], Rotation::next());
+
+            Constraints::with_selector(q, Some(offset + size + slack - bound))
+        });
+
+        CropConfig {
+            pixels,
+            q_window,
+            range,
+        }
+    }
+
+    pub fn construct(config: CropConfig) -> Self {
+        Self { config }
+    }
+
+    /// Crop `image` to `window`, returning the cropped image and the window
+    /// cells `[x, y, width, height]`
+    pub fn crop(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        window: CropWindow,
+    ) -> Result<(AssignedImage, [AssignedCell<Fp, Fp>; 4]), Error> {
+        if !window.fits(image.width, image.height) {
+            return Err(Error::Synthesis);
+        }
+
+        let params = self.assign_window(
+            layouter.namespace(|| "crop window"),
+            (image.width, image.height),
+            window,
+        )?;
+
+        let output = Self::window_pixels(image, window)
+            .map(|pixel| [0, 1, 2].map(|c| pixel[c].value().copied()))
+            .collect();
+        let cropped = self.assign_pixels(layouter.namespace(|| "crop pixels"), image, window, output)?;
+
+        Ok((cropped, params))
+    }
+
+    /// Like `crop`, but the output reuses the input cells instead of copying
+    /// them into a region of its own
+    ///
+    /// For fusing the crop into a following chip that copies its inputs anyway.
+    pub fn view(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        window: CropWindow,
+    ) -> Result<(AssignedImage, [AssignedCell<Fp, Fp>; 4]), Error> {
+        if !window.fits(image.width, image.height) {
+            return Err(Error::Synthesis);
+        }
+
+        let params = self.assign_window(
+            layouter.namespace(|| "crop window"),
+            (image.width, image.height),
+            window,
+        )?;
+        let view = AssignedImage {
+            width: window.width,
+            height: window.height,
+            pixels: Self::window_pixels(image, window).cloned().collect(),
+        };
+
+        Ok((view, params))
+    }
+
+    /// Assign the window from constants and prove it fits the image bounds
+    fn assign_window(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        (width, height): (usize, usize),
+        window: CropWindow,
+    ) -> Result<[AssignedCell<Fp, Fp>; 4], Error> {
+        let config = &self.config;
+        let axes = [(window.x, window.width, width), (window.y, window.height, height)];
+
+        let cells = layouter.assign_region(
+            || "crop window",
+            |mut region| {
+                let mut cells = Vec::with_capacity(6);
+                for (axis, &(offset, size, bound)) in axes.iter().enumerate() {
+                    let row = axis * 2;
+                    config.q_window.enable(&mut region, row)?;
+
+                    let [offset, size, bound] = [offset, size, bound].map(|v| Fp::from(v as u64));
+                    // Negative slack wraps around the field and fails the range check
+                    let slack = bound - offset - size;
+
+                    cells.push(region.assign_advice_from_constant(|| "offset", config.pixels[0], row, offset)?);
+                    cells.push(region.assign_advice_from_constant(|| "size", config.pixels[1], row, size)?);
+                    cells.push(region.assign_advice(|| "slack", config.pixels[2], row, || Value::known(slack))?);
+                    region.assign_advice_from_constant(|| "bound", config.pixels[0], row + 1, bound)?;
+                }
+                Ok(cells)
+            },
+        )?;
+
+        // Range-checking offsets, sizes and slacks rules out wrap-around
+        RangeCheckChip::construct(config.range.clone())
+            .check_u16(layouter.namespace(|| "crop window range"), &cells)?;
+
+        let [x, width, _, y, height, _]: [AssignedCell<Fp, Fp>; 6] =
+            cells.try_into().map_err(|_| Error::Synthesis)?;
+        Ok([x, y, width, height])
+    }
+
+    /// Assign the claimed output pixels and tie each one to its input cell
+    fn assign_pixels(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        window: CropWindow,
+        output: Vec<[Value<Fp>; 3]>,
+    ) -> Result<AssignedImage, Error> {
+        let config = &self.config;
+
+        let pixels = layouter.assign_region(
+            || "crop pixels",
+            |mut region| {
+                let mut pixels = Vec::with_capacity(output.len());
+                for (offset, (source, values)) in
+                    Self::window_pixels(image, window).zip(&output).enumerate()
+                {
+                    let mut assign = |c: usize| -> Result<AssignedCell<Fp, Fp>, Error> {
+                        let cell = region.assign_advice(
+                            || format!("cropped pixel {}[{}]", offset, c),
+                            config.pixels[c],
+                            offset,
+                            || values[c],
+                        )?;
+                        region.constrain_equal(cell.cell(), source[c].cell())?;
+                        Ok(cell)
+                    };
+                    pixels.push([assign(0)?, assign(1)?, assign(2)?]);
+                }
+                Ok(pixels)
+            },
+        )?;
+
+        Ok(AssignedImage {
+            width: window.width,
+            height: window.height,
+            pixels,
+        })
+    }
+
+    /// Input pixels inside `window`, row-major
+    fn window_pixels(
+        image: &AssignedImage,
+        window: CropWindow,
+    ) -> impl Iterator<Item = &[AssignedCell<Fp, Fp>; 3]> {
+        (window.y..window.y + window.height)
+            .flat_map(move |y| (window.x..window.x + window.width).map(move |x| image.pixel(x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{range::RangeCheckChip, ByteTable};
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+
+    const WIDTH: usize = 6;
+    const HEIGHT: usize = 5;
+
+    #[derive(Clone)]
+    struct CropTestCircuit {
+        window: CropWindow,
+        /// Output pixel whose red channel the prover lies about
+        tamper: Option<usize>,
+    }
+
+    #[derive(Clone, Debug)]
+    struct CropTestConfig {
+        pixels: [Column<Advice>; 3],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        crop: CropConfig,
+    }
+
+    fn pixel_value(x: usize, y: usize, c: usize) -> Fp {
+        Fp::from(((x * 31 + y * 7 + c * 101) % 256) as u64)
+    }
+
+    impl Circuit<Fp> for CropTestCircuit {
+        type Config = CropTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, pixels, bytes);
+            let crop = CropChip::configure(meta, pixels, range);
+
+            CropTestConfig {
+                pixels,
+                instance,
+                bytes,
+                crop,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let chip = CropChip::construct(config.crop.clone());
+
+            let pixels = layouter.assign_region(
+                || "input",
+                |mut region| {
+                    let mut pixels = Vec::new();
+                    for y in 0..HEIGHT {
+                        for x in 0..WIDTH {
+                            let mut assign = |c: usize| {
+                                region.assign_advice(
+                                    || "pixel",
+                                    config.pixels[c],
+                                    y * WIDTH + x,
+                                    || Value::known(pixel_value(x, y, c)),
+                                )
+                            };
+                            pixels.push([assign(0)?, assign(1)?, assign(2)?]);
+                        }
+                    }
+                    Ok(pixels)
+                },
+            )?;
+            let image = AssignedImage {
+                width: WIDTH,
+                height: HEIGHT,
+                pixels,
+            };
+
+            let params = chip.assign_window(layouter.namespace(|| "window"), (WIDTH, HEIGHT), self.window)?;
+            if self.window.fits(WIDTH, HEIGHT) {
+                let window = self.window;
+                let output = (window.y..window.y + window.height)
+                    .flat_map(|y| (window.x..window.x + window.width).map(move |x| (x, y)))
+                    .enumerate()
+                    .map(|(i, (x, y))| {
+                        let lie = if self.tamper == Some(i) { Fp::one() } else { Fp::zero() };
+                        [0, 1, 2].map(|c| Value::known(pixel_value(x, y, c) + if c == 0 { lie } else { Fp::zero() }))
+                    })
+                    .collect();
+                chip.assign_pixels(layouter.namespace(|| "pixels"), &image, window, output)?;
+            }
+
+            for (row, cell) in params.iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn run(window: CropWindow, tamper: Option<usize>, public: [usize; 4]) -> bool {
+        let circuit = CropTestCircuit { window, tamper };
+        let instance = public.iter().map(|&v| Fp::from(v as u64)).collect();
+        MockProver::run(9, &circuit, vec![instance])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    const WINDOW: CropWindow = CropWindow {
+        x: 1,
+        y: 2,
+        width: 4,
+        height: 3,
+    };
+
+    #[test]
+    fn honest_crop_is_accepted() {
+        assert!(run(WINDOW, None, [1, 2, 4, 3]));
+    }
+
+    #[test]
+    fn tampered_output_pixel_is_rejected() {
+        assert!(!run(WINDOW, Some(0), [1, 2, 4, 3]));
+        assert!(!run(WINDOW, Some(11), [1, 2, 4, 3]));
+    }
+
+    #[test]
+    fn window_must_match_public_inputs() {
+        assert!(!run(WINDOW, None, [2, 2, 4, 3]));
+        assert!(!run(WINDOW, None, [1, 2, 3, 3]));
+    }
+
+    #[test]
+    fn window_outside_image_is_rejected() {
+        // x + width = 8 > 6 wraps the slack around the field
+        let window = CropWindow {
+            x: 5,
+            y: 0,
+            width: 3,
+            height: 1,
+        };
+        assert!(!run(window, None, [5, 0, 3, 1]));
+    }
+}
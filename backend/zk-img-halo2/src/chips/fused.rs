@@ -0,0 +1,320 @@
+//! Fused operation gadgets (Section 8.2)
+//!
+//! Crop + resize feeds a cell view of the crop window straight into the
+//! resize chip, so the cropped image is never copied into a region of its
+//! own.
+//!
+//! Grayscale + contrast proves each pixel with a single gate over three rows:
+//!
+//! ```text
+//!   | r     | g   | b   | y   |   weight bias divisor offset (fixed)
+//!   | rem_y | q   | rem | gap |
+//!   | lo    | hi  | d   | out |
+//! ```
+//!
+//! where `LUMA · (r, g, b) + LUMA.bias = y * 2^16 + rem_y` is the integer luma
+//! of `transforms::colorspace::LUMA`, and `y` then goes through the same
+//! rescale-and-clamp as `LinearChip` with the contrast weights. `rem_y`, `q`,
+//! `rem`, `gap` and `d` are range-checked to 16 bits and `y` and `out` to 8
+//! bits, so the output matches the unfused grayscale then contrast exactly,
+//! at a quarter of the rows.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Fixed, Selector, VirtualCells},
+    poly::Rotation,
+};
+
+use super::{
+    linear::{clamp_offset, signed},
+    range::{low_u64, RangeCheckChip, RangeCheckConfig},
+    AssignedImage, CropChip, CropConfig, CropWindow, ResizeChip, ResizeConfig,
+};
+use crate::transforms::{colorspace::LUMA, filters, Rational, ResizeFilter};
+
+#[derive(Clone, Debug)]
+pub struct FusedConfig {
+    advice: [Column<Advice>; 4],
+    fixed: [Column<Fixed>; 4],
+    q_gray_contrast: Selector,
+    crop: CropConfig,
+    resize: ResizeConfig,
+    range: RangeCheckConfig,
+}
+
+/// Chip proving fused operation pairs
+#[derive(Clone, Debug)]
+pub struct FusedChip {
+    config: FusedConfig,
+}
+
+impl FusedChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        advice: [Column<Advice>; 4],
+        crop: CropConfig,
+        resize: ResizeConfig,
+        range: RangeCheckConfig,
+    ) -> FusedConfig {
+        for column in advice {
+            meta.enable_equality(column);
+        }
+
+        let fixed = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+        let q_gray_contrast = meta.selector();
+
+        meta.create_gate("grayscale contrast", |meta| {
+            let q = meta.query_selector(q_gray_contrast);
+            let cell = |meta: &mut VirtualCells<'_, Fp>, column: usize, row: i32| {
+                meta.query_advice(advice[column], Rotation(row))
+            };
+            let rgb = [cell(meta, 0, 0), cell(meta, 1, 0), cell(meta, 2, 0)];
+            let y = cell(meta, 3, 0);
+            let [rem_y, quotient, rem, gap] = [0, 1, 2, 3].map(|c| cell(meta, c, 1));
+            let [lo, hi, d, out] = [0, 1, 2, 3].map(|c| cell(meta, c, 2));
+            let [weight, bias, divisor, offset] = fixed.map(|column| meta.query_fixed(column));
+
+            let one = Expression::Constant(Fp::one());
+            let constant = |value: i64| Expression::Constant(signed(value));
+            let luma = rgb
+                .into_iter()
+                .zip(LUMA.weights)
+                .fold(constant(LUMA.bias), |sum, (channel, weight)| sum + channel * constant(weight));
+            let v = quotient.clone() - offset;
+            let in_range = one.clone() - lo.clone() - hi.clone();
+
+            Constraints::with_selector(
+                q,
+                [
+                    ("luma", luma - (y.clone() * constant(LUMA.divisor as i64) + rem_y)),
+                    ("division", y * weight + bias - (quotient * divisor.clone() + rem.clone())),
+                    ("remainder below divisor", gap - (divisor - one.clone() - rem)),
+                    ("lo is boolean", lo.clone() * (one.clone() - lo.clone())),
+                    ("hi is boolean", hi.clone() * (one.clone() - hi.clone())),
+                    ("flags exclusive", lo.clone() * hi.clone()),
+                    ("clamped output", out - (in_range * v.clone() + hi.clone() * constant(255))),
+                    ("clamp distance", d - (lo * (-v.clone() - one) + hi * (v - constant(256)))),
+                ],
+            )
+        });
+
+        FusedConfig {
+            advice,
+            fixed,
+            q_gray_contrast,
+            crop,
+            resize,
+            range,
+        }
+    }
+
+    pub fn construct(config: FusedConfig) -> Self {
+        Self { config }
+    }
+
+    /// Crop to `window` and resize to `width` x `height` without laying out
+    /// the cropped image, returning the crop window cells `[x, y, width, height]`
+    pub fn crop_resize(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        window: CropWindow,
+        (width, height): (usize, usize),
+        filter: ResizeFilter,
+    ) -> Result<(AssignedImage, [AssignedCell<Fp, Fp>; 4]), Error> {
+        let crop = CropChip::construct(self.config.crop.clone());
+        let (view, params) = crop.view(layouter.namespace(|| "crop window"), image, window)?;
+
+        let resize = ResizeChip::construct(self.config.resize.clone());
+        let resized = resize.resize(layouter.namespace(|| "resize"), &view, width, height, filter)?;
+
+        Ok((resized, params))
+    }
+
+    /// Luma followed by contrast, one gate per pixel
+    pub fn grayscale_contrast(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        contrast: Rational,
+    ) -> Result<AssignedImage, Error> {
+        let config = &self.config;
+        let weights = filters::contrast_weights(contrast);
+        let [weight] = weights.weights;
+        let divisor = weights.divisor;
+        let offset = clamp_offset(weights.bias + weight.min(0) * 255, divisor);
+        let bias = weights.bias + offset * divisor as i64;
+
+        let (outputs, bytes, wide) = layouter.assign_region(
+            || "grayscale contrast",
+            |mut region| {
+                let mut outputs = Vec::with_capacity(image.pixels.len());
+                let mut bytes = Vec::with_capacity(image.pixels.len() * 2);
+                let mut wide = Vec::with_capacity(image.pixels.len() * 5);
+
+                for (index, pixel) in image.pixels.iter().enumerate() {
+                    let row = index * 3;
+                    config.q_gray_contrast.enable(&mut region, row)?;
+                    for (column, value) in config.fixed.iter().zip([signed(weight), signed(bias), Fp::from(divisor), signed(offset)]) {
+                        region.assign_fixed(|| "contrast", *column, row, || Value::known(value))?;
+                    }
+
+                    let mut luma = Value::known(LUMA.bias as u64);
+                    for (c, channel) in pixel.iter().enumerate() {
+                        let cell = channel.copy_advice(|| "channel", &mut region, config.advice[c], row)?;
+                        luma = luma + cell.value().map(|v| low_u64(v) * LUMA.weights[c] as u64);
+                    }
+                    let y = luma.map(|luma| luma / LUMA.divisor);
+                    let rem_y = luma.map(|luma| luma % LUMA.divisor);
+
+                    let total = y.map(|y| (weight * y as i64 + bias) as u64);
+                    let quotient = total.map(|total| total / divisor);
+                    let rem = total.map(|total| total % divisor);
+                    let gap = rem.map(|rem| divisor - 1 - rem);
+                    let v = quotient.map(|quotient| quotient as i64 - offset);
+                    let (lo, hi) = (v.map(|v| v < 0), v.map(|v| v > 255));
+                    let d = v.map(|v| if v < 0 { -v - 1 } else if v > 255 { v - 256 } else { 0 });
+                    let out = v.map(|v| v.clamp(0, 255) as u64);
+
+                    let mut assign = |name: &'static str, column: usize, row: usize, value: Value<Fp>| {
+                        region.assign_advice(|| name, config.advice[column], row, || value)
+                    };
+                    let y = assign("luma", 3, row, y.map(Fp::from))?;
+                    let rem_y = assign("luma remainder", 0, row + 1, rem_y.map(Fp::from))?;
+                    let quotient = assign("quotient", 1, row + 1, quotient.map(Fp::from))?;
+                    let rem = assign("remainder", 2, row + 1, rem.map(Fp::from))?;
+                    let gap = assign("gap", 3, row + 1, gap.map(Fp::from))?;
+                    assign("below zero", 0, row + 2, lo.map(|lo| Fp::from(lo as u64)))?;
+                    assign("above 255", 1, row + 2, hi.map(|hi| Fp::from(hi as u64)))?;
+                    let d = assign("clamp distance", 2, row + 2, d.map(signed))?;
+                    let out = assign("out", 3, row + 2, out.map(Fp::from))?;
+
+                    wide.extend([rem_y, quotient, rem, gap, d]);
+                    bytes.extend([y, out.clone()]);
+                    outputs.push(out);
+                }
+                Ok((outputs, bytes, wide))
+            },
+        )?;
+
+        let range = RangeCheckChip::construct(config.range.clone());
+        range.check_u16(layouter.namespace(|| "rescale range"), &wide)?;
+        range.check_u8(layouter.namespace(|| "byte range"), &bytes)?;
+
+        Ok(AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels: outputs.into_iter().map(|out| [out.clone(), out.clone(), out]).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use crate::transforms::fused;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    #[derive(Clone)]
+    struct GrayContrastTestCircuit {
+        source: RgbImage,
+        contrast: Rational,
+    }
+
+    #[derive(Clone, Debug)]
+    struct GrayContrastTestConfig {
+        advice: [Column<Advice>; 4],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        fused: FusedConfig,
+    }
+
+    impl Circuit<Fp> for GrayContrastTestCircuit {
+        type Config = GrayContrastTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let pixels = [advice[0], advice[1], advice[2]];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, pixels, bytes);
+            let crop = CropChip::configure(meta, pixels, range.clone());
+            let resize = ResizeChip::configure(meta, pixels, range.clone());
+            let fused = FusedChip::configure(meta, advice, crop, resize, range);
+
+            GrayContrastTestConfig {
+                advice,
+                instance,
+                bytes,
+                fused,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let pixels = [config.advice[0], config.advice[1], config.advice[2]];
+            let image = assign_image(&mut layouter, pixels, &self.source)?;
+
+            let chip = FusedChip::construct(config.fused.clone());
+            let output = chip.grayscale_contrast(layouter.namespace(|| "fused"), &image, self.contrast)?;
+
+            for (row, cell) in output.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn source() -> RgbImage {
+        let mut image = test_image(4, 3);
+        image.put_pixel(0, 0, image::Rgb([255, 255, 255]));
+        image.put_pixel(1, 0, image::Rgb([0, 0, 0]));
+        image
+    }
+
+    fn prove(contrast: Rational, expected: Vec<Fp>) -> bool {
+        let circuit = GrayContrastTestCircuit {
+            source: source(),
+            contrast,
+        };
+        MockProver::run(10, &circuit, vec![expected]).unwrap().verify().is_ok()
+    }
+
+    fn native(contrast: Rational) -> Vec<Fp> {
+        channels(&fused::grayscale_contrast(&DynamicImage::ImageRgb8(source()), contrast).to_rgb8())
+    }
+
+    #[test]
+    fn grayscale_contrast_matches_unfused_native() {
+        for (num, den) in [(1, 1), (3, 2), (7, 3), (1, 5), (-1, 2), (0, 1)] {
+            let contrast = Rational::new(num, den).unwrap();
+            assert!(prove(contrast, native(contrast)), "contrast {}/{}", num, den);
+        }
+    }
+
+    #[test]
+    fn wrong_fused_output_is_rejected() {
+        let contrast = Rational::new(5, 2).unwrap();
+        let mut expected = native(contrast);
+        expected[7] += Fp::one();
+
+        assert!(!prove(contrast, expected));
+    }
+}
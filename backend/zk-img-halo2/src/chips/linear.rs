@@ -0,0 +1,552 @@
+//! Integer linear maps with rounding and clamping
+//!
+//! Proves `out = clamp(floor((Σ w_i * x_i + bias) / divisor), 0, 255)` for
+//! the weights in a `transforms::IntegerWeights`, or with a single product
+//! `x * factor` of two cells in place of the weighted sum. Each evaluation uses
+//! `ceil(N / 3)` accumulation rows (one for a product) followed by two rescale
+//! rows:
+//!
+//! ```text
+//!   | x0 | x1 | x2 | acc   |   w0 w1 w2 bias   (q_sum or q_product)
+//!   | .. | .. | .. | ..    |
+//!   | q  | rem| out| total |   divisor offset  (q_rescale)
+//!   | lo | hi | d  | gap   |
+//! ```
+//!
+//! `total = q * divisor + rem` with `rem` and `gap = divisor - 1 - rem`
+//! range-checked, to 16 bits or to 32 for divisors beyond 2^16 such as pixel
+//! counts, so `q` is the floor. The bias is raised by `offset * divisor`
+//! so `total` is never negative, and `v = q - offset` is the signed quotient.
+//! `lo`/`hi` flag `v < 0` and `v > 255`; `d` is `-v - 1` or `v - 256` in those
+//! cases and is range-checked to prove the flag, while `out` is range-checked
+//! to a byte so an unflagged `v` must already lie in `[0, 255]`.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Fixed, Selector},
+    poly::Rotation,
+};
+
+use super::range::{low_u64, RangeCheckChip, RangeCheckConfig, U16_BOUND};
+use crate::transforms::IntegerWeights;
+
+#[derive(Clone, Debug)]
+pub struct LinearConfig {
+    advice: [Column<Advice>; 4],
+    fixed: [Column<Fixed>; 4],
+    q_start: Selector,
+    q_sum: Selector,
+    q_product: Selector,
+    q_rescale: Selector,
+    range: RangeCheckConfig,
+}
+
+/// Chip evaluating `IntegerWeights` over assigned cells
+#[derive(Clone, Debug)]
+pub struct LinearChip {
+    config: LinearConfig,
+}
+
+impl LinearChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        advice: [Column<Advice>; 4],
+        range: RangeCheckConfig,
+    ) -> LinearConfig {
+        for column in advice {
+            meta.enable_equality(column);
+        }
+
+        let fixed = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+        let q_start = meta.selector();
+        let q_sum = meta.selector();
+        let q_product = meta.selector();
+        let q_rescale = meta.selector();
+        let [a0, a1, a2, acc] = advice;
+
+        meta.create_gate("accumulator starts at zero", |meta| {
+            let q = meta.query_selector(q_start);
+            let acc = meta.query_advice(acc, Rotation::cur());
+
+            Constraints::with_selector(q, Some(acc))
+        });
+
+        // acc_next = acc + w0 * x0 + w1 * x1 + w2 * x2 + bias
+        meta.create_gate("weighted sum", |meta| {
+            let q = meta.query_selector(q_sum);
+            let acc_next = meta.query_advice(acc, Rotation::next());
+            let acc = meta.query_advice(acc, Rotation::cur());
+            let bias = meta.query_fixed(fixed[3]);
+
+            let sum = [a0, a1, a2]
+                .into_iter()
+                .zip(fixed)
+                .map(|(input, weight)| meta.query_advice(input, Rotation::cur()) * meta.query_fixed(weight))
+                .fold(acc + bias, |sum, term| sum + term);
+
+            Constraints::with_selector(q, Some(acc_next - sum))
+        });
+
+        // acc_next = acc + x0 * x1 + bias
+        meta.create_gate("product", |meta| {
+            let q = meta.query_selector(q_product);
+            let acc_next = meta.query_advice(acc, Rotation::next());
+            let acc = meta.query_advice(acc, Rotation::cur());
+            let x = meta.query_advice(a0, Rotation::cur());
+            let factor = meta.query_advice(a1, Rotation::cur());
+            let bias = meta.query_fixed(fixed[3]);
+
+            Constraints::with_selector(q, Some(acc_next - (acc + x * factor + bias)))
+        });
+
+        meta.create_gate("rescale and clamp", |meta| {
+            let q = meta.query_selector(q_rescale);
+            let quotient = meta.query_advice(a0, Rotation::cur());
+            let rem = meta.query_advice(a1, Rotation::cur());
+            let out = meta.query_advice(a2, Rotation::cur());
+            let total = meta.query_advice(acc, Rotation::cur());
+            let lo = meta.query_advice(a0, Rotation::next());
+            let hi = meta.query_advice(a1, Rotation::next());
+            let d = meta.query_advice(a2, Rotation::next());
+            let gap = meta.query_advice(acc, Rotation::next());
+            let divisor = meta.query_fixed(fixed[0]);
+            let offset = meta.query_fixed(fixed[1]);
+
+            let one = Expression::Constant(Fp::one());
+            let constant = |value: u64| Expression::Constant(Fp::from(value));
+            let v = quotient.clone() - offset;
+            let in_range = one.clone() - lo.clone() - hi.clone();
+
+            Constraints::with_selector(
+                q,
+                [
+                    ("division", total - (quotient * divisor.clone() + rem.clone())),
+                    ("remainder below divisor", gap - (divisor - one.clone() - rem)),
+                    ("lo is boolean", lo.clone() * (one.clone() - lo.clone())),
+                    ("hi is boolean", hi.clone() * (one.clone() - hi.clone())),
+                    ("flags exclusive", lo.clone() * hi.clone()),
+                    ("clamped output", out - (in_range * v.clone() + hi.clone() * constant(255))),
+                    (
+                        "clamp distance",
+                        d - (lo * (-v.clone() - one) + hi * (v - constant(256))),
+                    ),
+                ],
+            )
+        });
+
+        LinearConfig {
+            advice,
+            fixed,
+            q_start,
+            q_sum,
+            q_product,
+            q_rescale,
+            range,
+        }
+    }
+
+    pub fn construct(config: LinearConfig) -> Self {
+        Self { config }
+    }
+
+    /// Evaluate each `(inputs, weights)` job, returning one byte cell per job
+    pub fn evaluate<const N: usize>(
+        &self,
+        layouter: impl Layouter<Fp>,
+        jobs: &[([AssignedCell<Fp, Fp>; N], IntegerWeights<N>)],
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let jobs: Vec<_> = jobs
+            .iter()
+            .map(|(inputs, weights)| Job {
+                terms: Terms::Weighted(inputs, &weights.weights),
+                bias: weights.bias,
+                divisor: weights.divisor,
+                lowest: weights.weights.iter().map(|&weight| weight.min(0) * 255).sum(),
+            })
+            .collect();
+
+        self.assign_jobs(layouter, &jobs)
+    }
+
+    /// `floor(Σ inputs / inputs.len())` of byte cells
+    pub fn average(
+        &self,
+        layouter: impl Layouter<Fp>,
+        inputs: &[AssignedCell<Fp, Fp>],
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let weights = vec![1; inputs.len()];
+        let job = Job {
+            terms: Terms::Weighted(inputs, &weights),
+            bias: 0,
+            divisor: inputs.len().max(1) as u64,
+            lowest: 0,
+        };
+
+        Ok(self.assign_jobs(layouter, &[job])?.remove(0))
+    }
+
+    /// `clamp(floor((x * factor + bias) / divisor), 0, 255)` for each
+    /// `[x, factor]` pair of non-negative cells
+    pub fn scale(
+        &self,
+        layouter: impl Layouter<Fp>,
+        jobs: &[[AssignedCell<Fp, Fp>; 2]],
+        bias: i64,
+        divisor: u64,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let jobs: Vec<_> = jobs
+            .iter()
+            .map(|[x, factor]| Job {
+                terms: Terms::Product(x, factor),
+                bias,
+                divisor,
+                lowest: 0,
+            })
+            .collect();
+
+        self.assign_jobs(layouter, &jobs)
+    }
+
+    fn assign_jobs(&self, mut layouter: impl Layouter<Fp>, jobs: &[Job]) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let config = &self.config;
+
+        let (outputs, wide, wider) = layouter.assign_region(
+            || "linear map",
+            |mut region| {
+                let mut outputs = Vec::with_capacity(jobs.len());
+                let mut wide = Vec::with_capacity(jobs.len() * 4);
+                let mut wider = Vec::new();
+                let mut start = 0;
+
+                for job in jobs {
+                    let offset = job.offset();
+                    let bias = job.bias + offset * job.divisor as i64;
+
+                    config.q_start.enable(&mut region, start)?;
+                    let mut acc = region.assign_advice(|| "acc", config.advice[3], start, || Value::known(Fp::zero()))?;
+
+                    let sum_rows = job.sum_rows();
+                    for row in 0..sum_rows {
+                        let sum_row = start + row;
+                        let row_bias = if row == 0 { bias } else { 0 };
+                        region.assign_fixed(|| "bias", config.fixed[3], sum_row, || Value::known(signed(row_bias)))?;
+                        let mut sum = acc.value().copied() + Value::known(signed(row_bias));
+
+                        match job.terms {
+                            Terms::Weighted(inputs, weights) => {
+                                config.q_sum.enable(&mut region, sum_row)?;
+                                for slot in 0..3 {
+                                    let index = row * 3 + slot;
+                                    let (input, weight) = match inputs.get(index) {
+                                        Some(input) => {
+                                            let cell = input.copy_advice(|| "input", &mut region, config.advice[slot], sum_row)?;
+                                            (cell.value().copied(), weights[index])
+                                        }
+                                        None => {
+                                            let zero = Value::known(Fp::zero());
+                                            region.assign_advice(|| "padding", config.advice[slot], sum_row, || zero)?;
+                                            (zero, 0)
+                                        }
+                                    };
+                                    region.assign_fixed(|| "weight", config.fixed[slot], sum_row, || Value::known(signed(weight)))?;
+                                    sum = sum + input.map(|input| input * signed(weight));
+                                }
+                            }
+                            Terms::Product(x, factor) => {
+                                config.q_product.enable(&mut region, sum_row)?;
+                                let x = x.copy_advice(|| "x", &mut region, config.advice[0], sum_row)?;
+                                let factor = factor.copy_advice(|| "factor", &mut region, config.advice[1], sum_row)?;
+                                sum = sum + x.value().copied() * factor.value().copied();
+                            }
+                        }
+
+                        acc = region.assign_advice(|| "acc", config.advice[3], sum_row + 1, || sum)?;
+                    }
+
+                    // Rescale rows
+                    let row = start + sum_rows;
+                    config.q_rescale.enable(&mut region, row)?;
+                    region.assign_fixed(|| "divisor", config.fixed[0], row, || Value::known(Fp::from(job.divisor)))?;
+                    region.assign_fixed(|| "offset", config.fixed[1], row, || Value::known(signed(offset)))?;
+
+                    let total = acc.value().map(low_u64);
+                    let quotient = total.map(|total| total / job.divisor);
+                    let rem = total.map(|total| total % job.divisor);
+                    let v = quotient.map(|quotient| quotient as i64 - offset);
+                    let (lo, hi) = (v.map(|v| v < 0), v.map(|v| v > 255));
+                    let out = v.map(|v| v.clamp(0, 255) as u64);
+                    let d = v.map(|v| if v < 0 { -v - 1 } else if v > 255 { v - 256 } else { 0 });
+                    let gap = rem.map(|rem| job.divisor - 1 - rem);
+
+                    let mut assign = |name: &'static str, column: usize, row: usize, value: Value<Fp>| {
+                        region.assign_advice(|| name, config.advice[column], row, || value)
+                    };
+                    let quotient = assign("quotient", 0, row, quotient.map(Fp::from))?;
+                    let rem = assign("remainder", 1, row, rem.map(Fp::from))?;
+                    let out = assign("out", 2, row, out.map(Fp::from))?;
+                    assign("below zero", 0, row + 1, lo.map(|lo| Fp::from(lo as u64)))?;
+                    assign("above 255", 1, row + 1, hi.map(|hi| Fp::from(hi as u64)))?;
+                    let d = assign("clamp distance", 2, row + 1, d.map(signed))?;
+                    let gap = assign("gap", 3, row + 1, gap.map(Fp::from))?;
+
+                    // Remainders of divisors beyond 2^16, such as pixel counts, need 32 bits
+                    if job.divisor > U16_BOUND {
+                        wide.extend([quotient, d]);
+                        wider.extend([rem, gap]);
+                    } else {
+                        wide.extend([quotient, rem, d, gap]);
+                    }
+                    outputs.push(out);
+                    start = row + 2;
+                }
+                Ok((outputs, wide, wider))
+            },
+        )?;
+
+        let range = RangeCheckChip::construct(config.range.clone());
+        range.check_u16(layouter.namespace(|| "rescale range"), &wide)?;
+        if !wider.is_empty() {
+            range.check_u32(layouter.namespace(|| "remainder range"), &wider)?;
+        }
+        range.check_u8(layouter.namespace(|| "output range"), &outputs)?;
+
+        Ok(outputs)
+    }
+}
+
+/// One evaluation laid out by `LinearChip::assign_jobs`
+struct Job<'a> {
+    terms: Terms<'a>,
+    bias: i64,
+    divisor: u64,
+    /// Lower bound of the terms' sum, used to keep the total non-negative
+    lowest: i64,
+}
+
+enum Terms<'a> {
+    Weighted(&'a [AssignedCell<Fp, Fp>], &'a [i64]),
+    Product(&'a AssignedCell<Fp, Fp>, &'a AssignedCell<Fp, Fp>),
+}
+
+impl Job<'_> {
+    fn sum_rows(&self) -> usize {
+        match self.terms {
+            Terms::Weighted(inputs, _) => inputs.len().div_ceil(3).max(1),
+            Terms::Product(..) => 1,
+        }
+    }
+
+    fn offset(&self) -> i64 {
+        clamp_offset(self.bias + self.lowest, self.divisor)
+    }
+}
+
+/// Smallest `k` with `lowest + k * divisor >= 0`, where `lowest` is the
+/// smallest possible `Σ w_i * x_i + bias`
+pub(crate) fn clamp_offset(lowest: i64, divisor: u64) -> i64 {
+    if lowest >= 0 {
+        0
+    } else {
+        lowest.unsigned_abs().div_ceil(divisor) as i64
+    }
+}
+
+/// Field encoding of a signed integer
+pub fn signed(value: i64) -> Fp {
+    if value < 0 {
+        -Fp::from(value.unsigned_abs())
+    } else {
+        Fp::from(value as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::ByteTable;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+
+    /// Rescale rows filled in by hand: `[q, rem, out, total, lo, hi, d, gap]`
+    type Forged = [i64; 8];
+
+    #[derive(Clone)]
+    struct LinearTestCircuit {
+        inputs: Vec<[u8; 2]>,
+        weights: IntegerWeights<2>,
+        forged: Option<(Forged, u64, i64)>,
+    }
+
+    #[derive(Clone, Debug)]
+    struct LinearTestConfig {
+        advice: [Column<Advice>; 4],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        linear: LinearConfig,
+    }
+
+    impl Circuit<Fp> for LinearTestCircuit {
+        type Config = LinearTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let advice = [meta.advice_column(), meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, [advice[0], advice[1], advice[2]], bytes);
+            let linear = LinearChip::configure(meta, advice, range);
+
+            LinearTestConfig {
+                advice,
+                instance,
+                bytes,
+                linear,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+
+            if let Some((forged, divisor, offset)) = self.forged {
+                let linear = &config.linear;
+                let (wide, wider, out) = layouter.assign_region(
+                    || "forged rescale",
+                    |mut region| {
+                        linear.q_rescale.enable(&mut region, 0)?;
+                        region.assign_fixed(|| "divisor", linear.fixed[0], 0, || Value::known(Fp::from(divisor)))?;
+                        region.assign_fixed(|| "offset", linear.fixed[1], 0, || Value::known(signed(offset)))?;
+
+                        let mut cells = Vec::new();
+                        for (i, value) in forged.iter().enumerate() {
+                            cells.push(region.assign_advice(
+                                || "forged",
+                                config.advice[i % 4],
+                                i / 4,
+                                || Value::known(signed(*value)),
+                            )?);
+                        }
+                        let [q, rem, out, _, _, _, d, gap] = cells.try_into().unwrap();
+                        // As in `assign_jobs`, rem and gap get 32 bits beyond 2^16
+                        Ok(if divisor > U16_BOUND {
+                            (vec![q, d], vec![rem, gap], out)
+                        } else {
+                            (vec![q, rem, d, gap], vec![], out)
+                        })
+                    },
+                )?;
+                let range = RangeCheckChip::construct(config.linear.range.clone());
+                range.check_u16(layouter.namespace(|| "forged wide"), &wide)?;
+                range.check_u32(layouter.namespace(|| "forged wider"), &wider)?;
+                range.check_u8(layouter.namespace(|| "forged out"), std::slice::from_ref(&out))?;
+                return layouter.constrain_instance(out.cell(), config.instance, 0);
+            }
+
+            let inputs = layouter.assign_region(
+                || "inputs",
+                |mut region| {
+                    let mut inputs = Vec::new();
+                    for (row, pair) in self.inputs.iter().enumerate() {
+                        let mut assign = |c: usize| {
+                            region.assign_advice(|| "input", config.advice[c], row, || Value::known(Fp::from(pair[c] as u64)))
+                        };
+                        inputs.push([assign(0)?, assign(1)?]);
+                    }
+                    Ok(inputs)
+                },
+            )?;
+            let jobs: Vec<_> = inputs.into_iter().map(|cells| (cells, self.weights)).collect();
+
+            let outputs = LinearChip::construct(config.linear.clone()).evaluate(layouter.namespace(|| "linear"), &jobs)?;
+            for (row, out) in outputs.iter().enumerate() {
+                layouter.constrain_instance(out.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn prove(circuit: &LinearTestCircuit, outputs: Vec<u8>) -> bool {
+        let instance = outputs.into_iter().map(|out| Fp::from(out as u64)).collect();
+        MockProver::run(9, circuit, vec![instance]).unwrap().verify().is_ok()
+    }
+
+    // (5a - 2b - 100) / 4, which spans both clamp edges
+    const WEIGHTS: IntegerWeights<2> = IntegerWeights {
+        weights: [5, -2],
+        bias: -100,
+        divisor: 4,
+    };
+
+    #[test]
+    fn matches_native_including_clamping() {
+        let inputs = vec![[0, 0], [255, 0], [0, 255], [200, 100], [137, 3], [90, 20], [255, 255]];
+        let expected: Vec<u8> = inputs.iter().map(|&pair| WEIGHTS.apply(pair)).collect();
+        assert!(expected.contains(&0) && expected.contains(&255));
+
+        let circuit = LinearTestCircuit {
+            inputs,
+            weights: WEIGHTS,
+            forged: None,
+        };
+        assert!(prove(&circuit, expected.clone()));
+
+        let mut wrong = expected;
+        wrong[4] += 1;
+        assert!(!prove(&circuit, wrong));
+    }
+
+    /// Check hand-filled rescale rows, exposing the claimed output
+    fn forged(rows: Forged) -> bool {
+        // total = 1030 with divisor 4 and no offset: v = 257, clamps to 255
+        forged_over(rows, 4)
+    }
+
+    fn forged_over(rows: Forged, divisor: u64) -> bool {
+        let circuit = LinearTestCircuit {
+            inputs: vec![],
+            weights: WEIGHTS,
+            forged: Some((rows, divisor, 0)),
+        };
+        MockProver::run(9, &circuit, vec![vec![signed(rows[2])]])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    #[test]
+    fn honest_rescale_rows_are_accepted() {
+        assert!(forged([257, 2, 255, 1030, 0, 1, 1, 1]));
+    }
+
+    #[test]
+    fn wrong_quotient_is_rejected() {
+        // 256 * 4 + 6: remainder is not below the divisor
+        assert!(!forged([256, 6, 255, 1030, 0, 1, 0, -3]));
+    }
+
+    #[test]
+    fn missing_clamp_is_rejected() {
+        // Claiming v is in range leaves out = 257, which is not a byte
+        assert!(!forged([257, 2, 257, 1030, 0, 0, 0, 1]));
+        // Claiming v < 0 needs -v - 1 to be a small non-negative number
+        assert!(!forged([257, 2, 0, 1030, 1, 0, -258, 1]));
+    }
+
+    #[test]
+    fn remainders_beyond_u16_divisors_are_range_checked() {
+        // Averaging 65537 pixels of 200: the gap 65536 no longer fits 16 bits
+        let total = 200 * (U16_BOUND as i64 + 1);
+        assert!(forged_over([200, 0, 200, total, 0, 0, 0, U16_BOUND as i64], U16_BOUND + 1));
+        // 199 * 65537 + 65537: remainder is not below the divisor
+        assert!(!forged_over([199, U16_BOUND as i64 + 1, 199, total, 0, 0, 0, -1], U16_BOUND + 1));
+    }
+}
@@ -0,0 +1,430 @@
+//! Masking of padded images
+//!
+//! An image padded to a larger canvas has its real width and height as
+//! cells. Each one becomes a prefix mask over the canvas columns or rows,
+//! `bit_i = [i < count]`, laid out with a running sum:
+//!
+//! ```text
+//!   | bit_0 | sum_0 = bit_0         |
+//!   | bit_i | sum_i = sum_{i-1} + bit_i |   bit_i ≤ bit_{i-1}
+//! ```
+//!
+//! Booleans that never rise again and sum to `count` are exactly the first
+//! `count` ones, so the last sum is tied to the count cell. A pixel's mask is
+//! the product of its column and row bits, which the chip uses to require
+//! zero padding in an input canvas and to zero the padding of an output one.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::{range::low_u64, AssignedImage};
+
+#[derive(Clone, Debug)]
+pub struct MaskConfig {
+    pixels: [Column<Advice>; 3],
+    mask: Column<Advice>,
+    q_prefix_first: Selector,
+    q_prefix_next: Selector,
+    q_product: Selector,
+    q_zero: Selector,
+    q_apply: Selector,
+    q_dimensions: Selector,
+}
+
+/// Chip masking the padding of canvases holding a smaller image
+#[derive(Clone, Debug)]
+pub struct MaskChip {
+    config: MaskConfig,
+}
+
+impl MaskChip {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>, pixels: [Column<Advice>; 3], mask: Column<Advice>) -> MaskConfig {
+        for column in pixels {
+            meta.enable_equality(column);
+        }
+        meta.enable_equality(mask);
+
+        let q_prefix_first = meta.selector();
+        let q_prefix_next = meta.selector();
+        let q_product = meta.selector();
+        let q_zero = meta.selector();
+        let q_apply = meta.selector();
+        let q_dimensions = meta.selector();
+        let one = Expression::Constant(Fp::one());
+
+        meta.create_gate("mask prefix start", |meta| {
+            let q = meta.query_selector(q_prefix_first);
+            let bit = meta.query_advice(pixels[0], Rotation::cur());
+            let sum = meta.query_advice(pixels[1], Rotation::cur());
+
+            Constraints::with_selector(q, [bit.clone() * (one.clone() - bit.clone()), sum - bit])
+        });
+
+        meta.create_gate("mask prefix step", |meta| {
+            let q = meta.query_selector(q_prefix_next);
+            let bit_prev = meta.query_advice(pixels[0], Rotation::prev());
+            let bit = meta.query_advice(pixels[0], Rotation::cur());
+            let sum_prev = meta.query_advice(pixels[1], Rotation::prev());
+            let sum = meta.query_advice(pixels[1], Rotation::cur());
+
+            Constraints::with_selector(
+                q,
+                [
+                    bit.clone() * (one.clone() - bit.clone()),
+                    bit.clone() * (one.clone() - bit_prev),
+                    sum - (sum_prev + bit),
+                ],
+            )
+        });
+
+        meta.create_gate("pixel mask", |meta| {
+            let q = meta.query_selector(q_product);
+            let column = meta.query_advice(pixels[0], Rotation::cur());
+            let row = meta.query_advice(pixels[1], Rotation::cur());
+            let mask = meta.query_advice(pixels[2], Rotation::cur());
+
+            Constraints::with_selector(q, Some(mask - column * row))
+        });
+
+        // Padding channels, where the mask is 0, must be 0
+        meta.create_gate("zero padding", |meta| {
+            let q = meta.query_selector(q_zero);
+            let mask = meta.query_advice(mask, Rotation::cur());
+
+            Constraints::with_selector(
+                q,
+                pixels.map(|column| meta.query_advice(column, Rotation::cur()) * (one.clone() - mask.clone())),
+            )
+        });
+
+        // The next row holds the pixel times its mask
+        meta.create_gate("apply mask", |meta| {
+            let q = meta.query_selector(q_apply);
+            let mask = meta.query_advice(mask, Rotation::cur());
+
+            Constraints::with_selector(
+                q,
+                pixels.map(|column| {
+                    let channel = meta.query_advice(column, Rotation::cur());
+                    let masked = meta.query_advice(column, Rotation::next());
+                    masked - channel * mask.clone()
+                }),
+            )
+        });
+
+        meta.create_gate("encode dimensions", |meta| {
+            let q = meta.query_selector(q_dimensions);
+            let width = meta.query_advice(pixels[0], Rotation::cur());
+            let height = meta.query_advice(pixels[1], Rotation::cur());
+            let encoded = meta.query_advice(pixels[2], Rotation::cur());
+            let shift = Expression::Constant(Fp::from(1 << 32));
+
+            Constraints::with_selector(q, Some(encoded - (width * shift + height)))
+        });
+
+        MaskConfig {
+            pixels,
+            mask,
+            q_prefix_first,
+            q_prefix_next,
+            q_product,
+            q_zero,
+            q_apply,
+            q_dimensions,
+        }
+    }
+
+    pub fn construct(config: MaskConfig) -> Self {
+        Self { config }
+    }
+
+    /// Row-major mask cells of a `canvas_width` x `canvas_height` canvas,
+    /// 1 inside the top-left `width` x `height` rectangle and 0 outside
+    ///
+    /// Fails to satisfy unless `width` and `height` fit the canvas.
+    pub fn masks(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        (canvas_width, canvas_height): (usize, usize),
+        width: &AssignedCell<Fp, Fp>,
+        height: &AssignedCell<Fp, Fp>,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let config = &self.config;
+        let columns = self.prefix(layouter.namespace(|| "column mask"), canvas_width, width)?;
+        let rows = self.prefix(layouter.namespace(|| "row mask"), canvas_height, height)?;
+
+        layouter.assign_region(
+            || "pixel masks",
+            |mut region| {
+                let mut masks = Vec::with_capacity(canvas_width * canvas_height);
+                for (y, row) in rows.iter().enumerate() {
+                    for (x, column) in columns.iter().enumerate() {
+                        let offset = y * canvas_width + x;
+                        config.q_product.enable(&mut region, offset)?;
+                        let column = column.copy_advice(|| "column bit", &mut region, config.pixels[0], offset)?;
+                        let row = row.copy_advice(|| "row bit", &mut region, config.pixels[1], offset)?;
+                        masks.push(region.assign_advice(
+                            || "mask",
+                            config.pixels[2],
+                            offset,
+                            || column.value().copied() * row.value().copied(),
+                        )?);
+                    }
+                }
+                Ok(masks)
+            },
+        )
+    }
+
+    /// Constrain every channel of `image` outside its mask to 0
+    pub fn check_padding(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        masks: &[AssignedCell<Fp, Fp>],
+    ) -> Result<(), Error> {
+        let config = &self.config;
+        if masks.len() != image.pixels.len() {
+            return Err(Error::Synthesis);
+        }
+
+        layouter.assign_region(
+            || "zero padding",
+            |mut region| {
+                for (offset, (pixel, mask)) in image.pixels.iter().zip(masks).enumerate() {
+                    config.q_zero.enable(&mut region, offset)?;
+                    for (channel, &column) in pixel.iter().zip(&config.pixels) {
+                        channel.copy_advice(|| "channel", &mut region, column, offset)?;
+                    }
+                    mask.copy_advice(|| "mask", &mut region, config.mask, offset)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// `image` with every channel outside its mask set to 0
+    pub fn apply(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        masks: &[AssignedCell<Fp, Fp>],
+    ) -> Result<AssignedImage, Error> {
+        let config = &self.config;
+        if masks.len() != image.pixels.len() {
+            return Err(Error::Synthesis);
+        }
+
+        let pixels = layouter.assign_region(
+            || "apply mask",
+            |mut region| {
+                let mut pixels = Vec::with_capacity(image.pixels.len());
+                for (index, (pixel, mask)) in image.pixels.iter().zip(masks).enumerate() {
+                    let offset = 2 * index;
+                    config.q_apply.enable(&mut region, offset)?;
+                    let mask = mask.copy_advice(|| "mask", &mut region, config.mask, offset)?;
+
+                    let mut assign = |c: usize| {
+                        let channel = pixel[c].copy_advice(|| "channel", &mut region, config.pixels[c], offset)?;
+                        region.assign_advice(
+                            || "masked channel",
+                            config.pixels[c],
+                            offset + 1,
+                            || channel.value().copied() * mask.value().copied(),
+                        )
+                    };
+                    pixels.push([assign(0)?, assign(1)?, assign(2)?]);
+                }
+                Ok(pixels)
+            },
+        )?;
+
+        Ok(AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels,
+        })
+    }
+
+    /// `width << 32 | height`, the in-circuit `encode_dimensions` of two cells
+    pub fn encode_dimensions(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        width: &AssignedCell<Fp, Fp>,
+        height: &AssignedCell<Fp, Fp>,
+    ) -> Result<AssignedCell<Fp, Fp>, Error> {
+        let config = &self.config;
+
+        layouter.assign_region(
+            || "encode dimensions",
+            |mut region| {
+                config.q_dimensions.enable(&mut region, 0)?;
+                let width = width.copy_advice(|| "width", &mut region, config.pixels[0], 0)?;
+                let height = height.copy_advice(|| "height", &mut region, config.pixels[1], 0)?;
+                region.assign_advice(
+                    || "width << 32 | height",
+                    config.pixels[2],
+                    0,
+                    || width.value().copied() * Value::known(Fp::from(1 << 32)) + height.value().copied(),
+                )
+            },
+        )
+    }
+
+    /// Bits `[i < count]` for `i` in `0..length`
+    fn prefix(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        length: usize,
+        count: &AssignedCell<Fp, Fp>,
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let config = &self.config;
+        if length == 0 {
+            return Err(Error::Synthesis);
+        }
+
+        layouter.assign_region(
+            || "mask prefix",
+            |mut region| {
+                let mut bits = Vec::with_capacity(length);
+                let mut sum: Option<AssignedCell<Fp, Fp>> = None;
+
+                for offset in 0..length {
+                    if offset == 0 {
+                        config.q_prefix_first.enable(&mut region, offset)?;
+                    } else {
+                        config.q_prefix_next.enable(&mut region, offset)?;
+                    }
+
+                    let bit = count.value().map(|count| Fp::from(((offset as u64) < low_u64(count)) as u64));
+                    let bit = region.assign_advice(|| "bit", config.pixels[0], offset, || bit)?;
+                    let value = match &sum {
+                        Some(prev) => prev.value().copied() + bit.value().copied(),
+                        None => bit.value().copied(),
+                    };
+                    sum = Some(region.assign_advice(|| "sum", config.pixels[1], offset, || value)?);
+                    bits.push(bit);
+                }
+
+                if let Some(sum) = &sum {
+                    region.constrain_equal(sum.cell(), count.cell())?;
+                }
+                Ok(bits)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::test_utils::{assign_image, channels, test_image};
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+    use image::RgbImage;
+
+    /// Masks a canvas to the size in instance rows 0 and 1, exposing the
+    /// masked channels and the encoded size after them
+    #[derive(Clone)]
+    struct MaskTestCircuit {
+        canvas: RgbImage,
+    }
+
+    #[derive(Clone, Debug)]
+    struct MaskTestConfig {
+        pixels: [Column<Advice>; 3],
+        instance: Column<Instance>,
+        mask: MaskConfig,
+    }
+
+    impl Circuit<Fp> for MaskTestCircuit {
+        type Config = MaskTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let mask_column = meta.advice_column();
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            MaskTestConfig {
+                pixels,
+                instance,
+                mask: MaskChip::configure(meta, pixels, mask_column),
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            let (width, height) = layouter.assign_region(
+                || "size",
+                |mut region| {
+                    let width = region.assign_advice_from_instance(|| "width", config.instance, 0, config.pixels[0], 0)?;
+                    let height = region.assign_advice_from_instance(|| "height", config.instance, 1, config.pixels[1], 0)?;
+                    Ok((width, height))
+                },
+            )?;
+            let image = assign_image(&mut layouter, config.pixels, &self.canvas)?;
+
+            let chip = MaskChip::construct(config.mask.clone());
+            let size = (self.canvas.width() as usize, self.canvas.height() as usize);
+            let masks = chip.masks(layouter.namespace(|| "masks"), size, &width, &height)?;
+            let masked = chip.apply(layouter.namespace(|| "apply"), &image, &masks)?;
+            let encoded = chip.encode_dimensions(layouter.namespace(|| "encode"), &width, &height)?;
+
+            for (row, cell) in masked.channels().chain([&encoded]).enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row + 2)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn masked(canvas: &RgbImage, width: u32, height: u32) -> Vec<Fp> {
+        let masked = RgbImage::from_fn(canvas.width(), canvas.height(), |x, y| {
+            if x < width && y < height {
+                *canvas.get_pixel(x, y)
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        });
+        channels(&masked)
+    }
+
+    fn prove(canvas: &RgbImage, size: (u32, u32), channels: Vec<Fp>) -> bool {
+        let circuit = MaskTestCircuit { canvas: canvas.clone() };
+        let mut instance = vec![Fp::from(size.0 as u64), Fp::from(size.1 as u64)];
+        instance.extend(channels);
+        instance.push(crate::chips::commitment::encode_dimensions(size.0 as usize, size.1 as usize));
+        MockProver::run(8, &circuit, vec![instance]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn masks_zero_everything_outside_the_real_size() {
+        let canvas = test_image(4, 3);
+        for (width, height) in [(4, 3), (1, 1), (3, 2), (0, 3)] {
+            assert!(prove(&canvas, (width, height), masked(&canvas, width, height)));
+        }
+    }
+
+    #[test]
+    fn size_must_fit_the_canvas_and_match_the_mask() {
+        let canvas = test_image(4, 3);
+
+        // Padding left unmasked
+        assert!(!prove(&canvas, (3, 2), channels(&canvas)));
+        // Masked to another size than the one claimed
+        assert!(!prove(&canvas, (3, 2), masked(&canvas, 2, 2)));
+        // No mask of 4 bits sums to 5
+        assert!(!prove(&canvas, (5, 3), masked(&canvas, 4, 3)));
+    }
+}
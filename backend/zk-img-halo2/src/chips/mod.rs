@@ -0,0 +1,149 @@
+//! Reusable halo2 chips for ZK-IMG circuits
+//!
+//! Chips operate on `AssignedImage`s so transformations can be chained by
+//! passing pixel cells from one chip to the next.
+
+pub mod adjust;
+pub mod chain;
+pub mod color;
+pub mod commitment;
+pub mod convolution;
+pub mod crop;
+pub mod fused;
+pub mod linear;
+pub mod mask;
+pub mod orientation;
+pub mod range;
+pub mod resize;
+pub mod translate;
+
+pub use adjust::{AdjustChip, AdjustConfig};
+pub use chain::ChainDigestChip;
+pub use color::ColorChip;
+pub use commitment::{ImageCommitmentChip, ImageCommitmentConfig};
+pub use convolution::ConvolutionChip;
+pub use crop::{CropChip, CropConfig, CropWindow};
+pub use fused::{FusedChip, FusedConfig};
+pub use linear::{LinearChip, LinearConfig};
+pub use mask::{MaskChip, MaskConfig};
+pub use orientation::{Orientation, OrientationChip};
+pub use range::{RangeCheckChip, RangeCheckConfig};
+pub use resize::{ResizeChip, ResizeConfig};
+pub use translate::{TranslateChip, TranslateConfig};
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::Fp,
+    plonk::{ConstraintSystem, Error, TableColumn},
+};
+
+/// Pixel cells of an image laid out in a circuit
+#[derive(Clone, Debug)]
+pub struct AssignedImage {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major RGB pixels, `width * height` entries
+    pub pixels: Vec<[AssignedCell<Fp, Fp>; 3]>,
+}
+
+impl AssignedImage {
+    /// Cells of the pixel at `(x, y)`
+    pub fn pixel(&self, x: usize, y: usize) -> &[AssignedCell<Fp, Fp>; 3] {
+        &self.pixels[y * self.width + x]
+    }
+
+    /// All channel cells in commitment order (row-major, then R, G, B)
+    pub fn channels(&self) -> impl Iterator<Item = &AssignedCell<Fp, Fp>> {
+        self.pixels.iter().flat_map(|pixel| pixel.iter())
+    }
+}
+
+/// Lookup table holding every 8-bit value, used to range-check pixel channels
+#[derive(Clone, Copy, Debug)]
+pub struct ByteTable {
+    pub values: TableColumn,
+}
+
+impl ByteTable {
+    pub fn configure(meta: &mut ConstraintSystem<Fp>) -> Self {
+        Self {
+            values: meta.lookup_table_column(),
+        }
+    }
+
+    /// Fill the table; must be called exactly once per synthesis
+    pub fn load(&self, layouter: &mut impl Layouter<Fp>) -> Result<(), Error> {
+        layouter.assign_table(
+            || "byte table",
+            |mut table| {
+                for value in 0..256 {
+                    table.assign_cell(
+                        || format!("byte {}", value),
+                        self.values,
+                        value,
+                        || Value::known(Fp::from(value as u64)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Helpers shared by the chip tests
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::AssignedImage;
+    use halo2_proofs::{
+        circuit::{Layouter, Value},
+        pasta::Fp,
+        plonk::{Advice, Column, Error},
+    };
+    use image::RgbImage;
+
+    /// Deterministic test pattern
+    pub fn test_image(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 37 + y * 11) as u8, (y * 53 + 7) as u8, ((x ^ y) * 29) as u8])
+        })
+    }
+
+    /// Assign `image` as a private witness, one pixel per row
+    pub fn assign_image(
+        layouter: &mut impl Layouter<Fp>,
+        columns: [Column<Advice>; 3],
+        image: &RgbImage,
+    ) -> Result<AssignedImage, Error> {
+        let pixels = layouter.assign_region(
+            || "test image",
+            |mut region| {
+                image
+                    .pixels()
+                    .enumerate()
+                    .map(|(offset, pixel)| {
+                        let mut assign = |c: usize| {
+                            region.assign_advice(
+                                || "pixel",
+                                columns[c],
+                                offset,
+                                || Value::known(Fp::from(pixel[c] as u64)),
+                            )
+                        };
+                        Ok([assign(0)?, assign(1)?, assign(2)?])
+                    })
+                    .collect::<Result<Vec<_>, Error>>()
+            },
+        )?;
+
+        Ok(AssignedImage {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels,
+        })
+    }
+
+    /// Channels of `image` in commitment order, for comparing against instance cells
+    pub fn channels(image: &RgbImage) -> Vec<Fp> {
+        image.as_raw().iter().map(|&c| Fp::from(c as u64)).collect()
+    }
+}
@@ -0,0 +1,162 @@
+//! Rotation and flip gadgets
+//!
+//! Quarter-turn rotations and flips only permute pixels, so like
+//! nearest-neighbour resizing they need no constraints of their own: output
+//! pixels reuse the cells of their source pixels, and whatever later binds the
+//! output (the commitment chip, or the next chip in the chain) is bound to the
+//! permuted input.
+
+use super::AssignedImage;
+use crate::transforms::RightAngle;
+
+/// Orientation of the pixel grid, see `transforms::physical`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Rotate(RightAngle),
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Orientation {
+    /// Dimensions of a reoriented `width` x `height` image
+    pub fn output_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Rotate(angle) => angle.output_size(width, height),
+            Self::FlipHorizontal | Self::FlipVertical => (width, height),
+        }
+    }
+
+    /// Source pixel of output `(x, y)` for a `width` x `height` input
+    pub fn source(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Rotate(angle) => angle.source(x, y, width, height),
+            Self::FlipHorizontal => (width - 1 - x, y),
+            Self::FlipVertical => (x, height - 1 - y),
+        }
+    }
+}
+
+/// Chip rotating or flipping an `AssignedImage` by rewiring its cells
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrientationChip;
+
+impl OrientationChip {
+    pub fn construct() -> Self {
+        Self
+    }
+
+    pub fn reorient(&self, image: &AssignedImage, orientation: Orientation) -> AssignedImage {
+        let (width, height) = orientation.output_size(image.width, image.height);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (source_x, source_y) = orientation.source(x, y, image.width, image.height);
+                image.pixel(source_x, source_y).clone()
+            })
+            .collect();
+
+        AssignedImage { width, height, pixels }
+    }
+
+    pub fn rotate(&self, image: &AssignedImage, angle: RightAngle) -> AssignedImage {
+        self.reorient(image, Orientation::Rotate(angle))
+    }
+
+    pub fn flip_horizontal(&self, image: &AssignedImage) -> AssignedImage {
+        self.reorient(image, Orientation::FlipHorizontal)
+    }
+
+    pub fn flip_vertical(&self, image: &AssignedImage) -> AssignedImage {
+        self.reorient(image, Orientation::FlipVertical)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::test_utils::{assign_image, channels, test_image};
+    use crate::transforms::physical;
+    use halo2_proofs::{
+        circuit::{Layouter, SimpleFloorPlanner},
+        dev::MockProver,
+        pasta::Fp,
+        plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    #[derive(Clone)]
+    struct OrientationTestCircuit {
+        source: RgbImage,
+        orientation: Orientation,
+    }
+
+    #[derive(Clone, Debug)]
+    struct OrientationTestConfig {
+        pixels: [Column<Advice>; 3],
+        instance: Column<Instance>,
+    }
+
+    impl Circuit<Fp> for OrientationTestCircuit {
+        type Config = OrientationTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            for column in pixels {
+                meta.enable_equality(column);
+            }
+
+            OrientationTestConfig { pixels, instance }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            let image = assign_image(&mut layouter, config.pixels, &self.source)?;
+            let reoriented = OrientationChip::construct().reorient(&image, self.orientation);
+
+            for (row, cell) in reoriented.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn prove(source: &RgbImage, orientation: Orientation, expected: &DynamicImage) -> bool {
+        let circuit = OrientationTestCircuit {
+            source: source.clone(),
+            orientation,
+        };
+        MockProver::run(6, &circuit, vec![channels(&expected.to_rgb8())])
+            .unwrap()
+            .verify()
+            .is_ok()
+    }
+
+    #[test]
+    fn orientations_match_native() {
+        let source = test_image(4, 3);
+        let image = DynamicImage::ImageRgb8(source.clone());
+
+        for degrees in [0.0, 90.0, 180.0, 270.0] {
+            let angle = RightAngle::from_degrees(degrees).unwrap();
+            let expected = physical::rotate(&image, degrees).unwrap();
+            assert!(prove(&source, Orientation::Rotate(angle), &expected));
+        }
+        assert!(prove(&source, Orientation::FlipHorizontal, &physical::flip_horizontal(&image)));
+        assert!(prove(&source, Orientation::FlipVertical, &physical::flip_vertical(&image)));
+    }
+
+    #[test]
+    fn wrong_orientation_is_rejected() {
+        let source = test_image(4, 3);
+        let image = DynamicImage::ImageRgb8(source.clone());
+
+        assert!(!prove(&source, Orientation::FlipHorizontal, &physical::flip_vertical(&image)));
+        assert!(!prove(&source, Orientation::Rotate(RightAngle::Deg180), &image));
+    }
+}
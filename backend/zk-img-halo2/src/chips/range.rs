@@ -0,0 +1,214 @@
+//! 8-, 16- and 32-bit range checks
+//!
+//! 8-bit values are looked up in the shared `ByteTable` directly. 16-bit
+//! values are decomposed into two bytes, `value = lo + 256 * hi`, and both
+//! bytes are looked up. 32-bit values are decomposed into two 16-bit limbs,
+//! `value = lo + 2^16 * hi`, which are then range checked as 16-bit values.
+
+use ff::PrimeField;
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::ByteTable;
+
+/// Exclusive upper bound of values accepted by `RangeCheckChip::check_u16`
+pub const U16_BOUND: u64 = 1 << 16;
+
+/// Exclusive upper bound of values accepted by `RangeCheckChip::check_u32`
+pub const U32_BOUND: u64 = 1 << 32;
+
+#[derive(Clone, Debug)]
+pub struct RangeCheckConfig {
+    value: Column<Advice>,
+    lo: Column<Advice>,
+    hi: Column<Advice>,
+    q_range: Selector,
+    q_byte: Selector,
+    q_limbs: Selector,
+}
+
+/// Chip proving that assigned cells fit in 8, 16 or 32 bits
+#[derive(Clone, Debug)]
+pub struct RangeCheckChip {
+    config: RangeCheckConfig,
+}
+
+impl RangeCheckChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        advice: [Column<Advice>; 3],
+        bytes: ByteTable,
+    ) -> RangeCheckConfig {
+        let [value, lo, hi] = advice;
+        meta.enable_equality(value);
+        // 32-bit limbs are copied into `value` for their 16-bit checks
+        meta.enable_equality(lo);
+        meta.enable_equality(hi);
+
+        let q_range = meta.complex_selector();
+        let q_byte = meta.complex_selector();
+        let q_limbs = meta.selector();
+
+        meta.create_gate("u16 decomposition", |meta| {
+            let q = meta.query_selector(q_range);
+            let value = meta.query_advice(value, Rotation::cur());
+            let lo = meta.query_advice(lo, Rotation::cur());
+            let hi = meta.query_advice(hi, Rotation::cur());
+            let shift = Expression::Constant(Fp::from(256));
+
+            Constraints::with_selector(q, Some(value - (lo + hi * shift)))
+        });
+
+        meta.create_gate("u32 decomposition", |meta| {
+            let q = meta.query_selector(q_limbs);
+            let value = meta.query_advice(value, Rotation::cur());
+            let lo = meta.query_advice(lo, Rotation::cur());
+            let hi = meta.query_advice(hi, Rotation::cur());
+            let shift = Expression::Constant(Fp::from(U16_BOUND));
+
+            Constraints::with_selector(q, Some(value - (lo + hi * shift)))
+        });
+
+        for byte in [lo, hi] {
+            meta.lookup(|meta| {
+                let q = meta.query_selector(q_range);
+                let byte = meta.query_advice(byte, Rotation::cur());
+
+                vec![(q * byte, bytes.values)]
+            });
+        }
+
+        meta.lookup(|meta| {
+            let q = meta.query_selector(q_byte);
+            let value = meta.query_advice(value, Rotation::cur());
+
+            vec![(q * value, bytes.values)]
+        });
+
+        RangeCheckConfig {
+            value,
+            lo,
+            hi,
+            q_range,
+            q_byte,
+            q_limbs,
+        }
+    }
+
+    pub fn construct(config: RangeCheckConfig) -> Self {
+        Self { config }
+    }
+
+    /// Constrain every cell in `cells` to `[0, 2^16)`
+    pub fn check_u16(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        cells: &[AssignedCell<Fp, Fp>],
+    ) -> Result<(), Error> {
+        let config = &self.config;
+
+        layouter.assign_region(
+            || "u16 range check",
+            |mut region| {
+                for (offset, cell) in cells.iter().enumerate() {
+                    config.q_range.enable(&mut region, offset)?;
+                    cell.copy_advice(|| "value", &mut region, config.value, offset)?;
+
+                    // Out-of-range values get garbage bytes and fail the gate
+                    let bytes = cell.value().map(|value| {
+                        let value = low_u64(value);
+                        (value & 0xff, (value >> 8) & 0xff)
+                    });
+                    region.assign_advice(
+                        || "lo",
+                        config.lo,
+                        offset,
+                        || bytes.map(|(lo, _)| Fp::from(lo)),
+                    )?;
+                    region.assign_advice(
+                        || "hi",
+                        config.hi,
+                        offset,
+                        || bytes.map(|(_, hi)| Fp::from(hi)),
+                    )?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Constrain every cell in `cells` to `[0, 2^32)`
+    pub fn check_u32(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        cells: &[AssignedCell<Fp, Fp>],
+    ) -> Result<(), Error> {
+        let config = &self.config;
+
+        let limbs = layouter.assign_region(
+            || "u32 range check",
+            |mut region| {
+                let mut limbs = Vec::with_capacity(2 * cells.len());
+                for (offset, cell) in cells.iter().enumerate() {
+                    config.q_limbs.enable(&mut region, offset)?;
+                    cell.copy_advice(|| "value", &mut region, config.value, offset)?;
+
+                    // Out-of-range values get limbs that fail the gate or the u16 checks
+                    let value = cell.value().map(low_u64);
+                    let lo = value.map(|value| Fp::from(value % U16_BOUND));
+                    let hi = value.map(|value| Fp::from((value / U16_BOUND) % U16_BOUND));
+                    limbs.push(region.assign_advice(|| "lo", config.lo, offset, || lo)?);
+                    limbs.push(region.assign_advice(|| "hi", config.hi, offset, || hi)?);
+                }
+                Ok(limbs)
+            },
+        )?;
+
+        self.check_u16(layouter.namespace(|| "u32 limbs"), &limbs)
+    }
+
+    /// Constrain every cell in `cells` to `[0, 256)`
+    pub fn check_u8(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        cells: &[AssignedCell<Fp, Fp>],
+    ) -> Result<(), Error> {
+        let config = &self.config;
+
+        layouter.assign_region(
+            || "u8 range check",
+            |mut region| {
+                for (offset, cell) in cells.iter().enumerate() {
+                    config.q_byte.enable(&mut region, offset)?;
+                    cell.copy_advice(|| "value", &mut region, config.value, offset)?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+/// Convert a field element holding a small integer back to `usize`
+pub fn field_to_usize(value: Fp) -> Option<usize> {
+    let repr = value.to_repr();
+    if repr[8..].iter().any(|&byte| byte != 0) {
+        return None;
+    }
+
+    usize::try_from(low_u64(&value)).ok()
+}
+
+/// Low 64 bits of a field element, used to derive witnesses from small values
+///
+/// Values that do not fit produce garbage witnesses, which the constraints
+/// then reject.
+pub(crate) fn low_u64(value: &Fp) -> u64 {
+    let repr = value.to_repr();
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&repr[..8]);
+    u64::from_le_bytes(low)
+}
@@ -0,0 +1,386 @@
+//! Resize gadget
+//!
+//! Nearest-neighbour output pixels reuse the cells of the sampled input pixel.
+//! Bilinear output channels are proven one per two rows:
+//!
+//! ```text
+//!   | p00 | p10 | p01 |   w00 w10 w01 w11 (fixed)
+//!   | p11 | out | rem |
+//! ```
+//!
+//! with `w00*p00 + w10*p10 + w01*p01 + w11*p11 + 2^15 = out * 2^16 + rem`.
+//! The weights are the fixed-point constants from
+//! `transforms::physical::bilinear_weights`, and `out` and `rem` are
+//! range-checked to 16 bits, which pins `out` to the rounded result of the
+//! native `transforms::physical::resize`.
+
+use halo2_proofs::{
+    circuit::{Layouter, Value},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Fixed, Selector},
+    poly::Rotation,
+};
+
+use super::{
+    range::{low_u64, RangeCheckChip, RangeCheckConfig},
+    AssignedImage,
+};
+use crate::transforms::{
+    physical::{bilinear_tap, bilinear_weights, nearest_source, RESIZE_WEIGHT_BITS},
+    ResizeFilter,
+};
+
+const SHIFT: u32 = 2 * RESIZE_WEIGHT_BITS;
+
+#[derive(Clone, Debug)]
+pub struct ResizeConfig {
+    pixels: [Column<Advice>; 3],
+    weights: [Column<Fixed>; 4],
+    q_bilinear: Selector,
+    range: RangeCheckConfig,
+}
+
+/// Chip resizing an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct ResizeChip {
+    config: ResizeConfig,
+}
+
+impl ResizeChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        pixels: [Column<Advice>; 3],
+        range: RangeCheckConfig,
+    ) -> ResizeConfig {
+        for column in pixels {
+            meta.enable_equality(column);
+        }
+
+        let weights = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+        let q_bilinear = meta.selector();
+
+        meta.create_gate("bilinear interpolation", |meta| {
+            let q = meta.query_selector(q_bilinear);
+            let samples = [
+                meta.query_advice(pixels[0], Rotation::cur()),
+                meta.query_advice(pixels[1], Rotation::cur()),
+                meta.query_advice(pixels[2], Rotation::cur()),
+                meta.query_advice(pixels[0], Rotation::next()),
+            ];
+            let out = meta.query_advice(pixels[1], Rotation::next());
+            let rem = meta.query_advice(pixels[2], Rotation::next());
+
+            let sum = samples
+                .into_iter()
+                .zip(weights)
+                .map(|(sample, weight)| sample * meta.query_fixed(weight))
+                .fold(Expression::Constant(Fp::from(1 << (SHIFT - 1))), |acc, term| acc + term);
+
+            Constraints::with_selector(q, Some(sum - out * Expression::Constant(Fp::from(1 << SHIFT)) - rem))
+        });
+
+        ResizeConfig {
+            pixels,
+            weights,
+            q_bilinear,
+            range,
+        }
+    }
+
+    pub fn construct(config: ResizeConfig) -> Self {
+        Self { config }
+    }
+
+    /// Resize `image` to `width` x `height` with the given filter
+    pub fn resize(
+        &self,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        width: usize,
+        height: usize,
+        filter: ResizeFilter,
+    ) -> Result<AssignedImage, Error> {
+        if width == 0 || height == 0 {
+            return Ok(AssignedImage {
+                width,
+                height,
+                pixels: vec![],
+            });
+        }
+        if image.width == 0 || image.height == 0 {
+            return Err(Error::Synthesis);
+        }
+
+        match filter {
+            ResizeFilter::Nearest => Ok(Self::nearest(image, width, height)),
+            ResizeFilter::Bilinear => self.bilinear(layouter, image, width, height),
+        }
+    }
+
+    /// Nearest-neighbour sampling only permutes cells, so no constraints are needed
+    fn nearest(image: &AssignedImage, width: usize, height: usize) -> AssignedImage {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let source_x = nearest_source(x, image.width, width);
+                let source_y = nearest_source(y, image.height, height);
+                image.pixel(source_x, source_y).clone()
+            })
+            .collect();
+
+        AssignedImage { width, height, pixels }
+    }
+
+    fn bilinear(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        width: usize,
+        height: usize,
+    ) -> Result<AssignedImage, Error> {
+        let config = &self.config;
+
+        let (pixels, checked) = layouter.assign_region(
+            || "bilinear resize",
+            |mut region| {
+                let mut pixels = Vec::with_capacity(width * height);
+                let mut checked = Vec::with_capacity(width * height * 6);
+
+                for y in 0..height {
+                    let ty = bilinear_tap(y, image.height, height);
+                    for x in 0..width {
+                        let tx = bilinear_tap(x, image.width, width);
+                        let weights = bilinear_weights(tx, ty);
+                        let corners = [(tx.lo, ty.lo), (tx.hi, ty.lo), (tx.lo, ty.hi), (tx.hi, ty.hi)]
+                            .map(|(sx, sy)| image.pixel(sx, sy));
+
+                        let mut channels = Vec::with_capacity(3);
+                        for c in 0..3 {
+                            let row = 2 * ((y * width + x) * 3 + c);
+                            config.q_bilinear.enable(&mut region, row)?;
+                            for (column, weight) in config.weights.iter().zip(weights) {
+                                region.assign_fixed(|| "weight", *column, row, || Value::known(Fp::from(weight)))?;
+                            }
+
+                            let placement = [
+                                (config.pixels[0], row),
+                                (config.pixels[1], row),
+                                (config.pixels[2], row),
+                                (config.pixels[0], row + 1),
+                            ];
+                            let mut total = Value::known(Fp::from(1 << (SHIFT - 1)));
+                            for ((column, offset), (corner, weight)) in placement.into_iter().zip(corners.iter().zip(weights)) {
+                                let sample = corner[c].copy_advice(|| "sample", &mut region, column, offset)?;
+                                total = total + sample.value().map(|v| *v * Fp::from(weight));
+                            }
+
+                            let total = total.map(|total| low_u64(&total));
+                            let out = region.assign_advice(
+                                || "interpolated",
+                                config.pixels[1],
+                                row + 1,
+                                || total.map(|total| Fp::from(total >> SHIFT)),
+                            )?;
+                            let rem = region.assign_advice(
+                                || "remainder",
+                                config.pixels[2],
+                                row + 1,
+                                || total.map(|total| Fp::from(total & ((1 << SHIFT) - 1))),
+                            )?;
+
+                            checked.extend([out.clone(), rem]);
+                            channels.push(out);
+                        }
+                        pixels.push([channels[0].clone(), channels[1].clone(), channels[2].clone()]);
+                    }
+                }
+                Ok((pixels, checked))
+            },
+        )?;
+
+        RangeCheckChip::construct(config.range.clone())
+            .check_u16(layouter.namespace(|| "bilinear rounding"), &checked)?;
+
+        Ok(AssignedImage { width, height, pixels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use crate::transforms::physical;
+    use halo2_proofs::{
+        circuit::{Region, SimpleFloorPlanner},
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    #[derive(Clone)]
+    struct ResizeTestCircuit {
+        source: RgbImage,
+        width: usize,
+        height: usize,
+        filter: ResizeFilter,
+        /// Add a hand-assigned bilinear row claiming `out + lie`
+        lie: Option<u64>,
+    }
+
+    #[derive(Clone, Debug)]
+    struct ResizeTestConfig {
+        pixels: [Column<Advice>; 3],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        resize: ResizeConfig,
+    }
+
+    impl Circuit<Fp> for ResizeTestCircuit {
+        type Config = ResizeTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, pixels, bytes);
+            let resize = ResizeChip::configure(meta, pixels, range);
+
+            ResizeTestConfig {
+                pixels,
+                instance,
+                bytes,
+                resize,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let image = assign_image(&mut layouter, config.pixels, &self.source)?;
+            let chip = ResizeChip::construct(config.resize.clone());
+
+            if let Some(lie) = self.lie {
+                // 255 * 2^16 + 2^15 rounds to 255; claim 255 + lie instead
+                let checked = layouter.assign_region(
+                    || "forged row",
+                    |mut region| {
+                        let resize = &config.resize;
+                        resize.q_bilinear.enable(&mut region, 0)?;
+                        let assign = |region: &mut Region<'_, Fp>, column, row, value: Fp| {
+                            region.assign_advice(|| "forged", column, row, || Value::known(value))
+                        };
+                        for (i, column) in resize.weights.iter().enumerate() {
+                            let weight = if i == 0 { 1 << SHIFT } else { 0 };
+                            region.assign_fixed(|| "weight", *column, 0, || Value::known(Fp::from(weight)))?;
+                        }
+                        for (column, row) in [(0, 0), (1, 0), (2, 0), (0, 1)] {
+                            assign(&mut region, config.pixels[column], row, Fp::from(255))?;
+                        }
+                        let half = Fp::from(1 << (SHIFT - 1));
+                        let out = assign(&mut region, config.pixels[1], 1, Fp::from(255 + lie))?;
+                        let rem = assign(&mut region, config.pixels[2], 1, half - Fp::from(lie << SHIFT))?;
+                        Ok(vec![out, rem])
+                    },
+                )?;
+                RangeCheckChip::construct(config.resize.range.clone())
+                    .check_u16(layouter.namespace(|| "forged range"), &checked)?;
+            }
+
+            let resized = chip.resize(
+                layouter.namespace(|| "resize"),
+                &image,
+                self.width,
+                self.height,
+                self.filter,
+            )?;
+            for (row, cell) in resized.channels().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn native(source: &RgbImage, width: u32, height: u32, filter: ResizeFilter) -> Vec<Fp> {
+        let resized = physical::resize(&DynamicImage::ImageRgb8(source.clone()), width, height, filter);
+        channels(&resized.to_rgb8())
+    }
+
+    fn prove(circuit: &ResizeTestCircuit, instance: Vec<Fp>) -> bool {
+        MockProver::run(10, circuit, vec![instance]).unwrap().verify().is_ok()
+    }
+
+    fn matches_native(src: (u32, u32), dst: (u32, u32), filter: ResizeFilter) -> bool {
+        let source = test_image(src.0, src.1);
+        let circuit = ResizeTestCircuit {
+            source: source.clone(),
+            width: dst.0 as usize,
+            height: dst.1 as usize,
+            filter,
+            lie: None,
+        };
+        prove(&circuit, native(&source, dst.0, dst.1, filter))
+    }
+
+    #[test]
+    fn bilinear_matches_native() {
+        assert!(matches_native((3, 2), (5, 4), ResizeFilter::Bilinear));
+        assert!(matches_native((6, 5), (4, 3), ResizeFilter::Bilinear));
+        assert!(matches_native((4, 4), (4, 4), ResizeFilter::Bilinear));
+    }
+
+    #[test]
+    fn nearest_matches_native() {
+        assert!(matches_native((3, 2), (5, 4), ResizeFilter::Nearest));
+        assert!(matches_native((6, 5), (4, 3), ResizeFilter::Nearest));
+    }
+
+    #[test]
+    fn identity_resize_is_lossless() {
+        let source = DynamicImage::ImageRgb8(test_image(5, 3));
+        for filter in [ResizeFilter::Nearest, ResizeFilter::Bilinear] {
+            assert_eq!(physical::resize(&source, 5, 3, filter).to_rgb8(), source.to_rgb8());
+        }
+    }
+
+    #[test]
+    fn wrong_output_pixel_is_rejected() {
+        let source = test_image(3, 2);
+        let circuit = ResizeTestCircuit {
+            source: source.clone(),
+            width: 5,
+            height: 4,
+            filter: ResizeFilter::Bilinear,
+            lie: None,
+        };
+        let mut instance = native(&source, 5, 4, ResizeFilter::Bilinear);
+        instance[7] += Fp::one();
+
+        assert!(!prove(&circuit, instance));
+    }
+
+    #[test]
+    fn misrounded_output_is_rejected() {
+        let source = test_image(2, 2);
+        let instance = native(&source, 3, 3, ResizeFilter::Bilinear);
+        let circuit = |lie| ResizeTestCircuit {
+            source: source.clone(),
+            width: 3,
+            height: 3,
+            filter: ResizeFilter::Bilinear,
+            lie: Some(lie),
+        };
+
+        // The forged row is only rejected because of the lie
+        assert!(prove(&circuit(0), instance.clone()));
+        assert!(!prove(&circuit(1), instance));
+    }
+}
@@ -0,0 +1,281 @@
+//! Translate gadget
+//!
+//! The shift `(dx, dy)` and the fill colour are assigned from fixed constants,
+//! so they are part of the verifying key. The shift is a signed witness,
+//! constrained on one row as
+//!
+//! ```text
+//!   | dx | dx + 2^15 |
+//!   | dy | dy + 2^15 |
+//! ```
+//!
+//! with the biased value range-checked to 16 bits, and the fill channels are
+//! range-checked to 8 bits. Every output pixel then reuses either the cells of
+//! its shifted source pixel or the fill cells, so each one is provably one of
+//! the two.
+
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    pasta::Fp,
+    plonk::{Advice, Column, ConstraintSystem, Constraints, Error, Expression, Selector},
+    poly::Rotation,
+};
+
+use super::{
+    linear::signed,
+    range::{RangeCheckChip, RangeCheckConfig},
+    AssignedImage,
+};
+use crate::transforms::physical::translate_source;
+
+/// Shifts must lie in `[-SHIFT_BIAS, SHIFT_BIAS)`
+const SHIFT_BIAS: u64 = 1 << 15;
+
+#[derive(Clone, Debug)]
+pub struct TranslateConfig {
+    pixels: [Column<Advice>; 3],
+    q_shift: Selector,
+    range: RangeCheckConfig,
+}
+
+/// Chip translating an `AssignedImage`
+#[derive(Clone, Debug)]
+pub struct TranslateChip {
+    config: TranslateConfig,
+}
+
+impl TranslateChip {
+    pub fn configure(
+        meta: &mut ConstraintSystem<Fp>,
+        pixels: [Column<Advice>; 3],
+        range: RangeCheckConfig,
+    ) -> TranslateConfig {
+        for column in pixels {
+            meta.enable_equality(column);
+        }
+
+        let q_shift = meta.selector();
+
+        meta.create_gate("signed shift", |meta| {
+            let q = meta.query_selector(q_shift);
+            let shift = meta.query_advice(pixels[0], Rotation::cur());
+            let biased = meta.query_advice(pixels[1], Rotation::cur());
+
+            Constraints::with_selector(q, Some(biased - shift - Expression::Constant(Fp::from(SHIFT_BIAS))))
+        });
+
+        TranslateConfig {
+            pixels,
+            q_shift,
+            range,
+        }
+    }
+
+    pub fn construct(config: TranslateConfig) -> Self {
+        Self { config }
+    }
+
+    /// Shift `image` by `(dx, dy)`, returning the translated image and the
+    /// parameter cells `[dx, dy, fill red, fill green, fill blue]`
+    pub fn translate(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        dx: i32,
+        dy: i32,
+        fill: [u8; 3],
+    ) -> Result<(AssignedImage, [AssignedCell<Fp, Fp>; 5]), Error> {
+        let bias = SHIFT_BIAS as i64;
+        if ![dx, dy].iter().all(|&shift| (-bias..bias).contains(&(shift as i64))) {
+            return Err(Error::Synthesis);
+        }
+
+        let [dx_cell, dy_cell] = self.assign_shift(layouter.namespace(|| "shift"), [dx, dy])?;
+        let fill = self.assign_fill(layouter.namespace(|| "fill"), fill)?;
+
+        let pixels = (0..image.height)
+            .flat_map(|y| (0..image.width).map(move |x| (x, y)))
+            .map(|(x, y)| match translate_source(x, y, dx, dy, image.width, image.height) {
+                Some((source_x, source_y)) => image.pixel(source_x, source_y).clone(),
+                None => fill.clone(),
+            })
+            .collect();
+
+        let translated = AssignedImage {
+            width: image.width,
+            height: image.height,
+            pixels,
+        };
+        let [red, green, blue] = fill;
+        Ok((translated, [dx_cell, dy_cell, red, green, blue]))
+    }
+
+    /// Assign the shift from constants and prove each component fits the signed range
+    fn assign_shift(
+        &self,
+        mut layouter: impl Layouter<Fp>,
+        shift: [i32; 2],
+    ) -> Result<[AssignedCell<Fp, Fp>; 2], Error> {
+        let config = &self.config;
+
+        let rows = layouter.assign_region(
+            || "signed shift",
+            |mut region| {
+                let mut rows = Vec::with_capacity(2);
+                for (row, &value) in shift.iter().enumerate() {
+                    config.q_shift.enable(&mut region, row)?;
+                    let value = signed(value as i64);
+
+                    let cell = region.assign_advice_from_constant(|| "shift", config.pixels[0], row, value)?;
+                    let biased = region.assign_advice(
+                        || "biased shift",
+                        config.pixels[1],
+                        row,
+                        || cell.value().map(|v| v + Fp::from(SHIFT_BIAS)),
+                    )?;
+                    rows.push((cell, biased));
+                }
+                Ok(rows)
+            },
+        )?;
+
+        let biased: Vec<_> = rows.iter().map(|(_, biased)| biased.clone()).collect();
+        RangeCheckChip::construct(config.range.clone()).check_u16(layouter.namespace(|| "shift range"), &biased)?;
+
+        let [(dx, _), (dy, _)]: [_; 2] = rows.try_into().map_err(|_| Error::Synthesis)?;
+        Ok([dx, dy])
+    }
+
+    fn assign_fill(&self, mut layouter: impl Layouter<Fp>, fill: [u8; 3]) -> Result<[AssignedCell<Fp, Fp>; 3], Error> {
+        let config = &self.config;
+
+        let cells = layouter.assign_region(
+            || "fill colour",
+            |mut region| {
+                let mut assign = |c: usize| {
+                    region.assign_advice_from_constant(|| "fill", config.pixels[c], 0, Fp::from(fill[c] as u64))
+                };
+                Ok([assign(0)?, assign(1)?, assign(2)?])
+            },
+        )?;
+
+        RangeCheckChip::construct(config.range.clone()).check_u8(layouter.namespace(|| "fill range"), &cells)?;
+        Ok(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chips::{
+        test_utils::{assign_image, channels, test_image},
+        ByteTable,
+    };
+    use crate::transforms::physical;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        plonk::{Circuit, Instance},
+    };
+    use image::{DynamicImage, RgbImage};
+
+    const FILL: [u8; 3] = [12, 200, 77];
+
+    #[derive(Clone)]
+    struct TranslateTestCircuit {
+        source: RgbImage,
+        shift: (i32, i32),
+    }
+
+    #[derive(Clone, Debug)]
+    struct TranslateTestConfig {
+        pixels: [Column<Advice>; 3],
+        instance: Column<Instance>,
+        bytes: ByteTable,
+        translate: TranslateConfig,
+    }
+
+    impl Circuit<Fp> for TranslateTestCircuit {
+        type Config = TranslateTestConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self {
+            self.clone()
+        }
+
+        fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+            let instance = meta.instance_column();
+            meta.enable_equality(instance);
+            let constants = meta.fixed_column();
+            meta.enable_constant(constants);
+
+            let bytes = ByteTable::configure(meta);
+            let range = RangeCheckChip::configure(meta, pixels, bytes);
+            let translate = TranslateChip::configure(meta, pixels, range);
+
+            TranslateTestConfig {
+                pixels,
+                instance,
+                bytes,
+                translate,
+            }
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+            config.bytes.load(&mut layouter)?;
+            let image = assign_image(&mut layouter, config.pixels, &self.source)?;
+
+            let (dx, dy) = self.shift;
+            let chip = TranslateChip::construct(config.translate.clone());
+            let (translated, params) = chip.translate(layouter.namespace(|| "translate"), &image, dx, dy, FILL)?;
+
+            for (row, cell) in params.iter().chain(translated.channels()).enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn instance(shift: (i32, i32), expected: &DynamicImage) -> Vec<Fp> {
+        let params = [signed(shift.0 as i64), signed(shift.1 as i64)]
+            .into_iter()
+            .chain(FILL.map(|c| Fp::from(c as u64)));
+        params.chain(channels(&expected.to_rgb8())).collect()
+    }
+
+    fn prove(source: &RgbImage, shift: (i32, i32), instance: Vec<Fp>) -> bool {
+        let circuit = TranslateTestCircuit {
+            source: source.clone(),
+            shift,
+        };
+        MockProver::run(9, &circuit, vec![instance]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn translations_match_native() {
+        let source = test_image(4, 3);
+        let image = DynamicImage::ImageRgb8(source.clone());
+
+        for shift in [(0, 0), (1, 2), (-2, 1), (3, -1), (-5, 0), (0, 7)] {
+            let expected = physical::translate(&image, shift.0, shift.1, FILL);
+            assert!(prove(&source, shift, instance(shift, &expected)));
+        }
+    }
+
+    #[test]
+    fn wrong_shift_or_fill_is_rejected() {
+        let source = test_image(4, 3);
+        let image = DynamicImage::ImageRgb8(source.clone());
+        let expected = physical::translate(&image, -1, 1, FILL);
+
+        // Claimed shift differs from the one the pixels were moved by
+        assert!(!prove(&source, (-1, 1), instance((1, 1), &expected)));
+
+        // Vacated pixels filled with a different colour
+        let black = physical::translate(&image, -1, 1, [0, 0, 0]);
+        let mut forged = instance((-1, 1), &black);
+        forged[2..5].copy_from_slice(&FILL.map(|c| Fp::from(c as u64)));
+        assert!(!prove(&source, (-1, 1), forged));
+    }
+}
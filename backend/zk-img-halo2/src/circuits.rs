@@ -3,59 +3,133 @@
 //! Based on Section 7 of the paper: "Detailed Implementation"
 //! Implements efficient circuits for HD image transformations
 
+use anyhow::Result;
 use halo2_proofs::{
-    arithmetic::FieldExt,
-    circuit::{Layouter, SimpleFloorPlanner, Value},
-    plonk::{Circuit, ConstraintSystem, Error, Instance, TableColumn},
+    arithmetic::Field,
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
     pasta::Fp,
+    plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed, Instance},
 };
-use halo2_gadgets::{
-    poseidon::{primitives as poseidon, PoseidonChip, PoseidonConfig, PoseidonInstructions},
-    table::{BitwiseTableChip, BitwiseTableConfig},
-};
+use halo2_gadgets::poseidon::{primitives::P128Pow5T3, Pow5Chip, Pow5Config};
 use std::marker::PhantomData;
 
+use crate::chips::{
+    commitment::native_image_commitment, AdjustChip, AdjustConfig, AssignedImage, ByteTable,
+    ChainDigestChip, ColorChip, ConvolutionChip, CropChip, CropConfig, CropWindow, FusedChip,
+    FusedConfig, ImageCommitmentChip,
+    ImageCommitmentConfig, LinearChip, LinearConfig, OrientationChip, RangeCheckChip, ResizeChip,
+    ResizeConfig, TranslateChip, TranslateConfig,
+};
+use crate::cost::{CircuitCost, ConstantColumns};
+use crate::keys::{KeyShape, KeyedCircuit};
+use crate::tiling::TileLayout;
+use crate::transforms::{
+    field_elements_to_image, image_to_field_elements, ConvolutionKernel, Rational, ResizeFilter,
+    RightAngle,
+};
+use crate::Transformation;
+
 /// Configuration for ZK-IMG circuit
 #[derive(Clone, Debug)]
-pub struct ZKIMGCircuitConfig<F: FieldExt> {
-    pub poseidon_config: PoseidonConfig<F, 3, 2>, // t=3, rate=2
-    pub instance: Instance,
+pub struct ZKIMGCircuitConfig<F: Field> {
+    pub pixels: [Column<Advice>; 3], // One RGB pixel per row
+    pub accumulator: Column<Advice>, // Running sums of the linear chip
+    pub constants: Column<Fixed>,
+    pub poseidon_config: Pow5Config<F, 3, 2>, // t=3, rate=2
+    pub commitment_config: ImageCommitmentConfig,
+    pub crop_config: CropConfig,
+    pub resize_config: ResizeConfig,
+    pub translate_config: TranslateConfig,
+    pub linear_config: LinearConfig,
+    pub adjust_config: AdjustConfig,
+    pub fused_config: FusedConfig,
+    pub bytes: ByteTable,
+    pub instance: Column<Instance>,
     pub _marker: PhantomData<F>,
 }
 
 /// ZK-IMG Circuit for image transformations
+///
+/// Lays out one chip per transformation, each consuming the previous step's
+/// pixel cells, so a single proof covers the whole chain.
 #[derive(Clone)]
-pub struct ZKIMGCircuit<F: FieldExt> {
+pub struct ZKIMGCircuit<F: Field> {
     pub image_pixels: Vec<Vec<Vec<F>>>, // [height][width][3] RGB values
-    pub transformation_params: Vec<F>,
+    pub transformations: Vec<Transformation>,
     pub input_hash: F,
     pub output_hash: F,
     pub _marker: PhantomData<F>,
 }
 
-impl<F: FieldExt> Circuit<F> for ZKIMGCircuit<F> {
-    type Config = ZKIMGCircuitConfig<F>;
+impl Circuit<Fp> for ZKIMGCircuit<Fp> {
+    type Config = ZKIMGCircuitConfig<Fp>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self {
-            image_pixels: vec![],
-            transformation_params: vec![],
-            input_hash: F::zero(),
-            output_hash: F::zero(),
-            _marker: PhantomData,
-        }
+        // The layout depends on the image dimensions, so keep the shape
+        let (width, height) = self.dimensions();
+        Self::blank(width, height, self.transformations.clone())
     }
 
-    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
         let instance = meta.instance_column();
         meta.enable_equality(instance);
 
+        let pixels = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+        for column in pixels {
+            meta.enable_equality(column);
+        }
+        let accumulator = meta.advice_column();
+
         // Configure Poseidon hash for input/output privacy
-        let poseidon_config = PoseidonChip::<F, 3, 2>::configure(meta);
+        let state = [meta.advice_column(), meta.advice_column(), meta.advice_column()];
+        let partial_sbox = meta.advice_column();
+        let rc_a = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+        let rc_b = [meta.fixed_column(), meta.fixed_column(), meta.fixed_column()];
+        let constants = rc_b[0];
+        meta.enable_constant(constants);
+
+        let poseidon_config =
+            Pow5Chip::configure::<P128Pow5T3>(meta, state, partial_sbox, rc_a, rc_b);
+
+        // Pixel commitments pack channels in the first two pixel columns
+        let bytes = ByteTable::configure(meta);
+        let commitment_config = ImageCommitmentChip::configure(
+            meta,
+            [pixels[0], pixels[1]],
+            bytes,
+            poseidon_config.clone(),
+        );
+
+        // Transformation chips share the pixel columns and range checks
+        let advice = [pixels[0], pixels[1], pixels[2], accumulator];
+        let range_config = RangeCheckChip::configure(meta, pixels, bytes);
+        let crop_config = CropChip::configure(meta, pixels, range_config.clone());
+        let resize_config = ResizeChip::configure(meta, pixels, range_config.clone());
+        let translate_config = TranslateChip::configure(meta, pixels, range_config.clone());
+        let linear_config = LinearChip::configure(meta, advice, range_config.clone());
+        let adjust_config = AdjustChip::configure(meta, advice, linear_config.clone(), range_config.clone());
+        let fused_config = FusedChip::configure(
+            meta,
+            advice,
+            crop_config.clone(),
+            resize_config.clone(),
+            range_config,
+        );
 
         ZKIMGCircuitConfig {
+            pixels,
+            accumulator,
+            constants,
             poseidon_config,
+            commitment_config,
+            crop_config,
+            resize_config,
+            translate_config,
+            linear_config,
+            adjust_config,
+            fused_config,
+            bytes,
             instance,
             _marker: PhantomData,
         }
@@ -64,190 +138,546 @@ impl<F: FieldExt> Circuit<F> for ZKIMGCircuit<F> {
     fn synthesize(
         &self,
         config: Self::Config,
-        mut layouter: impl Layouter<F>,
+        mut layouter: impl Layouter<Fp>,
     ) -> Result<(), Error> {
-        // Load Poseidon chip
-        let poseidon_chip = PoseidonChip::<F, 3, 2>::construct(config.poseidon_config.clone());
-
-        // Hash input image (for privacy)
-        let input_hash = self.hash_image(&poseidon_chip, &mut layouter)?;
-
-        // Apply transformations
-        let transformed_pixels = self.apply_transformations(&mut layouter)?;
-
-        // Hash output image (for privacy)
-        let output_hash = self.hash_image_output(&poseidon_chip, &transformed_pixels, &mut layouter)?;
+        let [input_hash, output_hash, chain_digest] = self.lay_out(&config, &mut layouter)?;
 
         // Constrain hashes match public inputs
         layouter.constrain_instance(input_hash.cell(), config.instance, 0)?;
         layouter.constrain_instance(output_hash.cell(), config.instance, 1)?;
 
+        // Bind the proof to the transformation chain it applied
+        layouter.constrain_instance(chain_digest.cell(), config.instance, 2)?;
+
         Ok(())
     }
 }
 
-impl<F: FieldExt> ZKIMGCircuit<F> {
-    /// Hash image pixels using Poseidon (for input privacy)
-    fn hash_image(
-        &self,
-        poseidon_chip: &PoseidonChip<F, 3, 2>,
-        layouter: &mut impl Layouter<F>,
-    ) -> Result<poseidon::Hash<F, poseidon::Poseidon<F, 3, 2>, 3, 2>, Error> {
-        // For HD images, we use a Merkle tree approach as described in the paper
-        // This is a simplified version - full implementation would chunk the image
-
-        let mut hasher = poseidon_chip.hash(layouter)?;
-
-        // Hash representative pixels (simplified for demo)
-        for row in 0..std::cmp::min(32, self.image_pixels.len()) {
-            for col in 0..std::cmp::min(32, self.image_pixels[row].len()) {
-                let r = self.image_pixels[row][col][0];
-                let g = self.image_pixels[row][col][1];
-                let b = self.image_pixels[row][col][2];
-
-                hasher.update(Value::known(r))?;
-                hasher.update(Value::known(g))?;
-                hasher.update(Value::known(b))?;
-            }
-        }
+impl ConstantColumns for ZKIMGCircuit<Fp> {
+    fn constant_columns(config: &Self::Config) -> Vec<Column<Fixed>> {
+        vec![config.constants]
+    }
+}
 
-        hasher.squeeze(layouter)
+impl KeyedCircuit for ZKIMGCircuit<Fp> {
+    fn circuit_id(&self) -> &'static str {
+        "zkimg"
     }
 
-    /// Hash output image (for output privacy)
-    fn hash_image_output(
-        &self,
-        poseidon_chip: &PoseidonChip<F, 3, 2>,
-        transformed_pixels: &[Vec<Vec<F>>],
-        layouter: &mut impl Layouter<F>,
-    ) -> Result<poseidon::Hash<F, poseidon::Poseidon<F, 3, 2>, 3, 2>, Error> {
-        let mut hasher = poseidon_chip.hash(layouter)?;
-
-        // Hash output pixels
-        for row in 0..std::cmp::min(32, transformed_pixels.len()) {
-            for col in 0..std::cmp::min(32, transformed_pixels[row].len()) {
-                let r = transformed_pixels[row][col][0];
-                let g = transformed_pixels[row][col][1];
-                let b = transformed_pixels[row][col][2];
-
-                hasher.update(Value::known(r))?;
-                hasher.update(Value::known(g))?;
-                hasher.update(Value::known(b))?;
-            }
+    fn key_shape(&self) -> Result<KeyShape> {
+        let (width, height) = self.dimensions();
+        Ok(KeyShape {
+            width: width as u32,
+            height: height as u32,
+            chain_digest: Transformation::chain_digest(&self.transformations)?,
+        })
+    }
+}
+
+impl ZKIMGCircuit<Fp> {
+    /// Build a circuit for the given image, computing the public hashes natively
+    pub fn new(image_pixels: Vec<Vec<Vec<Fp>>>, transformations: Vec<Transformation>) -> Result<Self> {
+        let input = field_elements_to_image(&image_pixels);
+        let output = transformations
+            .iter()
+            .try_fold(input, |image, transformation| transformation.apply(&image))?;
+
+        let input_hash = Self::native_image_hash(&image_pixels);
+        let output_hash = Self::native_image_hash(&image_to_field_elements(&output));
+
+        Ok(Self {
+            image_pixels,
+            transformations,
+            input_hash,
+            output_hash,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Circuit with the layout of an image of the given size but no witness data
+    pub fn blank(width: usize, height: usize, transformations: Vec<Transformation>) -> Self {
+        Self {
+            image_pixels: vec![vec![vec![Fp::zero(); 3]; width]; height],
+            transformations,
+            input_hash: Fp::zero(),
+            output_hash: Fp::zero(),
+            _marker: PhantomData,
         }
+    }
+
+    /// Public inputs in instance-column order: [input hash, output hash, chain digest]
+    pub fn public_inputs(&self) -> Result<Vec<Fp>> {
+        let chain_digest = Transformation::chain_digest(&self.transformations)?;
+        Ok(vec![self.input_hash, self.output_hash, chain_digest])
+    }
 
-        hasher.squeeze(layouter)
+    /// Layout cost of proving this chain on this image size
+    pub fn cost(&self) -> Result<CircuitCost> {
+        Ok(CircuitCost::measure(self)?)
     }
 
-    /// Apply image transformations in circuit
-    fn apply_transformations(
+    /// Native counterpart of the in-circuit image commitment
+    pub fn native_image_hash(pixels: &[Vec<Vec<Fp>>]) -> Fp {
+        let height = pixels.len();
+        let width = pixels.first().map(|row| row.len()).unwrap_or(0);
+        let channels: Vec<Fp> = pixels.iter().flatten().flatten().copied().collect();
+
+        native_image_commitment(width, height, &channels)
+    }
+
+    /// Commit to the input, apply the chain and commit to the output,
+    /// returning the input hash, output hash and chain digest cells
+    pub(crate) fn lay_out(
         &self,
-        layouter: &mut impl Layouter<F>,
-    ) -> Result<Vec<Vec<Vec<F>>>, Error> {
-        let mut result = self.image_pixels.clone();
-
-        // Apply transformations based on parameters
-        // For now, implement basic crop if parameters are provided
-        if self.transformation_params.len() >= 4 {
-            let crop_x = self.transformation_params[0];
-            let crop_y = self.transformation_params[1];
-            let crop_width = self.transformation_params[2];
-            let crop_height = self.transformation_params[3];
-
-            // Apply crop transformation
-            result = self.apply_crop(crop_x, crop_y, crop_width, crop_height, layouter)?;
-        }
+        config: &ZKIMGCircuitConfig<Fp>,
+        layouter: &mut impl Layouter<Fp>,
+    ) -> Result<[AssignedCell<Fp, Fp>; 3], Error> {
+        config.bytes.load(layouter)?;
+        let commitment_chip = ImageCommitmentChip::construct(config.commitment_config.clone());
 
-        Ok(result)
+        // Load the private input image
+        let input_image = self.load_image(config, layouter)?;
+
+        // Hash input image (for privacy)
+        let input_hash = commitment_chip.commit(layouter.namespace(|| "input commitment"), &input_image)?;
+
+        // Apply transformations
+        let (transformed_image, chain) = self.apply_transformations(config, layouter, &input_image)?;
+
+        // Hash output image (for privacy)
+        let output_hash =
+            commitment_chip.commit(layouter.namespace(|| "output commitment"), &transformed_image)?;
+
+        let chain_digest = ChainDigestChip::construct(config.poseidon_config.clone(), config.pixels[0])
+            .digest(layouter.namespace(|| "chain digest"), &chain)?;
+
+        Ok([input_hash, output_hash, chain_digest])
+    }
+
+    pub(crate) fn dimensions(&self) -> (usize, usize) {
+        let height = self.image_pixels.len();
+        let width = self.image_pixels.first().map(|row| row.len()).unwrap_or(0);
+        (width, height)
     }
 
-    /// Apply crop transformation in circuit
-    fn apply_crop(
+    /// Assign every input pixel to the pixel columns
+    pub(crate) fn load_image(
         &self,
-        crop_x: F,
-        crop_y: F,
-        crop_width: F,
-        crop_height: F,
-        layouter: &mut impl Layouter<F>,
-    ) -> Result<Vec<Vec<Vec<F>>>, Error> {
-        // Convert field elements to usable values
-        // In practice, these would be constrained to valid ranges
-        let crop_x_u32 = self.field_to_u32(crop_x);
-        let crop_y_u32 = self.field_to_u32(crop_y);
-        let crop_width_u32 = self.field_to_u32(crop_width);
-        let crop_height_u32 = self.field_to_u32(crop_height);
-
-        // Validate crop bounds (would add constraints in real implementation)
-        if crop_x_u32 + crop_width_u32 > self.image_pixels[0].len() as u32 ||
-           crop_y_u32 + crop_height_u32 > self.image_pixels.len() as u32 {
-            return Err(Error::Synthesis);
+        config: &ZKIMGCircuitConfig<Fp>,
+        layouter: &mut impl Layouter<Fp>,
+    ) -> Result<AssignedImage, Error> {
+        let (width, height) = self.dimensions();
+
+        let pixels = layouter.assign_region(
+            || "load image",
+            |mut region| {
+                let mut pixels = Vec::with_capacity(width * height);
+                for (y, row) in self.image_pixels.iter().enumerate() {
+                    for (x, pixel) in row.iter().enumerate() {
+                        let offset = y * width + x;
+                        let mut assign = |c: usize| {
+                            region.assign_advice(
+                                || format!("pixel ({}, {})[{}]", x, y, c),
+                                config.pixels[c],
+                                offset,
+                                || Value::known(pixel[c]),
+                            )
+                        };
+                        pixels.push([assign(0)?, assign(1)?, assign(2)?]);
+                    }
+                }
+                Ok(pixels)
+            },
+        )?;
+
+        Ok(AssignedImage { width, height, pixels })
+    }
+
+    /// Apply image transformations in circuit, returning the output image
+    /// and the cells of the encoded chain
+    pub(crate) fn apply_transformations(
+        &self,
+        config: &ZKIMGCircuitConfig<Fp>,
+        layouter: &mut impl Layouter<Fp>,
+        image: &AssignedImage,
+    ) -> Result<(AssignedImage, Vec<AssignedCell<Fp, Fp>>), Error> {
+        let mut image = image.clone();
+        let mut chain = Vec::new();
+
+        for (step, transformation) in self.transformations.iter().enumerate() {
+            let mut layouter = layouter.namespace(|| format!("step {}: {:?}", step, transformation));
+            let (next, params) = Self::apply_step(config, layouter.namespace(|| "apply"), &image, transformation)?;
+            chain.extend(Self::assign_encoding(config, layouter.namespace(|| "encode"), transformation, &params)?);
+            image = next;
         }
 
-        // Extract cropped region
-        let mut cropped = Vec::new();
-        for y in crop_y_u32..(crop_y_u32 + crop_height_u32) {
-            let mut row = Vec::new();
-            for x in crop_x_u32..(crop_x_u32 + crop_width_u32) {
-                let pixel = self.image_pixels[y as usize][x as usize].clone();
-                row.push(pixel);
+        Ok((image, chain))
+    }
+
+    /// Lay out the chip for one transformation, returning its output and the
+    /// parameter cells the chip assigned, in encoding order
+    fn apply_step(
+        config: &ZKIMGCircuitConfig<Fp>,
+        layouter: impl Layouter<Fp>,
+        image: &AssignedImage,
+        transformation: &Transformation,
+    ) -> Result<(AssignedImage, Vec<AssignedCell<Fp, Fp>>), Error> {
+        let crop = CropChip::construct(config.crop_config.clone());
+        let resize = ResizeChip::construct(config.resize_config.clone());
+        let color = ColorChip::construct(config.linear_config.clone());
+        let convolution = ConvolutionChip::construct(config.linear_config.clone());
+        let adjust = AdjustChip::construct(config.adjust_config.clone());
+        let fused = FusedChip::construct(config.fused_config.clone());
+        let orientation = OrientationChip::construct();
+
+        let window = |x: u32, y: u32, width: u32, height: u32| CropWindow {
+            x: x as usize,
+            y: y as usize,
+            width: width as usize,
+            height: height as usize,
+        };
+
+        let output = match transformation {
+            Transformation::Crop { x, y, width, height } => {
+                let (cropped, params) = crop.crop(layouter, image, window(*x, *y, *width, *height))?;
+                return Ok((cropped, params.to_vec()));
             }
-            cropped.push(row);
-        }
+            Transformation::Resize { width, height, filter } => {
+                resize.resize(layouter, image, *width as usize, *height as usize, *filter)?
+            }
+            Transformation::Rotate { degrees } => {
+                let angle = RightAngle::from_degrees(*degrees).map_err(|_| Error::Synthesis)?;
+                orientation.rotate(image, angle)
+            }
+            Transformation::FlipHorizontal => orientation.flip_horizontal(image),
+            Transformation::FlipVertical => orientation.flip_vertical(image),
+            Transformation::Translate { dx, dy, fill } => {
+                let chip = TranslateChip::construct(config.translate_config.clone());
+                let (translated, params) = chip.translate(layouter, image, *dx, *dy, *fill)?;
+                return Ok((translated, params.to_vec()));
+            }
+            Transformation::ToYCbCr => color.rgb_to_ycbcr(layouter, image)?,
+            Transformation::ToRGB => color.ycbcr_to_rgb(layouter, image)?,
+            Transformation::Grayscale => color.grayscale(layouter, image)?,
+            Transformation::Sharpen => convolution.convolve(layouter, image, &ConvolutionKernel::SHARPEN)?,
+            Transformation::Blur => convolution.convolve(layouter, image, &ConvolutionKernel::BLUR)?,
+            Transformation::Convolve(kernel) => convolution.convolve(layouter, image, kernel)?,
+            Transformation::Contrast(contrast) => adjust.contrast(layouter, image, *contrast)?,
+            Transformation::Brightness(brightness) => adjust.brightness(layouter, image, *brightness)?,
+            Transformation::WhiteBalance => adjust.white_balance(layouter, image)?,
+            Transformation::CropResize {
+                crop_x,
+                crop_y,
+                crop_width,
+                crop_height,
+                resize_width,
+                resize_height,
+                filter,
+            } => {
+                let window = window(*crop_x, *crop_y, *crop_width, *crop_height);
+                let size = (*resize_width as usize, *resize_height as usize);
+                let (resized, params) = fused.crop_resize(layouter, image, window, size, *filter)?;
+                return Ok((resized, params.to_vec()));
+            }
+            Transformation::GrayscaleContrast { contrast } => fused.grayscale_contrast(layouter, image, *contrast)?,
+            Transformation::ToneMap(tone) => adjust.tone_map(layouter, image, tone)?,
+        };
 
-        Ok(cropped)
+        Ok((output, vec![]))
     }
 
-    /// Convert field element to u32 (simplified - real implementation would constrain properly)
-    fn field_to_u32(&self, value: F) -> u32 {
-        // This is a placeholder - real implementation would use proper field arithmetic
-        // and constraints to ensure valid conversions
-        let bytes = value.to_bytes();
-        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    /// Assign `transformation.encode()` from constants, tying the parameters
+    /// the chip assigned to their slots right after the tag
+    fn assign_encoding(
+        config: &ZKIMGCircuitConfig<Fp>,
+        mut layouter: impl Layouter<Fp>,
+        transformation: &Transformation,
+        params: &[AssignedCell<Fp, Fp>],
+    ) -> Result<Vec<AssignedCell<Fp, Fp>>, Error> {
+        let encoded = transformation.encode().map_err(|_| Error::Synthesis)?;
+        if params.len() >= encoded.len() {
+            return Err(Error::Synthesis);
+        }
+
+        layouter.assign_region(
+            || "transformation encoding",
+            |mut region| {
+                let mut cells = Vec::with_capacity(encoded.len());
+                for (row, &value) in encoded.iter().enumerate() {
+                    let cell = region.assign_advice_from_constant(|| "encoding", config.pixels[0], row, value)?;
+                    if let Some(param) = row.checked_sub(1).and_then(|i| params.get(i)) {
+                        region.constrain_equal(cell.cell(), param.cell())?;
+                    }
+                    cells.push(cell);
+                }
+                Ok(cells)
+            },
+        )
     }
 }
 
 /// Optimized circuit for fused operations (as described in paper)
+///
+/// Proves a chain made only of fused operations with their dedicated gates
+/// (see `chips::fused`). It shares `ZKIMGCircuit`'s configuration and public
+/// inputs, so its proofs verify exactly like those of the equivalent
+/// `Transformation` chain.
 #[derive(Clone)]
-pub struct FusedOperationCircuit<F: FieldExt> {
-    pub operations: Vec<FusedOperation<F>>,
+pub struct FusedOperationCircuit<F: Field> {
+    pub image_pixels: Vec<Vec<Vec<F>>>, // [height][width][3] RGB values
+    pub operations: Vec<FusedOperation>,
+    pub input_hash: F,
+    pub output_hash: F,
     pub _marker: PhantomData<F>,
 }
 
-#[derive(Clone)]
-pub enum FusedOperation<F: FieldExt> {
+#[derive(Clone, Debug, PartialEq)]
+pub enum FusedOperation {
     CropResize {
-        crop_x: F,
-        crop_y: F,
-        crop_w: F,
-        crop_h: F,
-        resize_w: F,
-        resize_h: F,
+        crop_x: u32,
+        crop_y: u32,
+        crop_w: u32,
+        crop_h: u32,
+        resize_w: u32,
+        resize_h: u32,
+        filter: ResizeFilter,
     },
     GrayscaleContrast {
-        contrast: F,
+        contrast: Rational,
     },
     // Add more fused operations as described in paper
 }
 
+impl FusedOperation {
+    /// The fused operation a transformation stands for, if it is one
+    pub fn from_transformation(transformation: &Transformation) -> Option<Self> {
+        match *transformation {
+            Transformation::CropResize {
+                crop_x,
+                crop_y,
+                crop_width,
+                crop_height,
+                resize_width,
+                resize_height,
+                filter,
+            } => Some(Self::CropResize {
+                crop_x,
+                crop_y,
+                crop_w: crop_width,
+                crop_h: crop_height,
+                resize_w: resize_width,
+                resize_h: resize_height,
+                filter,
+            }),
+            Transformation::GrayscaleContrast { contrast } => Some(Self::GrayscaleContrast { contrast }),
+            _ => None,
+        }
+    }
+
+    pub fn to_transformation(&self) -> Transformation {
+        match *self {
+            Self::CropResize {
+                crop_x,
+                crop_y,
+                crop_w,
+                crop_h,
+                resize_w,
+                resize_h,
+                filter,
+            } => Transformation::CropResize {
+                crop_x,
+                crop_y,
+                crop_width: crop_w,
+                crop_height: crop_h,
+                resize_width: resize_w,
+                resize_height: resize_h,
+                filter,
+            },
+            Self::GrayscaleContrast { contrast } => Transformation::GrayscaleContrast { contrast },
+        }
+    }
+
+    /// The chain of unfused transformations this operation replaces
+    pub fn unfused(&self) -> Vec<Transformation> {
+        match *self {
+            Self::CropResize {
+                crop_x,
+                crop_y,
+                crop_w,
+                crop_h,
+                resize_w,
+                resize_h,
+                filter,
+            } => vec![
+                Transformation::Crop {
+                    x: crop_x,
+                    y: crop_y,
+                    width: crop_w,
+                    height: crop_h,
+                },
+                Transformation::Resize {
+                    width: resize_w,
+                    height: resize_h,
+                    filter,
+                },
+            ],
+            Self::GrayscaleContrast { contrast } => {
+                vec![Transformation::Grayscale, Transformation::Contrast(contrast)]
+            }
+        }
+    }
+}
+
+impl Circuit<Fp> for FusedOperationCircuit<Fp> {
+    type Config = ZKIMGCircuitConfig<Fp>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        let (width, height) = self.pipeline().dimensions();
+        Self::blank(width, height, self.operations.clone())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        ZKIMGCircuit::configure(meta)
+    }
+
+    fn synthesize(&self, config: Self::Config, layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        self.pipeline().synthesize(config, layouter)
+    }
+}
+
+impl ConstantColumns for FusedOperationCircuit<Fp> {
+    fn constant_columns(config: &Self::Config) -> Vec<Column<Fixed>> {
+        vec![config.constants]
+    }
+}
+
+impl KeyedCircuit for FusedOperationCircuit<Fp> {
+    fn circuit_id(&self) -> &'static str {
+        "zkimg-fused"
+    }
+
+    fn key_shape(&self) -> Result<KeyShape> {
+        self.pipeline().key_shape()
+    }
+}
+
+impl FusedOperationCircuit<Fp> {
+    pub fn new(image_pixels: Vec<Vec<Vec<Fp>>>, operations: Vec<FusedOperation>) -> Result<Self> {
+        let transformations = operations.iter().map(FusedOperation::to_transformation).collect();
+        let pipeline = ZKIMGCircuit::new(image_pixels, transformations)?;
+
+        Ok(Self {
+            image_pixels: pipeline.image_pixels,
+            operations,
+            input_hash: pipeline.input_hash,
+            output_hash: pipeline.output_hash,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn blank(width: usize, height: usize, operations: Vec<FusedOperation>) -> Self {
+        Self {
+            image_pixels: vec![vec![vec![Fp::zero(); 3]; width]; height],
+            operations,
+            input_hash: Fp::zero(),
+            output_hash: Fp::zero(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Public inputs in instance-column order: [input hash, output hash, chain digest]
+    pub fn public_inputs(&self) -> Result<Vec<Fp>> {
+        self.pipeline().public_inputs()
+    }
+
+    /// The same operations written as the unfused transformation chain
+    pub fn unfused_chain(&self) -> Vec<Transformation> {
+        self.operations.iter().flat_map(FusedOperation::unfused).collect()
+    }
+
+    /// Layout cost of the fused circuit and of the equivalent unfused chain
+    pub fn cost_comparison(&self) -> Result<(CircuitCost, CircuitCost)> {
+        let (width, height) = self.pipeline().dimensions();
+        let fused = CircuitCost::measure(self)?;
+        let unfused = ZKIMGCircuit::blank(width, height, self.unfused_chain()).cost()?;
+        Ok((fused, unfused))
+    }
+
+    fn pipeline(&self) -> ZKIMGCircuit<Fp> {
+        ZKIMGCircuit {
+            image_pixels: self.image_pixels.clone(),
+            transformations: self.operations.iter().map(FusedOperation::to_transformation).collect(),
+            input_hash: self.input_hash,
+            output_hash: self.output_hash,
+            _marker: PhantomData,
+        }
+    }
+}
+
 /// Circuit for HD images using tiling approach
-pub struct HDImageCircuit<F: FieldExt> {
-    pub tiles: Vec<Vec<Vec<Vec<F>>>>, // [tile_y][tile_x][height][width][3]
-    pub tile_size: usize,
+///
+/// Each tile of `layout` is proven by its own `ZKIMGCircuit` (see `tiling`),
+/// and the image is committed by the Merkle roots of the tile digests.
+#[derive(Clone)]
+pub struct HDImageCircuit<F: Field> {
+    pub image_pixels: Vec<Vec<Vec<F>>>, // [height][width][3] RGB values
+    pub transformations: Vec<Transformation>,
+    pub layout: TileLayout,
     pub _marker: PhantomData<F>,
 }
 
-impl<F: FieldExt> HDImageCircuit<F> {
+impl HDImageCircuit<Fp> {
+    /// Tile the image for `transformations`, failing rather than dropping
+    /// tiles when it needs more than `max_tiles`
+    pub fn new(
+        image_pixels: Vec<Vec<Vec<Fp>>>,
+        transformations: Vec<Transformation>,
+        tile_size: u32,
+        max_tiles: usize,
+    ) -> Result<Self> {
+        let height = image_pixels.len() as u32;
+        let width = image_pixels.first().map(|row| row.len()).unwrap_or(0) as u32;
+        let layout = TileLayout::for_chain(width, height, tile_size, max_tiles, &transformations)?;
+
+        Ok(Self {
+            image_pixels,
+            transformations,
+            layout,
+            _marker: PhantomData,
+        })
+    }
+
     /// Process HD image by proving tiles independently
-    pub fn process_hd_tiles(&self) -> Vec<ZKIMGCircuit<F>> {
-        // Implementation would create separate circuits for each tile
-        // as described in Section 8.1 of the paper
-        vec![] // Placeholder
+    ///
+    /// Returns one circuit per tile, in `layout.tiles` order.
+    pub fn process_hd_tiles(&self) -> Result<Vec<ZKIMGCircuit<Fp>>> {
+        self.layout
+            .tiles
+            .iter()
+            .map(|tile| ZKIMGCircuit::new(tile.input.extract(&self.image_pixels), tile.chain(&self.transformations)))
+            .collect()
+    }
+
+    /// Public commitments of the tiled proof: [input root, output root, chain digest]
+    ///
+    /// The chain digest is that of the untiled chain; each tile proof exposes
+    /// the digest of its own `Tile::chain`.
+    pub fn public_inputs(&self, tiles: &[ZKIMGCircuit<Fp>]) -> Result<Vec<Fp>> {
+        let inputs: Vec<_> = tiles.iter().map(|tile| tile.input_hash).collect();
+        let outputs: Vec<_> = tiles.iter().map(|tile| tile.output_hash).collect();
+
+        Ok(vec![
+            self.layout.commitment(&inputs)?,
+            self.layout.commitment(&outputs)?,
+            Transformation::chain_digest(&self.transformations)?,
+        ])
     }
 }
 
 /// Performance-optimized circuit using operation fusion
-pub struct OptimizedZKIMGCircuit<F: FieldExt> {
-    pub fused_operations: Vec<FusedOperation<F>>,
+pub struct OptimizedZKIMGCircuit<F: Field> {
+    pub fused_operations: Vec<FusedOperation>,
     pub image_chunks: Vec<Vec<F>>, // Chunked image data for efficiency
     pub _marker: PhantomData<F>,
 }
@@ -256,3 +686,184 @@ pub struct OptimizedZKIMGCircuit<F: FieldExt> {
 // - Operation packing
 // - Constraint sharing
 // - Efficient field arithmetic
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::{filters, physical, Rational, ResizeFilter, ToneMap};
+    use crate::poseidon_image_hash;
+    use halo2_proofs::dev::MockProver;
+    use image::{DynamicImage, RgbImage};
+
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 41 + y * 3) as u8, (y * 67 + 20) as u8, ((x + y) * 23) as u8])
+        }))
+    }
+
+    fn prove(circuit: &ZKIMGCircuit<Fp>, public_inputs: Vec<Fp>) -> bool {
+        MockProver::run(12, circuit, vec![public_inputs]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn chain_matches_native_steps() {
+        let image = test_image(6, 5);
+        let chain = vec![
+            Transformation::Crop { x: 1, y: 1, width: 4, height: 3 },
+            Transformation::Resize { width: 5, height: 4, filter: ResizeFilter::Bilinear },
+            Transformation::Grayscale,
+            Transformation::Sharpen,
+        ];
+        let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), chain).unwrap();
+
+        let cropped = physical::crop(&image, 1, 1, 4, 3);
+        let resized = physical::resize(&cropped, 5, 4, ResizeFilter::Bilinear);
+        let expected = filters::sharpen(&filters::grayscale(&resized));
+        assert_eq!(circuit.output_hash, poseidon_image_hash(&expected));
+
+        assert!(prove(&circuit, circuit.public_inputs().unwrap()));
+    }
+
+    #[test]
+    fn every_transformation_has_a_chip() {
+        let image = test_image(4, 3);
+        let contrast = Rational::new(3, 2).unwrap();
+        let chains = [
+            vec![Transformation::Rotate { degrees: 90.0 }, Transformation::FlipHorizontal],
+            vec![Transformation::FlipVertical, Transformation::Translate { dx: 1, dy: -1, fill: [9, 8, 7] }],
+            vec![Transformation::ToYCbCr, Transformation::ToRGB],
+            vec![Transformation::Blur, Transformation::Convolve(ConvolutionKernel::SHARPEN)],
+            vec![Transformation::Contrast(contrast), Transformation::Brightness(Rational::new(-1, 4).unwrap())],
+            vec![Transformation::WhiteBalance],
+            vec![Transformation::ToneMap(ToneMap {
+                shift: 12,
+                contrast,
+                offset: -30,
+            })],
+            vec![Transformation::Resize { width: 2, height: 5, filter: ResizeFilter::Nearest }],
+            vec![
+                Transformation::CropResize {
+                    crop_x: 1,
+                    crop_y: 0,
+                    crop_width: 3,
+                    crop_height: 2,
+                    resize_width: 4,
+                    resize_height: 4,
+                    filter: ResizeFilter::Bilinear,
+                },
+                Transformation::GrayscaleContrast { contrast },
+            ],
+        ];
+
+        for chain in chains {
+            let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), chain.clone()).unwrap();
+            assert!(prove(&circuit, circuit.public_inputs().unwrap()), "{:?}", chain);
+        }
+    }
+
+    #[test]
+    fn public_chain_must_match_circuit() {
+        let image = test_image(4, 4);
+        let chain = vec![
+            Transformation::Crop { x: 0, y: 1, width: 3, height: 3 },
+            Transformation::Resize { width: 2, height: 2, filter: ResizeFilter::Nearest },
+        ];
+        let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), chain.clone()).unwrap();
+        let public_inputs = circuit.public_inputs().unwrap();
+
+        // Claim a different resize width
+        let mut claimed = chain;
+        claimed[1] = Transformation::Resize { width: 3, height: 2, filter: ResizeFilter::Nearest };
+        let mut tampered = public_inputs.clone();
+        tampered[2] = Transformation::chain_digest(&claimed).unwrap();
+        assert!(!prove(&circuit, tampered));
+
+        // Claim an output the chain did not produce
+        let mut tampered = public_inputs;
+        tampered[1] = poseidon_image_hash(&image);
+        assert!(!prove(&circuit, tampered));
+    }
+
+    #[test]
+    fn crop_outside_image_is_an_error() {
+        let image = test_image(4, 4);
+        let crop = Transformation::Crop { x: 3, y: 0, width: 2, height: 1 };
+        assert!(ZKIMGCircuit::new(image_to_field_elements(&image), vec![crop]).is_err());
+    }
+
+    fn fused_operations() -> Vec<FusedOperation> {
+        vec![
+            FusedOperation::CropResize {
+                crop_x: 1,
+                crop_y: 0,
+                crop_w: 4,
+                crop_h: 4,
+                resize_w: 3,
+                resize_h: 3,
+                filter: ResizeFilter::Bilinear,
+            },
+            FusedOperation::GrayscaleContrast { contrast: Rational::new(3, 2).unwrap() },
+        ]
+    }
+
+    #[test]
+    fn fused_circuit_proves_the_unfused_chain() {
+        let image = test_image(6, 5);
+        let circuit = FusedOperationCircuit::new(image_to_field_elements(&image), fused_operations()).unwrap();
+        let public_inputs = circuit.public_inputs().unwrap();
+        assert!(MockProver::run(12, &circuit, vec![public_inputs.clone()]).unwrap().verify().is_ok());
+
+        // Same output as proving the unfused chain, under the fused chain digest
+        let unfused = ZKIMGCircuit::new(image_to_field_elements(&image), circuit.unfused_chain()).unwrap();
+        let unfused_inputs = unfused.public_inputs().unwrap();
+        assert_eq!(public_inputs[..2], unfused_inputs[..2]);
+        assert_ne!(public_inputs[2], unfused_inputs[2]);
+    }
+
+    #[test]
+    fn fused_operations_cost_less_than_unfused_chain() {
+        for operation in fused_operations() {
+            let circuit = FusedOperationCircuit::blank(6, 5, vec![operation.clone()]);
+            let (fused, unfused) = circuit.cost_comparison().unwrap();
+            assert!(fused.advice_cells < unfused.advice_cells, "{:?}: {:?} vs {:?}", operation, fused, unfused);
+            assert!(fused.gate_rows < unfused.gate_rows, "{:?}: {:?} vs {:?}", operation, fused, unfused);
+        }
+    }
+
+    #[test]
+    fn tiles_reassemble_the_full_output() {
+        let image = test_image(7, 5);
+        let chain = vec![
+            Transformation::Blur,
+            Transformation::Grayscale,
+            Transformation::Sharpen,
+        ];
+        let hd = HDImageCircuit::new(image_to_field_elements(&image), chain.clone(), 3, 6).unwrap();
+        assert_eq!(hd.layout.halo, 2);
+
+        let expected = chain.iter().try_fold(image.clone(), |image, step| step.apply(&image)).unwrap();
+        let expected = image_to_field_elements(&expected);
+
+        let tiles = hd.process_hd_tiles().unwrap();
+        for (tile, circuit) in hd.layout.tiles.iter().zip(&tiles) {
+            assert_eq!(circuit.output_hash, ZKIMGCircuit::native_image_hash(&tile.core.extract(&expected)));
+            assert!(prove(circuit, circuit.public_inputs().unwrap()), "{:?}", tile);
+        }
+
+        let public_inputs = hd.public_inputs(&tiles).unwrap();
+        let outputs: Vec<_> = hd
+            .layout
+            .tiles
+            .iter()
+            .map(|tile| ZKIMGCircuit::native_image_hash(&tile.core.extract(&expected)))
+            .collect();
+        assert_eq!(public_inputs[1], hd.layout.commitment(&outputs).unwrap());
+    }
+
+    #[test]
+    fn tiling_refuses_to_truncate() {
+        let image = test_image(7, 5);
+        assert!(HDImageCircuit::new(image_to_field_elements(&image), vec![], 3, 5).is_err());
+        assert!(HDImageCircuit::new(image_to_field_elements(&image), vec![Transformation::WhiteBalance], 3, 6).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Layout cost of a circuit, measured without proving
+//!
+//! Runs the circuit's floor planner against an `Assignment` that only records
+//! where cells land, like keygen does, so it is cheap enough to compare
+//! transformation chains before committing to one.
+
+use halo2_proofs::{
+    circuit::Value,
+    pasta::Fp,
+    plonk::{
+        Advice, Any, Assigned, Assignment, Circuit, Column, ConstraintSystem, Error, FloorPlanner, Fixed,
+        Instance, Selector,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// Resources a circuit's layout uses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitCost {
+    /// Rows used, including lookup tables
+    pub rows: usize,
+    /// Selector enablements, i.e. rows on which a gate or lookup is active
+    pub gate_rows: usize,
+    pub advice_cells: usize,
+    /// Copy constraints in the permutation argument
+    pub copies: usize,
+    /// Smallest `k` whose `2^k` rows fit the layout and the blinding rows
+    pub min_k: u32,
+}
+
+/// Circuits that can report the fixed columns they enabled for constants,
+/// which the floor planner needs and `ConstraintSystem` does not expose
+pub trait ConstantColumns: Circuit<Fp> {
+    fn constant_columns(config: &Self::Config) -> Vec<Column<Fixed>>;
+}
+
+impl CircuitCost {
+    pub fn measure<C: ConstantColumns>(circuit: &C) -> Result<Self, Error> {
+        let mut cs = ConstraintSystem::default();
+        let config = C::configure(&mut cs);
+        let constants = C::constant_columns(&config);
+
+        let mut recorder = CostRecorder::default();
+        C::FloorPlanner::synthesize(&mut recorder, circuit, config, constants)?;
+
+        let mut cost = recorder.cost;
+        let needed = (cost.rows + cs.blinding_factors() + 1).max(cs.minimum_rows());
+        cost.min_k = needed.next_power_of_two().trailing_zeros();
+        Ok(cost)
+    }
+}
+
+#[derive(Default)]
+struct CostRecorder {
+    cost: CircuitCost,
+}
+
+impl CostRecorder {
+    fn touch(&mut self, row: usize) {
+        self.cost.rows = self.cost.rows.max(row + 1);
+    }
+}
+
+impl Assignment<Fp> for CostRecorder {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(&mut self, _: A, _: &Selector, row: usize) -> Result<(), Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.cost.gate_rows += 1;
+        self.touch(row);
+        Ok(())
+    }
+
+    fn query_instance(&self, _: Column<Instance>, _: usize) -> Result<Value<Fp>, Error> {
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(&mut self, _: A, _: Column<Advice>, row: usize, _: V) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.cost.advice_cells += 1;
+        self.touch(row);
+        Ok(())
+    }
+
+    fn assign_fixed<V, VR, A, AR>(&mut self, _: A, _: Column<Fixed>, row: usize, _: V) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.touch(row);
+        Ok(())
+    }
+
+    fn copy(&mut self, _: Column<Any>, _: usize, _: Column<Any>, _: usize) -> Result<(), Error> {
+        self.cost.copies += 1;
+        Ok(())
+    }
+
+    fn fill_from_row(&mut self, _: Column<Fixed>, _: usize, _: Value<Assigned<Fp>>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
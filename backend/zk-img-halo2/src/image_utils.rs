@@ -3,8 +3,11 @@
 //! Helper functions for image processing and conversion
 
 use image::{DynamicImage, GenericImageView, Pixel, RgbImage};
+use ff::PrimeField;
 use halo2_proofs::pasta::Fp;
 
+use crate::chips::commitment::native_image_commitment;
+
 /// Convert RGB pixel values to field elements
 pub fn rgb_to_field(r: u8, g: u8, b: u8) -> [Fp; 3] {
     [
@@ -17,9 +20,9 @@ pub fn rgb_to_field(r: u8, g: u8, b: u8) -> [Fp; 3] {
 /// Convert field elements back to RGB values
 pub fn field_to_rgb(fields: &[Fp; 3]) -> [u8; 3] {
     [
-        (fields[0].get_lower_32() & 0xFF) as u8,
-        (fields[1].get_lower_32() & 0xFF) as u8,
-        (fields[2].get_lower_32() & 0xFF) as u8,
+        fields[0].to_repr()[0],
+        fields[1].to_repr()[0],
+        fields[2].to_repr()[0],
     ]
 }
 
@@ -49,7 +52,7 @@ pub fn field_matrix_to_image(matrix: &[Vec<[Fp; 3]>]) -> DynamicImage {
 
     let height = matrix.len();
     let width = matrix[0].len();
-    let mut image = DynamicImage::new_rgb8(width as u32, height as u32);
+    let mut image = RgbImage::new(width as u32, height as u32);
 
     for (y, row) in matrix.iter().enumerate() {
         for (x, pixel) in row.iter().enumerate() {
@@ -58,7 +61,7 @@ pub fn field_matrix_to_image(matrix: &[Vec<[Fp; 3]>]) -> DynamicImage {
         }
     }
 
-    image
+    DynamicImage::ImageRgb8(image)
 }
 
 /// Chunk image into smaller pieces for efficient processing
@@ -83,30 +86,32 @@ pub fn chunk_image(image: &DynamicImage, chunk_size: usize) -> Vec<Vec<Vec<[Fp;
     chunks
 }
 
-/// Calculate image hash using Poseidon-friendly method
-pub fn calculate_poseidon_image_hash(image: &DynamicImage, sample_size: usize) -> Vec<u8> {
-    let matrix = image_to_field_matrix(image);
-    let mut hash_input = Vec::new();
-
-    // Sample pixels for hashing (to keep hash input reasonable)
-    let step_y = std::cmp::max(1, matrix.len() / sample_size);
-    let step_x = std::cmp::max(1, matrix[0].len() / sample_size);
-
-    for y in (0..matrix.len()).step_by(step_y) {
-        for x in (0..matrix[y].len()).step_by(step_x) {
-            let pixel = &matrix[y][x];
-            // Convert field elements to bytes for hashing
-            hash_input.extend_from_slice(&pixel[0].to_bytes());
-            hash_input.extend_from_slice(&pixel[1].to_bytes());
-            hash_input.extend_from_slice(&pixel[2].to_bytes());
-        }
-    }
+/// Poseidon commitment to every pixel of `image`
+///
+/// Uses the same channel packing and Merkle tree shape as
+/// `ImageCommitmentChip`, so the result can be used directly as the circuit's
+/// input/output public input without running the prover.
+pub fn poseidon_image_hash(image: &DynamicImage) -> Fp {
+    let rgb = image.to_rgb8();
+    let channels: Vec<Fp> = rgb.as_raw().iter().map(|&c| Fp::from(c as u64)).collect();
+
+    native_image_commitment(rgb.width() as usize, rgb.height() as usize, &channels)
+}
+
+/// Calculate image hash using Poseidon, in its canonical byte encoding
+pub fn calculate_poseidon_image_hash(image: &DynamicImage) -> Vec<u8> {
+    image_hash_to_bytes(poseidon_image_hash(image))
+}
 
-    // Use SHA256 as placeholder for Poseidon hash
-    use sha2::{Digest, Sha256};
-    let mut hasher = Sha256::new();
-    hasher.update(&hash_input);
-    hasher.finalize().to_vec()
+/// Canonical encoding of an image hash: the 32-byte little-endian field representation
+pub fn image_hash_to_bytes(hash: Fp) -> Vec<u8> {
+    hash.to_repr().to_vec()
+}
+
+/// Decode an image hash, rejecting non-canonical encodings
+pub fn image_hash_from_bytes(bytes: &[u8]) -> Option<Fp> {
+    let repr: [u8; 32] = bytes.try_into().ok()?;
+    Fp::from_repr(repr).into()
 }
 
 /// Image quality metrics for verification
@@ -162,3 +167,81 @@ pub fn validate_image_for_zk(image: &DynamicImage, max_size: usize) -> Result<()
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::{image_to_field_elements, physical};
+    use crate::{Transformation, ZKIMGCircuit};
+    use halo2_proofs::dev::MockProver;
+
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 37 + y) as u8, (y * 53) as u8, (x ^ y) as u8 * 7])
+        }))
+    }
+
+    #[test]
+    fn native_hash_matches_circuit_digest() {
+        // 7x5 spans several packed words and an unbalanced Merkle tree
+        let image = test_image(7, 5);
+        let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), vec![]).unwrap();
+        let hash = poseidon_image_hash(&image);
+
+        let no_chain = Transformation::chain_digest(&[]).unwrap();
+
+        let prover = MockProver::run(10, &circuit, vec![vec![hash, hash, no_chain]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn native_hash_matches_cropped_circuit_output() {
+        let image = test_image(6, 6);
+        let crop = Transformation::Crop { x: 1, y: 2, width: 4, height: 3 };
+        let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), vec![crop.clone()]).unwrap();
+
+        let cropped = physical::crop(&image, 1, 2, 4, 3);
+        let mut public_inputs = vec![poseidon_image_hash(&image), poseidon_image_hash(&cropped)];
+        public_inputs.push(Transformation::chain_digest(&[crop]).unwrap());
+
+        let prover = MockProver::run(10, &circuit, vec![public_inputs]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn circuit_rejects_hash_of_different_image() {
+        let image = test_image(6, 6);
+        let mut altered = image.to_rgb8();
+        altered.get_pixel_mut(5, 5)[1] ^= 1;
+        let altered = DynamicImage::ImageRgb8(altered);
+
+        let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), vec![]).unwrap();
+        let hash = poseidon_image_hash(&altered);
+
+        assert_ne!(hash, poseidon_image_hash(&image));
+        let no_chain = Transformation::chain_digest(&[]).unwrap();
+        let prover = MockProver::run(10, &circuit, vec![vec![hash, hash, no_chain]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn hash_depends_on_dimensions() {
+        // Same channel stream, different shape
+        let wide = test_image(4, 1);
+        let tall = DynamicImage::ImageRgb8(
+            RgbImage::from_raw(1, 4, wide.to_rgb8().into_raw()).unwrap(),
+        );
+
+        assert_ne!(poseidon_image_hash(&wide), poseidon_image_hash(&tall));
+    }
+
+    #[test]
+    fn hash_bytes_round_trip() {
+        let hash = poseidon_image_hash(&test_image(3, 3));
+        let bytes = calculate_poseidon_image_hash(&test_image(3, 3));
+
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(image_hash_from_bytes(&bytes), Some(hash));
+        assert_eq!(image_hash_from_bytes(&[0xff; 32]), None);
+    }
+}
@@ -0,0 +1,553 @@
+//! Key files
+//!
+//! halo2_proofs 0.3 exposes no byte encoding of proving or verifying keys,
+//! so a key file records what the key was generated for instead of the key
+//! itself, in a fixed binary header (integers little-endian):
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 8     | magic `ZKIMGKEY`                               |
+//! | 2     | format version                                 |
+//! | 1     | kind: 0 proving, 1 verifying                   |
+//! | 4     | k                                              |
+//! | 1 + n | circuit identity, length-prefixed UTF-8        |
+//! | 4 + 4 | image width and height                         |
+//! | 32    | chain digest of the transformations            |
+//! | 32    | `vk_fingerprint` of the circuit's verifying key |
+//! | 32    | SHA-256 checksum of everything above           |
+//!
+//! A key file is therefore not a cache: halo2_proofs 0.3.5 cannot serialize
+//! keys, so loading always reruns keygen, and the header is what the
+//! regenerated key is checked against. The key is only returned if the file
+//! was written for the same kind, k, circuit and shape and the circuit has
+//! the recorded fingerprint. Anything else is an error naming the mismatch,
+//! never a key for some other circuit.
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use ff::PrimeField;
+use group::{Curve, GroupEncoding};
+use halo2_proofs::{
+    circuit::Value,
+    dev::CircuitGates,
+    pasta::{EqAffine, Fp},
+    plonk::{
+        Advice, Any, Assigned, Assignment, Column, ConstraintSystem, Error, FloorPlanner, Fixed, Instance, Selector,
+    },
+    poly::{
+        commitment::{Blind, Params},
+        EvaluationDomain,
+    },
+};
+use sha2::{Digest, Sha256};
+
+use crate::cost::ConstantColumns;
+
+const MAGIC: &[u8; 8] = b"ZKIMGKEY";
+
+/// Current key file format version
+pub const KEY_FILE_VERSION: u16 = 2;
+
+/// Version of the verifying key encoding `vk_fingerprint` hashes
+pub const VK_ENCODING_VERSION: u16 = 1;
+
+/// Image size and chain a circuit's keys were generated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyShape {
+    pub width: u32,
+    pub height: u32,
+    pub chain_digest: Fp,
+}
+
+/// Circuits whose keys are determined by their identity and `KeyShape`
+pub trait KeyedCircuit: ConstantColumns {
+    /// Name of the circuit layout, distinct for every circuit type and variant
+    fn circuit_id(&self) -> &'static str;
+
+    fn key_shape(&self) -> Result<KeyShape>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    Proving,
+    Verifying,
+}
+
+/// Header of a key file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyFileHeader {
+    pub kind: KeyKind,
+    pub k: u32,
+    pub circuit_id: String,
+    pub shape: KeyShape,
+    pub fingerprint: [u8; 32],
+}
+
+impl KeyFileHeader {
+    pub fn new<C: KeyedCircuit>(kind: KeyKind, params: &Params<EqAffine>, circuit: &C) -> Result<Self> {
+        Ok(Self {
+            kind,
+            k: params.k(),
+            circuit_id: circuit.circuit_id().to_string(),
+            shape: circuit.key_shape()?,
+            fingerprint: vk_fingerprint(params, circuit)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&KEY_FILE_VERSION.to_le_bytes());
+        bytes.push(match self.kind {
+            KeyKind::Proving => 0,
+            KeyKind::Verifying => 1,
+        });
+        bytes.extend_from_slice(&self.k.to_le_bytes());
+        bytes.push(self.circuit_id.len() as u8);
+        bytes.extend_from_slice(self.circuit_id.as_bytes());
+        bytes.extend_from_slice(&self.shape.width.to_le_bytes());
+        bytes.extend_from_slice(&self.shape.height.to_le_bytes());
+        bytes.extend_from_slice(&self.shape.chain_digest.to_repr());
+        bytes.extend_from_slice(&self.fingerprint);
+
+        let checksum = Sha256::digest(&bytes);
+        bytes.extend_from_slice(&checksum);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 32 || !bytes.starts_with(MAGIC) {
+            return Err(anyhow!("Not a ZK-IMG key file"));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 32);
+        if Sha256::digest(body).as_slice() != checksum {
+            return Err(anyhow!("Key file checksum mismatch: the file is corrupted"));
+        }
+
+        let mut reader = Reader { bytes: body, offset: MAGIC.len() };
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != KEY_FILE_VERSION {
+            return Err(anyhow!(
+                "Key file format version {} is not supported, expected {}",
+                version,
+                KEY_FILE_VERSION
+            ));
+        }
+
+        let kind = match reader.take(1)?[0] {
+            0 => KeyKind::Proving,
+            1 => KeyKind::Verifying,
+            other => return Err(anyhow!("Unknown key kind {}", other)),
+        };
+        let k = u32::from_le_bytes(reader.array()?);
+        let id_length = reader.take(1)?[0] as usize;
+        let circuit_id = String::from_utf8(reader.take(id_length)?.to_vec()).context("Invalid circuit identity")?;
+        let width = u32::from_le_bytes(reader.array()?);
+        let height = u32::from_le_bytes(reader.array()?);
+        let chain_digest = Option::from(Fp::from_repr(reader.array()?))
+            .ok_or_else(|| anyhow!("Invalid chain digest in key file"))?;
+        let fingerprint = reader.array()?;
+        if reader.offset != body.len() {
+            return Err(anyhow!("Trailing bytes in key file header"));
+        }
+
+        Ok(Self {
+            kind,
+            k,
+            circuit_id,
+            shape: KeyShape {
+                width,
+                height,
+                chain_digest,
+            },
+            fingerprint,
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_bytes()).with_context(|| format!("Failed to write key file {}", path))
+    }
+
+    pub fn read(path: &str) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read key file {}", path))?;
+        Self::from_bytes(&bytes).with_context(|| format!("Invalid key file {}", path))
+    }
+
+    /// Fail unless this header was written for a `kind` key of `circuit` at `k`
+    pub fn check<C: KeyedCircuit>(&self, kind: KeyKind, k: u32, circuit: &C) -> Result<()> {
+        if self.kind != kind {
+            return Err(anyhow!("Expected a {:?} key file, found a {:?} key", kind, self.kind));
+        }
+        if self.k != k {
+            return Err(anyhow!("Key was generated for k={}, not k={}", self.k, k));
+        }
+        if self.circuit_id != circuit.circuit_id() {
+            return Err(anyhow!(
+                "Key was generated for circuit {}, not {}",
+                self.circuit_id,
+                circuit.circuit_id()
+            ));
+        }
+
+        let shape = circuit.key_shape()?;
+        if self.shape.width != shape.width || self.shape.height != shape.height {
+            return Err(anyhow!(
+                "Key was generated for {}x{} images, not {}x{}",
+                self.shape.width,
+                self.shape.height,
+                shape.width,
+                shape.height
+            ));
+        }
+        if self.shape.chain_digest != shape.chain_digest {
+            return Err(anyhow!("Key was generated for a different transformation chain"));
+        }
+        Ok(())
+    }
+
+    /// Fail unless `circuit` has the verifying key this header was written for
+    pub fn check_fingerprint<C: KeyedCircuit>(&self, params: &Params<EqAffine>, circuit: &C) -> Result<()> {
+        if vk_fingerprint(params, circuit)? != self.fingerprint {
+            return Err(anyhow!("Circuit's verifying key does not match the key file fingerprint"));
+        }
+        Ok(())
+    }
+}
+
+/// SHA-256 over an explicit encoding of the verifying key keygen derives for
+/// `circuit` with `params`
+///
+/// halo2_proofs 0.3.5 keeps the parts of a `VerifyingKey` private, so they
+/// are derived from the circuit the way keygen derives them. The encoding
+/// (integers little-endian, field elements and points in their canonical
+/// 32-byte form, lists prefixed by their u64 length) is:
+///
+/// 1. `ZKIMG-VK` and `VK_ENCODING_VERSION`,
+/// 2. the domain: k, the extended k and the generators of both domains,
+/// 3. the constraint system: degree, blinding factors, minimum rows and the
+///    gate polynomials as `dev::CircuitGates` lists them,
+/// 4. the commitment to every fixed and selector column, in the order
+///    synthesis first assigns them,
+/// 5. the permutation: for every column with copy constraints, in the order
+///    they are first copied, the commitment to the column mapping each cell
+///    to the next cell of its equality class.
+pub fn vk_fingerprint<C: KeyedCircuit>(params: &Params<EqAffine>, circuit: &C) -> Result<[u8; 32]> {
+    let mut cs = ConstraintSystem::default();
+    let config = C::configure(&mut cs);
+    let constants = C::constant_columns(&config);
+
+    let n = 1usize << params.k();
+    if n < cs.minimum_rows() {
+        return Err(Error::NotEnoughRowsAvailable { current_k: params.k() }.into());
+    }
+    let mut recorder = KeyRecorder::new(n, n - (cs.blinding_factors() + 1));
+    C::FloorPlanner::synthesize(&mut recorder, circuit, config, constants)?;
+
+    let domain = EvaluationDomain::<Fp>::new(cs.degree() as u32, params.k());
+    let gates = CircuitGates::collect::<Fp, C>().to_string();
+    let commit = |values: Vec<Fp>| {
+        let commitment = params.commit_lagrange(&domain.lagrange_from_vec(values), Blind::default());
+        commitment.to_affine().to_bytes()
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"ZKIMG-VK");
+    hasher.update(VK_ENCODING_VERSION.to_le_bytes());
+
+    hasher.update(params.k().to_le_bytes());
+    hasher.update(domain.extended_len().trailing_zeros().to_le_bytes());
+    hasher.update(domain.get_omega().to_repr());
+    hasher.update(domain.get_extended_omega().to_repr());
+
+    for value in [cs.degree(), cs.blinding_factors(), cs.minimum_rows(), gates.len()] {
+        hasher.update((value as u64).to_le_bytes());
+    }
+    hasher.update(gates.as_bytes());
+
+    let permutation = recorder.permutation();
+    for columns in [recorder.fixed, permutation] {
+        hasher.update((columns.len() as u64).to_le_bytes());
+        for column in columns {
+            hasher.update(commit(column));
+        }
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// `Assignment` that records what keygen commits to: the fixed and selector
+/// columns and the copy constraints
+struct KeyRecorder {
+    n: usize,
+    usable_rows: usize,
+    fixed: Vec<Vec<Fp>>,
+    fixed_columns: HashMap<Column<Fixed>, usize>,
+    selectors: HashMap<Selector, usize>,
+    copies: Vec<[(Column<Any>, usize); 2]>,
+}
+
+impl KeyRecorder {
+    fn new(n: usize, usable_rows: usize) -> Self {
+        Self {
+            n,
+            usable_rows,
+            fixed: Vec::new(),
+            fixed_columns: HashMap::new(),
+            selectors: HashMap::new(),
+            copies: Vec::new(),
+        }
+    }
+
+    fn check_row(&self, row: usize) -> Result<(), Error> {
+        if row >= self.usable_rows {
+            return Err(Error::NotEnoughRowsAvailable { current_k: self.n.trailing_zeros() });
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, index: usize, row: usize, value: Value<Assigned<Fp>>) -> Result<(), Error> {
+        self.check_row(row)?;
+        let mut known = None;
+        let _ = value.evaluate().map(|value| known = Some(value));
+        self.fixed[index][row] = known.ok_or(Error::Synthesis)?;
+        Ok(())
+    }
+
+    fn fixed_index(&mut self, column: Column<Fixed>) -> usize {
+        let (fixed, n) = (&mut self.fixed, self.n);
+        *self.fixed_columns.entry(column).or_insert_with(|| {
+            fixed.push(vec![Fp::zero(); n]);
+            fixed.len() - 1
+        })
+    }
+
+    /// Canonical permutation: every copied cell `(column, row)` is numbered
+    /// `column * n + row`, with columns in the order they are first copied,
+    /// and maps to the next number of its equality class, cyclically
+    fn permutation(&self) -> Vec<Vec<Fp>> {
+        let mut columns: Vec<Column<Any>> = Vec::new();
+        let mut parent: HashMap<usize, usize> = HashMap::new();
+        let root = |parent: &mut HashMap<usize, usize>, cell: usize| {
+            let mut root = cell;
+            while let Some(&next) = parent.get(&root).filter(|&&next| next != root) {
+                root = next;
+            }
+            parent.insert(cell, root);
+            root
+        };
+
+        for copy in &self.copies {
+            let [left, right] = copy.map(|(column, row)| {
+                let index = columns.iter().position(|&known| known == column).unwrap_or_else(|| {
+                    columns.push(column);
+                    columns.len() - 1
+                });
+                index * self.n + row
+            });
+            let (left, right) = (root(&mut parent, left), root(&mut parent, right));
+            parent.insert(left.max(right), left.min(right));
+        }
+
+        let mut classes: HashMap<usize, Vec<usize>> = HashMap::new();
+        let cells: Vec<usize> = parent.keys().copied().collect();
+        for cell in cells {
+            classes.entry(root(&mut parent, cell)).or_default().push(cell);
+        }
+
+        let mut mapping: Vec<Vec<Fp>> = (0..columns.len())
+            .map(|column| (0..self.n).map(|row| Fp::from((column * self.n + row) as u64)).collect())
+            .collect();
+        for mut class in classes.into_values() {
+            class.sort_unstable();
+            for (i, &cell) in class.iter().enumerate() {
+                let next = class[(i + 1) % class.len()];
+                mapping[cell / self.n][cell % self.n] = Fp::from(next as u64);
+            }
+        }
+        mapping
+    }
+}
+
+impl Assignment<Fp> for KeyRecorder {
+    fn enter_region<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn exit_region(&mut self) {}
+
+    fn enable_selector<A, AR>(&mut self, _: A, selector: &Selector, row: usize) -> Result<(), Error>
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let (fixed, n) = (&mut self.fixed, self.n);
+        let index = *self.selectors.entry(*selector).or_insert_with(|| {
+            fixed.push(vec![Fp::zero(); n]);
+            fixed.len() - 1
+        });
+        self.set(index, row, Value::known(Fp::one().into()))
+    }
+
+    fn query_instance(&self, _: Column<Instance>, row: usize) -> Result<Value<Fp>, Error> {
+        self.check_row(row)?;
+        Ok(Value::unknown())
+    }
+
+    fn assign_advice<V, VR, A, AR>(&mut self, _: A, _: Column<Advice>, row: usize, _: V) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        self.check_row(row)
+    }
+
+    fn assign_fixed<V, VR, A, AR>(&mut self, _: A, column: Column<Fixed>, row: usize, to: V) -> Result<(), Error>
+    where
+        V: FnOnce() -> Value<VR>,
+        VR: Into<Assigned<Fp>>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.fixed_index(column);
+        self.set(index, row, to().map(|value| value.into()))
+    }
+
+    fn copy(&mut self, left: Column<Any>, left_row: usize, right: Column<Any>, right_row: usize) -> Result<(), Error> {
+        self.check_row(left_row)?;
+        self.check_row(right_row)?;
+        self.copies.push([(left, left_row), (right, right_row)]);
+        Ok(())
+    }
+
+    fn fill_from_row(&mut self, column: Column<Fixed>, from_row: usize, to: Value<Assigned<Fp>>) -> Result<(), Error> {
+        self.check_row(from_row)?;
+        let index = self.fixed_index(column);
+        for row in from_row..self.usable_rows {
+            self.set(index, row, to)?;
+        }
+        Ok(())
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self, _: Option<String>) {}
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.offset + length;
+        let slice = self.bytes.get(self.offset..end).ok_or_else(|| anyhow!("Truncated key file header"))?;
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof_system::ZKIMGProofSystem;
+    use crate::transforms::{image_to_field_elements, Rational};
+    use crate::{Transformation, ZKIMGCircuit};
+    use image::{DynamicImage, RgbImage};
+
+    fn key_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("zkimg-{}-{}.key", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn header_round_trips_and_detects_corruption() {
+        let header = KeyFileHeader {
+            kind: KeyKind::Verifying,
+            k: 12,
+            circuit_id: "zkimg".to_string(),
+            shape: KeyShape {
+                width: 640,
+                height: 480,
+                chain_digest: Fp::from(99),
+            },
+            fingerprint: [7; 32],
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(KeyFileHeader::from_bytes(&bytes).unwrap(), header);
+
+        let mut corrupted = bytes.clone();
+        corrupted[14] ^= 1;
+        assert!(KeyFileHeader::from_bytes(&corrupted).unwrap_err().to_string().contains("checksum"));
+        assert!(KeyFileHeader::from_bytes(&bytes[..40]).is_err());
+        assert!(KeyFileHeader::from_bytes(b"{\"json\": true}").is_err());
+    }
+
+    #[test]
+    fn keys_only_load_for_the_circuit_they_were_saved_for() {
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let chain = vec![Transformation::Grayscale];
+        let circuit = ZKIMGCircuit::blank(2, 2, chain.clone());
+        let (pk_path, vk_path) = (key_path("pk"), key_path("vk"));
+
+        proof_system.save_proving_key(&circuit, &pk_path).unwrap();
+        proof_system.save_verifying_key(&circuit, &vk_path).unwrap();
+        let pk = proof_system.load_proving_key(&pk_path, &circuit).unwrap();
+        let vk = proof_system.load_verifying_key(&vk_path, &circuit).unwrap();
+
+        // The regenerated keys work together
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 2, |x, y| image::Rgb([x as u8 * 90, y as u8 * 60, 7])));
+        let proving = ZKIMGCircuit::new(image_to_field_elements(&image), chain).unwrap();
+        let public_inputs = proving.public_inputs().unwrap();
+        let proof = proof_system.prove(&pk, proving, &public_inputs).unwrap();
+        assert!(proof_system.verify(&vk, &proof, &public_inputs).unwrap());
+
+        // Another kind, chain, image size or k
+        assert!(proof_system.load_proving_key(&vk_path, &circuit).is_err());
+        let blurred = ZKIMGCircuit::blank(2, 2, vec![Transformation::Blur]);
+        assert!(proof_system.load_verifying_key(&vk_path, &blurred).is_err());
+        let larger = ZKIMGCircuit::blank(3, 2, vec![Transformation::Grayscale]);
+        assert!(proof_system.load_verifying_key(&vk_path, &larger).is_err());
+        let other_k = ZKIMGProofSystem::new(12).unwrap();
+        assert!(other_k.load_verifying_key(&vk_path, &circuit).is_err());
+
+        for path in [pk_path, vk_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn fingerprints_are_deterministic_and_tell_circuits_apart() {
+        let params = ZKIMGProofSystem::new(11).unwrap().params().clone();
+        let fingerprint = |chain| vk_fingerprint(&params, &ZKIMGCircuit::blank(2, 2, chain)).unwrap();
+        let brightness = |numerator| Transformation::Brightness(Rational::new(numerator, 10).unwrap());
+
+        assert_eq!(fingerprint(vec![Transformation::Grayscale]), fingerprint(vec![Transformation::Grayscale]));
+        // Other gates, other fixed values only, and other copies only
+        assert_ne!(fingerprint(vec![Transformation::Grayscale]), fingerprint(vec![Transformation::Blur]));
+        assert_ne!(fingerprint(vec![brightness(12)]), fingerprint(vec![brightness(13)]));
+        assert_ne!(fingerprint(vec![Transformation::FlipHorizontal]), fingerprint(vec![Transformation::FlipVertical]));
+
+        let other_k = ZKIMGProofSystem::new(12).unwrap();
+        let circuit = ZKIMGCircuit::blank(2, 2, vec![Transformation::Grayscale]);
+        assert_ne!(vk_fingerprint(other_k.params(), &circuit).unwrap(), fingerprint(vec![Transformation::Grayscale]));
+    }
+}
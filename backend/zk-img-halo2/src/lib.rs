@@ -4,25 +4,59 @@
 //!
 //! This implementation uses halo2 for efficient ZK-SNARKs on HD images (720p)
 
+pub mod accumulation;
+pub mod chips;
 pub mod circuits;
+pub mod cost;
 pub mod transforms;
 pub mod proof_system;
 pub mod image_utils;
+pub mod keys;
+pub mod params;
+pub mod planner;
 pub mod recursive_circuit;
+pub mod registry;
+pub mod size_class;
+pub mod tiling;
 
 use std::collections::HashMap;
-use halo2_proofs::{pasta::Fp, plonk::Circuit};
-use image::{DynamicImage, RgbImage};
-use anyhow::{Result, anyhow};
+use std::time::Instant;
+use chips::{chain::native_chain_digest, linear::signed};
+use cost::CircuitCost;
+use keys::{vk_fingerprint, KeyedCircuit};
+use params::{check_params_hash, ParamsCache};
+use registry::{CircuitId, RegistryEntry, VkRegistry};
+use size_class::{check_paddable, PaddedZKIMGCircuit, SizeClass};
+use planner::FusionPlan;
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::{keygen_vk, ProvingKey, VerifyingKey},
+};
+use image::{DynamicImage, GenericImageView};
+use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
 
 /// Configuration for ZK-IMG system
 #[derive(Clone, Debug)]
 pub struct ZKIMGConfig {
+    /// Most pixels an input image, or any step's output, may have
     pub max_image_size: usize,
     pub k: u32, // Circuit size parameter (2^k rows)
     pub enable_operation_fusion: bool,
-    pub use_poseidon: bool,
+    /// Directory of cached params files (see `params::ParamsCache`);
+    /// params are generated in memory when unset
+    pub params_dir: Option<String>,
+    /// Expected `params::params_hash` of the params for `k`; any other
+    /// params are rejected, however they were obtained
+    pub params_hash: Option<[u8; 32]>,
+    /// Directory of registered verifying keys (see `registry::VkRegistry`);
+    /// when set, `setup` registers its key there and only proofs of
+    /// registered circuits verify
+    pub vk_registry_dir: Option<String>,
+    /// Canvases images are padded to, so one key proves every image that
+    /// fits one (see `size_class`); empty proves every size with its own key.
+    /// `k` must fit the largest class used, see `ZKIMGSystem::required_k`
+    pub size_classes: Vec<SizeClass>,
 }
 
 impl Default for ZKIMGConfig {
@@ -31,7 +65,10 @@ impl Default for ZKIMGConfig {
             max_image_size: 1280 * 720, // HD 720p
             k: 17, // ~131K rows - suitable for HD images
             enable_operation_fusion: true,
-            use_poseidon: true,
+            params_dir: None,
+            params_hash: None,
+            vk_registry_dir: None,
+            size_classes: Vec::new(),
         }
     }
 }
@@ -39,31 +76,37 @@ impl Default for ZKIMGConfig {
 /// ZK-IMG proof system
 pub struct ZKIMGSystem {
     config: ZKIMGConfig,
-    circuit_cache: HashMap<String, Box<dyn Circuit<Fp>>>,
+    proof_system: Option<ZKIMGProofSystem>,
+    /// Proving keys by `key_cache_shape`, with their `vk_fingerprint`
+    key_cache: HashMap<String, (ProvingKey<EqAffine>, [u8; 32])>,
+    metrics: ProofMetrics,
 }
 
 impl ZKIMGSystem {
     pub fn new(config: ZKIMGConfig) -> Self {
         Self {
             config,
-            circuit_cache: HashMap::new(),
+            proof_system: None,
+            key_cache: HashMap::new(),
+            metrics: ProofMetrics::new(),
         }
     }
 
+    /// Timings and sizes recorded by the most recent proof generation
+    pub fn last_metrics(&self) -> &ProofMetrics {
+        &self.metrics
+    }
+
     /// Generate proof for image transformation chain
     pub fn prove_transformation_chain(
         &mut self,
         original_image: &DynamicImage,
         transformations: &[Transformation],
     ) -> Result<ZKIMGProof> {
-        println!("🔐 ZK-IMG: Generating proof for {} transformations", transformations.len());
+        eprintln!("🔐 ZK-IMG: Generating proof for {} transformations", transformations.len());
 
-        // Fuse operations for efficiency
-        let fused_transforms = if self.config.enable_operation_fusion {
-            self.fuse_operations(transformations)?
-        } else {
-            transformations.to_vec()
-        };
+        let (width, height) = original_image.dimensions();
+        let fused_transforms = self.plan_transformations(width, height, transformations)?;
 
         // Generate proof using halo2
         let proof = self.generate_halo2_proof(original_image, &fused_transforms)?;
@@ -71,51 +114,138 @@ impl ZKIMGSystem {
         Ok(proof)
     }
 
+    /// Run keygen ahead of time for proving `transformations` on images of the given size
+    ///
+    /// Returns the encoded `VerifyingKeyRef` that proofs for this shape will carry.
+    /// With a registry configured the key is also registered under its `CircuitId`.
+    /// With size classes configured the key is the one of the image's class.
+    pub fn setup(&mut self, width: u32, height: u32, transformations: &[Transformation]) -> Result<Vec<u8>> {
+        let fused_transforms = self.plan_transformations(width, height, transformations)?;
+        let size_class = self.size_class(width, height, &fused_transforms)?;
+        let shape = key_cache_shape(width, height, &fused_transforms, size_class);
+
+        let setup_start = Instant::now();
+        let (key_width, key_height) = match size_class {
+            Some(class) => {
+                self.ensure_proving_key(&PaddedZKIMGCircuit::blank(class, fused_transforms.clone()), shape.clone())?;
+                (class.width, class.height)
+            }
+            None => {
+                let circuit = ZKIMGCircuit::blank(width as usize, height as usize, fused_transforms.clone());
+                self.ensure_proving_key(&circuit, shape.clone())?;
+                (width, height)
+            }
+        };
+        let fingerprint = self.key_cache[&shape].1;
+        let verification_key = VerifyingKeyRef::new(self.config.k, key_width, key_height, fingerprint).to_bytes();
+
+        if let (Some(registry), Some(proof_system)) = (self.registry(), &self.proof_system) {
+            let entry = RegistryEntry {
+                k: self.config.k,
+                width: key_width,
+                height: key_height,
+                transformations: fused_transforms,
+                size_class,
+            };
+            registry.register(proof_system, &entry)?;
+        }
+
+        let mut metrics = ProofMetrics::new();
+        metrics.setup_time_ms = setup_start.elapsed().as_secs_f64() * 1000.0;
+        metrics.vk_size_bytes = verification_key.len();
+        self.metrics = metrics;
+
+        Ok(verification_key)
+    }
+
+    /// ID of the circuit `setup` and proving use for this shape, after planning
+    pub fn circuit_id(&self, width: u32, height: u32, transformations: &[Transformation]) -> Result<CircuitId> {
+        let fused_transforms = self.plan_transformations(width, height, transformations)?;
+        match self.size_class(width, height, &fused_transforms)? {
+            Some(class) => CircuitId::padded(self.config.k, class, &fused_transforms),
+            None => CircuitId::new(self.config.k, width, height, &fused_transforms),
+        }
+    }
+
+    /// Smallest `k` that fits proving `transformations` on a `width` x `height`
+    /// image, after planning and padding to its size class if configured
+    pub fn required_k(&self, width: u32, height: u32, transformations: &[Transformation]) -> Result<u32> {
+        let fused_transforms = self.plan_transformations(width, height, transformations)?;
+        let cost = match self.size_class(width, height, &fused_transforms)? {
+            Some(class) => PaddedZKIMGCircuit::blank(class, fused_transforms).cost()?,
+            None => ZKIMGCircuit::blank(width as usize, height as usize, fused_transforms).cost()?,
+        };
+        Ok(cost.min_k)
+    }
+
     /// Verify ZK-IMG proof
     pub fn verify_proof(&self, proof: &ZKIMGProof, public_inputs: &[Fp]) -> Result<bool> {
-        println!("🔍 ZK-IMG: Verifying proof");
+        eprintln!("🔍 ZK-IMG: Verifying proof");
 
         // Use halo2 verification
         self.verify_halo2_proof(proof, public_inputs)
     }
 
-    /// Fuse multiple operations into efficient combinations
-    fn fuse_operations(&self, transformations: &[Transformation]) -> Result<Vec<Transformation>> {
-        let mut fused = Vec::new();
-        let mut i = 0;
-
-        while i < transformations.len() {
-            // Look for fusable operations
-            if i + 1 < transformations.len() {
-                match (&transformations[i], &transformations[i + 1]) {
-                    // Fuse crop + resize
-                    (Transformation::Crop(crop), Transformation::Resize(resize)) => {
-                        fused.push(Transformation::CropResize {
-                            crop_x: crop.x,
-                            crop_y: crop.y,
-                            crop_width: crop.width,
-                            crop_height: crop.height,
-                            resize_width: resize.width,
-                            resize_height: resize.height,
-                        });
-                        i += 2;
-                        continue;
-                    }
-                    // Fuse consecutive filters
-                    (Transformation::Grayscale, Transformation::Contrast(contrast)) => {
-                        fused.push(Transformation::GrayscaleContrast { contrast: *contrast });
-                        i += 2;
-                        continue;
-                    }
-                    _ => {}
-                }
-            }
+    /// Verify a proof, accepting it only if it is a proof of circuit `expected`
+    pub fn verify_proof_for_circuit(&self, proof: &ZKIMGProof, public_inputs: &[Fp], expected: &CircuitId) -> Result<bool> {
+        let id = proof_circuit_id(proof)?;
+        if id != *expected {
+            eprintln!("❌ Proof is for circuit {}, expected {}", id, expected);
+            return Ok(false);
+        }
+
+        self.verify_proof(proof, public_inputs)
+    }
+
+    /// Plan `transformations` for a `width` x `height` image and report the
+    /// constraint cost before and after
+    pub fn plan(&self, width: u32, height: u32, transformations: &[Transformation]) -> Result<FusionPlan> {
+        let plan = planner::plan(width, height, transformations)?;
+        eprintln!(
+            "🧩 Fusion plan: {} → {} steps, {} → {} rows (k {} → {})",
+            plan.original.len(),
+            plan.planned.len(),
+            plan.before.rows,
+            plan.after.rows,
+            plan.before.min_k,
+            plan.after.min_k
+        );
+        Ok(plan)
+    }
 
-            fused.push(transformations[i].clone());
-            i += 1;
+    /// Apply operation fusion when enabled, rejecting images and steps
+    /// larger than `max_image_size`
+    fn plan_transformations(&self, width: u32, height: u32, transformations: &[Transformation]) -> Result<Vec<Transformation>> {
+        for transformation in transformations {
+            transformation.validate()?;
         }
+        self.check_image_size(width, height)?;
 
-        Ok(fused)
+        let planned = if self.config.enable_operation_fusion {
+            planner::optimize(width, height, transformations)
+        } else {
+            transformations.to_vec()
+        };
+
+        let (mut step_width, mut step_height) = (width, height);
+        for transformation in &planned {
+            (step_width, step_height) = transformation.output_size(step_width, step_height);
+            self.check_image_size(step_width, step_height)
+                .with_context(|| format!("{:?} output is too large", transformation))?;
+        }
+        Ok(planned)
+    }
+
+    fn check_image_size(&self, width: u32, height: u32) -> Result<()> {
+        if width as u64 * height as u64 > self.config.max_image_size as u64 {
+            return Err(anyhow!(
+                "A {}x{} image exceeds the limit of {} pixels",
+                width,
+                height,
+                self.config.max_image_size
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -124,11 +254,22 @@ impl ZKIMGSystem {
 pub enum Transformation {
     // Physical transformations
     Crop { x: u32, y: u32, width: u32, height: u32 },
-    Resize { width: u32, height: u32 },
+    Resize {
+        width: u32,
+        height: u32,
+        #[serde(default)]
+        filter: ResizeFilter,
+    },
     Rotate { degrees: f32 },
     FlipHorizontal,
     FlipVertical,
-    Translate { dx: i32, dy: i32 },
+    Translate {
+        dx: i32,
+        dy: i32,
+        /// RGB colour of the vacated pixels
+        #[serde(default)]
+        fill: [u8; 3],
+    },
 
     // Color space conversions
     ToYCbCr,
@@ -138,8 +279,9 @@ pub enum Transformation {
     Grayscale,
     Sharpen,
     Blur,
-    Contrast(f32),
-    Brightness(f32),
+    Convolve(ConvolutionKernel),
+    Contrast(Rational),
+    Brightness(Rational),
     WhiteBalance,
 
     // Fused operations for efficiency
@@ -150,8 +292,205 @@ pub enum Transformation {
         crop_height: u32,
         resize_width: u32,
         resize_height: u32,
+        #[serde(default)]
+        filter: ResizeFilter,
     },
-    GrayscaleContrast { contrast: f32 },
+    GrayscaleContrast { contrast: Rational },
+    /// Brightness and contrast folded into one map by the planner
+    ToneMap(ToneMap),
+}
+
+impl Transformation {
+    /// Reject parameters no circuit can prove, such as arbitrary rotation angles
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Transformation::Rotate { degrees } => {
+                RightAngle::from_degrees(*degrees)?;
+            }
+            Transformation::ToneMap(tone) if !tone.is_valid() => {
+                return Err(anyhow!("Tone map shift and offset must be within ±{}", MAX_TONE_SHIFT));
+            }
+            Transformation::Convolve(kernel) => kernel.check()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Apply the transformation natively, bit-identical to the circuit
+    pub fn apply(&self, image: &DynamicImage) -> Result<DynamicImage> {
+        let fits = |x: u32, y: u32, width: u32, height: u32| {
+            let (image_width, image_height) = image.dimensions();
+            if x as u64 + width as u64 > image_width as u64 || y as u64 + height as u64 > image_height as u64 {
+                return Err(anyhow!(
+                    "Crop {}x{} at ({}, {}) does not fit a {}x{} image",
+                    width, height, x, y, image_width, image_height
+                ));
+            }
+            Ok(())
+        };
+
+        Ok(match self {
+            Transformation::Crop { x, y, width, height } => {
+                fits(*x, *y, *width, *height)?;
+                physical::crop(image, *x, *y, *width, *height)
+            }
+            Transformation::Resize { width, height, filter } => physical::resize(image, *width, *height, *filter),
+            Transformation::Rotate { degrees } => physical::rotate(image, *degrees)?,
+            Transformation::FlipHorizontal => physical::flip_horizontal(image),
+            Transformation::FlipVertical => physical::flip_vertical(image),
+            Transformation::Translate { dx, dy, fill } => physical::translate(image, *dx, *dy, *fill),
+            Transformation::ToYCbCr => colorspace::rgb_to_ycbcr(image),
+            Transformation::ToRGB => colorspace::ycbcr_to_rgb(image),
+            Transformation::Grayscale => filters::grayscale(image),
+            Transformation::Sharpen => filters::sharpen(image),
+            Transformation::Blur => filters::blur(image),
+            Transformation::Convolve(kernel) => filters::convolve(image, kernel),
+            Transformation::Contrast(contrast) => filters::contrast(image, *contrast),
+            Transformation::Brightness(brightness) => filters::brightness(image, *brightness),
+            Transformation::WhiteBalance => filters::white_balance(image),
+            Transformation::CropResize {
+                crop_x,
+                crop_y,
+                crop_width,
+                crop_height,
+                resize_width,
+                resize_height,
+                filter,
+            } => {
+                fits(*crop_x, *crop_y, *crop_width, *crop_height)?;
+                fused::crop_resize(
+                    image,
+                    *crop_x,
+                    *crop_y,
+                    *crop_width,
+                    *crop_height,
+                    *resize_width,
+                    *resize_height,
+                    *filter,
+                )
+            }
+            Transformation::GrayscaleContrast { contrast } => fused::grayscale_contrast(image, *contrast),
+            Transformation::ToneMap(tone) => filters::tone_map(image, tone),
+        })
+    }
+
+    /// Dimensions of the output for a `width` x `height` input
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transformation::Crop { width, height, .. } => (*width, *height),
+            Transformation::Resize { width, height, .. } => (*width, *height),
+            Transformation::CropResize { resize_width, resize_height, .. } => (*resize_width, *resize_height),
+            Transformation::Rotate { degrees } => match RightAngle::from_degrees(*degrees) {
+                Ok(angle) => {
+                    let (width, height) = angle.output_size(width as usize, height as usize);
+                    (width as u32, height as u32)
+                }
+                Err(_) => (width, height),
+            },
+            _ => (width, height),
+        }
+    }
+
+    /// Canonical field encoding `[tag, params...]`
+    ///
+    /// The tag fixes the number of parameters, so concatenated encodings of a
+    /// chain are unambiguous. Parameters are normalized so that equal
+    /// transformations encode equally: rotations as quarter turns in `0..4`,
+    /// rationals in lowest terms as `[numerator, denominator]`, signed values
+    /// as field negations and resize filters as `0` (nearest) or `1`
+    /// (bilinear). Changing this encoding requires bumping
+    /// `chips::chain::CHAIN_ENCODING_VERSION`.
+    pub fn encode(&self) -> Result<Vec<Fp>> {
+        let unsigned = |values: &[u64]| values.iter().map(|&v| Fp::from(v)).collect::<Vec<_>>();
+        let filter_code = |filter: &ResizeFilter| match filter {
+            ResizeFilter::Nearest => 0,
+            ResizeFilter::Bilinear => 1,
+        };
+        let rational = |value: &Rational| vec![signed(value.numerator()), Fp::from(value.denominator() as u64)];
+
+        let (tag, params) = match self {
+            Transformation::Crop { x, y, width, height } => {
+                (1, unsigned(&[*x as u64, *y as u64, *width as u64, *height as u64]))
+            }
+            Transformation::Resize { width, height, filter } => {
+                (2, unsigned(&[*width as u64, *height as u64, filter_code(filter)]))
+            }
+            Transformation::Rotate { degrees } => {
+                let turns = match RightAngle::from_degrees(*degrees)? {
+                    RightAngle::Deg0 => 0,
+                    RightAngle::Deg90 => 1,
+                    RightAngle::Deg180 => 2,
+                    RightAngle::Deg270 => 3,
+                };
+                (3, unsigned(&[turns]))
+            }
+            Transformation::FlipHorizontal => (4, vec![]),
+            Transformation::FlipVertical => (5, vec![]),
+            Transformation::Translate { dx, dy, fill } => {
+                let mut params = vec![signed(*dx as i64), signed(*dy as i64)];
+                params.extend(fill.map(|c| Fp::from(c as u64)));
+                (6, params)
+            }
+            Transformation::ToYCbCr => (7, vec![]),
+            Transformation::ToRGB => (8, vec![]),
+            Transformation::Grayscale => (9, vec![]),
+            Transformation::Sharpen => (10, vec![]),
+            Transformation::Blur => (11, vec![]),
+            Transformation::Convolve(kernel) => {
+                let mut params: Vec<_> = kernel.weights.iter().flatten().map(|&w| signed(w as i64)).collect();
+                params.push(Fp::from(kernel.divisor.get() as u64));
+                (12, params)
+            }
+            Transformation::Contrast(contrast) => (13, rational(contrast)),
+            Transformation::Brightness(brightness) => (14, rational(brightness)),
+            Transformation::WhiteBalance => (15, vec![]),
+            Transformation::CropResize {
+                crop_x,
+                crop_y,
+                crop_width,
+                crop_height,
+                resize_width,
+                resize_height,
+                filter,
+            } => (
+                16,
+                unsigned(&[
+                    *crop_x as u64,
+                    *crop_y as u64,
+                    *crop_width as u64,
+                    *crop_height as u64,
+                    *resize_width as u64,
+                    *resize_height as u64,
+                    filter_code(filter),
+                ]),
+            ),
+            Transformation::GrayscaleContrast { contrast } => (17, rational(contrast)),
+            Transformation::ToneMap(tone) => {
+                let mut params = vec![signed(tone.shift as i64)];
+                params.extend(rational(&tone.contrast));
+                params.push(signed(tone.offset as i64));
+                (18, params)
+            }
+        };
+
+        let mut encoded = vec![Fp::from(tag)];
+        encoded.extend(params);
+        Ok(encoded)
+    }
+
+    /// Concatenated canonical encodings of a chain
+    pub fn encode_chain(transformations: &[Transformation]) -> Result<Vec<Fp>> {
+        let mut encoded = Vec::new();
+        for transformation in transformations {
+            encoded.extend(transformation.encode()?);
+        }
+        Ok(encoded)
+    }
+
+    /// Poseidon digest of a chain, as exposed by `ZKIMGCircuit`
+    pub fn chain_digest(transformations: &[Transformation]) -> Result<Fp> {
+        Ok(native_chain_digest(&Self::encode_chain(transformations)?))
+    }
 }
 
 /// ZK-IMG proof output
@@ -163,6 +502,9 @@ pub struct ZKIMGProof {
     pub input_hash: Vec<u8>,
     pub output_hash: Vec<u8>,
     pub verification_key: Vec<u8>,
+    /// Canvas the image was padded to, for proofs of a `PaddedZKIMGCircuit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_class: Option<SizeClass>,
 }
 
 /// Performance metrics (as reported in paper)
@@ -175,16 +517,245 @@ pub struct PerformanceMetrics {
 }
 
 impl ZKIMGSystem {
-    fn generate_halo2_proof(&self, image: &DynamicImage, transformations: &[Transformation]) -> Result<ZKIMGProof> {
-        // Implementation would go here using halo2_proofs
-        // This is a placeholder showing the structure
+    fn generate_halo2_proof(&mut self, image: &DynamicImage, transformations: &[Transformation]) -> Result<ZKIMGProof> {
+        let (width, height) = image.dimensions();
+        let pixels = image_to_field_elements(image);
+        let size_class = self.size_class(width, height, transformations)?;
+        let shape = key_cache_shape(width, height, transformations, size_class);
+
+        let (proof_bytes, public_inputs, verification_key) = match size_class {
+            Some(class) => {
+                let circuit = PaddedZKIMGCircuit::new(class, pixels, transformations.to_vec())?;
+                let public_inputs = circuit.public_inputs()?;
+                let (proof_bytes, verification_key) =
+                    self.prove_circuit(circuit, shape, (class.width, class.height), &public_inputs)?;
+                (proof_bytes, public_inputs, verification_key)
+            }
+            None => {
+                let circuit = ZKIMGCircuit::new(pixels, transformations.to_vec())?;
+                let public_inputs = circuit.public_inputs()?;
+                let (proof_bytes, verification_key) = self.prove_circuit(circuit, shape, (width, height), &public_inputs)?;
+                (proof_bytes, public_inputs, verification_key)
+            }
+        };
 
-        Err(anyhow!("Halo2 implementation in progress"))
+        Ok(ZKIMGProof {
+            proof_bytes,
+            input_hash: image_hash_to_bytes(public_inputs[0]),
+            output_hash: image_hash_to_bytes(public_inputs[1]),
+            public_inputs,
+            transformation_chain: transformations.to_vec(),
+            verification_key,
+            size_class,
+        })
+    }
+
+    /// Prove `circuit` with the cached key for `shape`, recording metrics and
+    /// returning the proof and the `VerifyingKeyRef` of its `width` x `height` key
+    fn prove_circuit<C: KeyedCircuit>(
+        &mut self,
+        circuit: C,
+        shape: String,
+        (width, height): (u32, u32),
+        public_inputs: &[Fp],
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut metrics = ProofMetrics::new();
+        let setup_start = Instant::now();
+        let shape = self.ensure_proving_key(&circuit, shape)?;
+        metrics.setup_time_ms = setup_start.elapsed().as_secs_f64() * 1000.0;
+
+        let (Some(proof_system), Some((pk, fingerprint))) = (&self.proof_system, self.key_cache.get(&shape)) else {
+            return Err(anyhow!("Proof system was not initialized"));
+        };
+
+        let proving_start = Instant::now();
+        let proof_bytes = proof_system.prove(pk, circuit, public_inputs)?;
+        metrics.proving_time_ms = proving_start.elapsed().as_secs_f64() * 1000.0;
+
+        let verification_key = VerifyingKeyRef::new(proof_system.k(), width, height, *fingerprint).to_bytes();
+        metrics.proof_size_bytes = proof_bytes.len();
+        metrics.vk_size_bytes = verification_key.len();
+        self.metrics = metrics;
+
+        Ok((proof_bytes, verification_key))
     }
 
     fn verify_halo2_proof(&self, proof: &ZKIMGProof, public_inputs: &[Fp]) -> Result<bool> {
-        // Implementation would go here
-        Err(anyhow!("Halo2 verification in progress"))
+        let vk_ref = VerifyingKeyRef::from_bytes(&proof.verification_key)?;
+
+        // A padded proof's key is its class's, and it proves the real size too
+        if let Some(class) = proof.size_class {
+            if (vk_ref.width, vk_ref.height) != (class.width, class.height) || public_inputs.len() != 5 {
+                eprintln!("❌ Proof does not match its size class");
+                return Ok(false);
+            }
+        }
+
+        // The hashes the proof reports are the ones it proves
+        let input_hash = image_hash_from_bytes(&proof.input_hash);
+        let output_hash = image_hash_from_bytes(&proof.output_hash);
+        if public_inputs.len() < 2 || input_hash != Some(public_inputs[0]) || output_hash != Some(public_inputs[1]) {
+            eprintln!("❌ Image hashes do not match the proven public inputs");
+            return Ok(false);
+        }
+
+        let owned;
+        let proof_system = match &self.proof_system {
+            Some(proof_system) if proof_system.k() == vk_ref.k => proof_system,
+            _ => {
+                owned = self.new_proof_system(vk_ref.k)?;
+                &owned
+            }
+        };
+
+        // The proof must attest to exactly the chain it carries
+        let chain_digest = Transformation::chain_digest(&proof.transformation_chain)?;
+        if public_inputs.get(2) != Some(&chain_digest) {
+            eprintln!("❌ Transformation chain does not match the proven chain digest");
+            return Ok(false);
+        }
+
+        // With a registry, the key is the one registered for the claimed
+        // shape. Otherwise it is regenerated from the shape.
+        let (vk, fingerprint) = match self.registry() {
+            Some(registry) => {
+                let id = proof_circuit_id(proof)?;
+                if !registry.contains(&id) {
+                    eprintln!("❌ No verifying key registered for circuit {}", id);
+                    return Ok(false);
+                }
+                let (entry, vk) = registry.lookup(proof_system, &id)?;
+                (vk, entry.fingerprint(proof_system)?)
+            }
+            None => proof_verifying_key(proof_system, proof)?,
+        };
+
+        // Make sure it is the key the proof was created against
+        if vk_ref.fingerprint != fingerprint {
+            eprintln!("❌ Verifying key does not match the proven circuit");
+            return Ok(false);
+        }
+
+        proof_system.verify(&vk, &proof.proof_bytes, public_inputs)
+    }
+
+    /// Class a `width` x `height` image is proven in, if size classes are configured
+    ///
+    /// Chains with steps that read the padding, such as crops, resizes and
+    /// blurs, fall back to an exact-size key.
+    fn size_class(&self, width: u32, height: u32, transformations: &[Transformation]) -> Result<Option<SizeClass>> {
+        if self.config.size_classes.is_empty() {
+            return Ok(None);
+        }
+        if let Err(err) = check_paddable(transformations) {
+            eprintln!("⚠️  {}; using an exact {}x{} key instead of a size class", err, width, height);
+            return Ok(None);
+        }
+        SizeClass::smallest_fitting(&self.config.size_classes, width, height).map(Some)
+    }
+
+    fn registry(&self) -> Option<VkRegistry> {
+        self.config.vk_registry_dir.as_ref().map(VkRegistry::new)
+    }
+
+    fn new_proof_system(&self, k: u32) -> Result<ZKIMGProofSystem> {
+        if let Some(cache) = self.params_cache() {
+            return ZKIMGProofSystem::cached(k, &cache);
+        }
+
+        let proof_system = ZKIMGProofSystem::new(k)?;
+        if let Some(hash) = &self.config.params_hash {
+            check_params_hash(proof_system.params(), hash)?;
+        }
+        Ok(proof_system)
+    }
+
+    fn params_cache(&self) -> Option<ParamsCache> {
+        let cache = ParamsCache::new(self.config.params_dir.as_ref()?);
+        Some(match self.config.params_hash {
+            Some(hash) => cache.pinned(hash),
+            None => cache,
+        })
+    }
+
+    /// Make sure a proving key exists for the circuit's shape, returning its cache key
+    ///
+    /// Fails before any keygen if the circuit needs more rows than `2^k`.
+    fn ensure_proving_key<C: KeyedCircuit>(&mut self, circuit: &C, shape: String) -> Result<String> {
+        if self.key_cache.contains_key(&shape) {
+            return Ok(shape);
+        }
+
+        let cost = CircuitCost::measure(circuit)?;
+        if cost.min_k > self.config.k {
+            return Err(anyhow!(
+                "Proving {} needs k={} ({} rows), but k={} is configured",
+                shape,
+                cost.min_k,
+                cost.rows,
+                self.config.k
+            ));
+        }
+
+        if self.proof_system.is_none() {
+            self.proof_system = Some(self.new_proof_system(self.config.k)?);
+        }
+        let proof_system = self
+            .proof_system
+            .as_ref()
+            .ok_or_else(|| anyhow!("Proof system was not initialized"))?;
+
+        // Keys only depend on the circuit shape, so reuse them across images
+        let (pk, _) = proof_system.setup(circuit)?;
+        let fingerprint = vk_fingerprint(proof_system.params(), circuit)?;
+        self.key_cache.insert(shape.clone(), (pk, fingerprint));
+
+        Ok(shape)
+    }
+}
+
+/// Key cache entry of a circuit: its image size or size class and chain
+pub(crate) fn key_cache_shape(width: u32, height: u32, transformations: &[Transformation], size_class: Option<SizeClass>) -> String {
+    match size_class {
+        Some(class) => format!("class {}x{}:{:?}", class.width, class.height, transformations),
+        None => format!("{}x{}:{:?}", width, height, transformations),
+    }
+}
+
+/// Key cache entry of the circuit `proof` claims to be a proof of
+pub(crate) fn proof_key_shape(proof: &ZKIMGProof) -> Result<String> {
+    let vk_ref = VerifyingKeyRef::from_bytes(&proof.verification_key)?;
+    Ok(key_cache_shape(vk_ref.width, vk_ref.height, &proof.transformation_chain, proof.size_class))
+}
+
+/// Verifying key of the circuit `proof` claims to be a proof of, regenerated
+/// from its size class, or its image size when unpadded, and its chain,
+/// with the key's `vk_fingerprint`
+pub(crate) fn proof_verifying_key(
+    proof_system: &ZKIMGProofSystem,
+    proof: &ZKIMGProof,
+) -> Result<(VerifyingKey<EqAffine>, [u8; 32])> {
+    let params = proof_system.params();
+    let chain = proof.transformation_chain.clone();
+    Ok(match proof.size_class {
+        Some(class) => {
+            let circuit = PaddedZKIMGCircuit::blank(class, chain);
+            (keygen_vk(params, &circuit)?, vk_fingerprint(params, &circuit)?)
+        }
+        None => {
+            let vk_ref = VerifyingKeyRef::from_bytes(&proof.verification_key)?;
+            let circuit = ZKIMGCircuit::blank(vk_ref.width as usize, vk_ref.height as usize, chain);
+            (keygen_vk(params, &circuit)?, vk_fingerprint(params, &circuit)?)
+        }
+    })
+}
+
+/// ID of the circuit `proof` claims to be a proof of
+fn proof_circuit_id(proof: &ZKIMGProof) -> Result<CircuitId> {
+    let vk_ref = VerifyingKeyRef::from_bytes(&proof.verification_key)?;
+    match proof.size_class {
+        Some(class) => CircuitId::padded(vk_ref.k, class, &proof.transformation_chain),
+        None => CircuitId::new(vk_ref.k, vk_ref.width, vk_ref.height, &proof.transformation_chain),
     }
 }
 
@@ -192,4 +763,159 @@ impl ZKIMGSystem {
 pub use circuits::*;
 pub use transforms::*;
 pub use proof_system::*;
-pub use image_utils::*;
\ No newline at end of file
+pub use image_utils::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use std::num::NonZeroU32;
+
+    #[test]
+    fn encoding_is_canonical() {
+        let rotate = |degrees| Transformation::Rotate { degrees }.encode().unwrap();
+        assert_eq!(rotate(90.0), rotate(-270.0));
+        assert_ne!(rotate(90.0), rotate(180.0));
+
+        let contrast: Transformation = serde_json::from_str(r#"{"Contrast":{"numerator":6,"denominator":4}}"#).unwrap();
+        assert_eq!(contrast.encode().unwrap(), Transformation::Contrast(Rational::new(3, 2).unwrap()).encode().unwrap());
+    }
+
+    #[test]
+    fn chain_digest_depends_on_order_and_parameters() {
+        let crop = Transformation::Crop { x: 0, y: 0, width: 2, height: 2 };
+        let digest = |chain: &[Transformation]| Transformation::chain_digest(chain).unwrap();
+
+        assert_ne!(digest(&[crop.clone(), Transformation::Grayscale]), digest(&[Transformation::Grayscale, crop.clone()]));
+        assert_ne!(digest(&[crop]), digest(&[Transformation::Crop { x: 0, y: 0, width: 2, height: 1 }]));
+        assert_ne!(digest(&[]), digest(&[Transformation::Grayscale]));
+    }
+
+    #[test]
+    fn verifier_rejects_a_swapped_chain_or_hash() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 3, |x, y| image::Rgb([x as u8 * 60, y as u8 * 80, 7])));
+        let mut system = ZKIMGSystem::new(ZKIMGConfig { k: 11, ..Default::default() });
+
+        let proof = system.prove_transformation_chain(&image, &[Transformation::FlipHorizontal]).unwrap();
+        assert!(system.verify_proof(&proof, &proof.public_inputs).unwrap());
+
+        let mut swapped = proof.clone();
+        swapped.transformation_chain = vec![Transformation::FlipVertical];
+        assert!(!system.verify_proof(&swapped, &swapped.public_inputs).unwrap());
+
+        // Reported image hashes must be the proven ones
+        let mut relabelled = proof.clone();
+        relabelled.input_hash = image_hash_to_bytes(Fp::from(42));
+        assert!(!system.verify_proof(&relabelled, &relabelled.public_inputs).unwrap());
+        let mut relabelled = proof;
+        relabelled.output_hash = relabelled.input_hash.clone();
+        assert!(!system.verify_proof(&relabelled, &relabelled.public_inputs).unwrap());
+    }
+
+    #[test]
+    fn proof_systems_only_use_pinned_params() {
+        let hash = params::params_hash(&halo2_proofs::poly::commitment::Params::new(11)).unwrap();
+        let pinned = |params_hash| ZKIMGSystem::new(ZKIMGConfig { k: 11, params_hash, ..Default::default() });
+
+        assert!(pinned(Some(hash)).new_proof_system(11).is_ok());
+        assert!(pinned(Some([0; 32])).new_proof_system(11).is_err());
+        assert!(pinned(Some(hash)).new_proof_system(10).is_err());
+    }
+
+    #[test]
+    fn size_classes_share_one_key() {
+        let image = |width, height| {
+            DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8 * 40, y as u8 * 50, 90])))
+        };
+        let chain = [Transformation::Grayscale];
+        let mut system = ZKIMGSystem::new(ZKIMGConfig {
+            k: 11,
+            size_classes: vec![SizeClass { width: 4, height: 4 }],
+            ..Default::default()
+        });
+
+        let small = system.prove_transformation_chain(&image(3, 2), &chain).unwrap();
+        let tall = system.prove_transformation_chain(&image(2, 4), &chain).unwrap();
+        assert_eq!(system.key_cache.len(), 1);
+        assert_eq!(small.verification_key, tall.verification_key);
+        assert_eq!(small.public_inputs[3..], [Fp::from(3), Fp::from(2)]);
+
+        // Verifiers need no size class configuration
+        let verifier = ZKIMGSystem::new(ZKIMGConfig { k: 11, ..Default::default() });
+        for proof in [&small, &tall] {
+            assert!(verifier.verify_proof(proof, &proof.public_inputs).unwrap());
+        }
+        let mut resized = small.public_inputs.clone();
+        resized[4] = Fp::from(3);
+        assert!(!verifier.verify_proof(&small, &resized).unwrap());
+
+        // Images too large for every class fail
+        assert!(system.prove_transformation_chain(&image(5, 1), &chain).is_err());
+
+        // Chains that read the padding fall back to an exact-size key
+        let blur = [Transformation::Blur];
+        let exact = system.prove_transformation_chain(&image(3, 2), &blur).unwrap();
+        assert_eq!(system.key_cache.len(), 2);
+        assert_eq!(system.circuit_id(3, 2, &blur).unwrap(), CircuitId::new(11, 3, 2, &blur).unwrap());
+        assert!(verifier.verify_proof(&exact, &exact.public_inputs).unwrap());
+
+        // Classes too large for k fail before keygen, naming the k they need
+        let mut large = ZKIMGSystem::new(ZKIMGConfig {
+            k: 11,
+            size_classes: vec![SizeClass { width: 16, height: 16 }],
+            ..Default::default()
+        });
+        assert_eq!(large.required_k(3, 2, &chain).unwrap(), 13);
+        let err = large.setup(3, 2, &chain).unwrap_err();
+        assert!(err.to_string().contains("needs k=13"), "{}", err);
+        assert!(large.proof_system.is_none() && large.key_cache.is_empty());
+    }
+
+    #[test]
+    fn images_and_steps_are_bounded_by_max_image_size() {
+        let mut system = ZKIMGSystem::new(ZKIMGConfig { k: 11, max_image_size: 6, ..Default::default() });
+        assert!(system.circuit_id(3, 2, &[Transformation::Grayscale]).is_ok());
+
+        // Rejected before any keygen
+        assert!(system.setup(2, 4, &[Transformation::Grayscale]).is_err());
+        let upscale = Transformation::Resize { width: 4, height: 2, filter: ResizeFilter::default() };
+        assert!(system.setup(3, 2, &[Transformation::Grayscale, upscale]).is_err());
+        assert!(system.proof_system.is_none() && system.key_cache.is_empty());
+    }
+
+    #[test]
+    fn unprovable_kernels_are_rejected_when_planning() {
+        let system = ZKIMGSystem::new(ZKIMGConfig { k: 11, ..Default::default() });
+        let kernel = ConvolutionKernel { weights: [[0, 0, 0], [0, 258, 0], [0, 0, 0]], divisor: NonZeroU32::MIN };
+        let err = system.circuit_id(4, 4, &[Transformation::Convolve(kernel)]).unwrap_err();
+        assert!(err.to_string().contains("cannot be proven"), "{}", err);
+        assert!(system.circuit_id(4, 4, &[Transformation::Convolve(ConvolutionKernel::BLUR)]).is_ok());
+    }
+
+    #[test]
+    fn registry_only_accepts_registered_circuits() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 2, |x, y| image::Rgb([x as u8 * 90, y as u8 * 90, 3])));
+        let chain = [Transformation::Grayscale];
+        let dir = std::env::temp_dir().join(format!("zkimg-vk-registry-{}", std::process::id()));
+        let config = ZKIMGConfig {
+            k: 11,
+            vk_registry_dir: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        // Nothing registered yet
+        let mut system = ZKIMGSystem::new(config.clone());
+        let proof = system.prove_transformation_chain(&image, &chain).unwrap();
+        assert!(!system.verify_proof(&proof, &proof.public_inputs).unwrap());
+
+        system.setup(2, 2, &chain).unwrap();
+        let id = system.circuit_id(2, 2, &chain).unwrap();
+        let verifier = ZKIMGSystem::new(config);
+        assert!(verifier.verify_proof(&proof, &proof.public_inputs).unwrap());
+        assert!(verifier.verify_proof_for_circuit(&proof, &proof.public_inputs, &id).unwrap());
+        let other = system.circuit_id(2, 2, &[Transformation::Blur]).unwrap();
+        assert!(!verifier.verify_proof_for_circuit(&proof, &proof.public_inputs, &other).unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+//! ZK-IMG command-line prover
+//!
+//! Implements the contract used by `backend/zk-img-halo2-wrapper.js`: the
+//! wrapper writes an `input.json` holding a base64 image and a transformation
+//! list, spawns this binary, and reads back a `ZKIMGProof` as JSON.
+//!
+//! Usage:
+//!   zk-img-halo2 prove   <input.json> [--output <file>] [--k <k>] [--params-dir <dir>] [--size-classes]
+//!   zk-img-halo2 verify  <proof.json> [--output <file>] [--params-dir <dir>] [--vk-registry <dir>]
+//!   zk-img-halo2 setup   <input.json> [--output <file>] [--k <k>] [--params-dir <dir>] [--vk-registry <dir>]
+//!                        [--size-classes]
+//!   zk-img-halo2 metrics <input.json> [--output <file>] [--k <k>] [--params-dir <dir>]
+//!
+//! With `--params-dir`, IPA params are read from (and first generated into)
+//! that directory instead of being regenerated by every run. Every command
+//! also accepts `--params-hash <hex>`, the SHA-256 of the params file for k,
+//! and then refuses any other params.
+//!
+//! With `--vk-registry`, `setup` registers the verifying key of its circuit
+//! in that directory and `verify` only accepts proofs of registered circuits.
+//!
+//! With `--size-classes`, images are padded to the smallest standard size
+//! class they fit (256², 512², 720p or 1080p), so one key per class and chain
+//! serves every image size. Classes need far more rows than the default k, so
+//! without `--k` the smallest k fitting the image's class and chain is used.
+//! Only per-pixel chains (color conversions, contrast, brightness, tone maps)
+//! can be padded: chains with a crop, resize, rotation, flip, blur, sharpen,
+//! white balance or similar step fall back to a key for the exact image size.
+//!
+//! Exit codes: 0 on success, 1 when a proof fails verification, 2 on errors.
+
+use std::fs;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zk_img_halo2::params::parse_hash;
+use zk_img_halo2::size_class::SizeClass;
+use zk_img_halo2::{ProofMetrics, Transformation, ZKIMGConfig, ZKIMGProof, ZKIMGSystem};
+
+const EXIT_INVALID: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+
+const USAGE: &str = concat!(
+    "Usage: zk-img-halo2 <prove|verify|setup|metrics> <file.json> [--output <file>] [--k <k>] ",
+    "[--params-dir <dir>] [--params-hash <hex>] [--vk-registry <dir>] [--size-classes]\n",
+    "  --size-classes only pads per-pixel chains; chains with crops, resizes, flips, rotations or\n",
+    "  filters use a key for the exact image size"
+);
+
+/// Request written by the Node wrapper
+#[derive(Deserialize)]
+struct ProveInput {
+    image: String,
+    transformations: Vec<Value>,
+}
+
+/// Proof plus the timings it took to produce
+#[derive(Serialize, Deserialize)]
+struct ProofOutput {
+    #[serde(flatten)]
+    proof: ZKIMGProof,
+    metrics: Option<ProofMetrics>,
+}
+
+#[derive(Serialize)]
+struct VerifyOutput {
+    valid: bool,
+    verification_time_ms: f64,
+}
+
+#[derive(Serialize)]
+struct SetupOutput {
+    circuit_id: String,
+    k: u32,
+    width: u32,
+    height: u32,
+    transformation_chain: Vec<Transformation>,
+    verification_key: Vec<u8>,
+    metrics: ProofMetrics,
+}
+
+#[derive(Serialize)]
+struct MetricsOutput {
+    valid: bool,
+    metrics: ProofMetrics,
+}
+
+struct Args {
+    command: String,
+    input: String,
+    output: Option<String>,
+    k: Option<u32>,
+    params_dir: Option<String>,
+    params_hash: Option<[u8; 32]>,
+    vk_registry: Option<String>,
+    size_classes: bool,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("❌ {}\n{}", err, USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_INVALID),
+        Err(err) => {
+            eprintln!("❌ {:#}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let command = args.next().ok_or_else(|| anyhow!("Missing command"))?;
+    let mut input = None;
+    let mut output = None;
+    let mut k = None;
+    let mut params_dir = None;
+    let mut params_hash = None;
+    let mut vk_registry = None;
+    let mut size_classes = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" | "-o" => {
+                output = Some(args.next().ok_or_else(|| anyhow!("--output needs a path"))?);
+            }
+            "--k" => {
+                let value = args.next().ok_or_else(|| anyhow!("--k needs a value"))?;
+                k = Some(value.parse().with_context(|| format!("Invalid k: {}", value))?);
+            }
+            "--params-dir" => {
+                params_dir = Some(args.next().ok_or_else(|| anyhow!("--params-dir needs a path"))?);
+            }
+            "--params-hash" => {
+                let value = args.next().ok_or_else(|| anyhow!("--params-hash needs a value"))?;
+                params_hash = Some(parse_hash(&value)?);
+            }
+            "--vk-registry" => {
+                vk_registry = Some(args.next().ok_or_else(|| anyhow!("--vk-registry needs a path"))?);
+            }
+            "--size-classes" => size_classes = true,
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(anyhow!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Args {
+        command,
+        input: input.ok_or_else(|| anyhow!("Missing input file"))?,
+        output,
+        k,
+        params_dir,
+        params_hash,
+        vk_registry,
+        size_classes,
+    })
+}
+
+/// Run a command, returning whether every checked proof verified
+fn run(args: &Args) -> Result<bool> {
+    match args.command.as_str() {
+        "prove" => {
+            let (image, transformations) = read_input(&args.input)?;
+            let mut system = ZKIMGSystem::new(prover_config(args, &image, &transformations)?);
+
+            let proof = system.prove_transformation_chain(&image, &transformations)?;
+            let output = ProofOutput {
+                proof,
+                metrics: Some(system.last_metrics().clone()),
+            };
+            write_output(args, &output)?;
+            Ok(true)
+        }
+        "verify" => {
+            let contents = fs::read_to_string(&args.input)
+                .with_context(|| format!("Failed to read {}", args.input))?;
+            let output: ProofOutput = serde_json::from_str(&contents).context("Invalid proof JSON")?;
+            let system = ZKIMGSystem::new(config(args));
+
+            let start = Instant::now();
+            let valid = system.verify_proof(&output.proof, &output.proof.public_inputs)?;
+            let verification_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            write_output(args, &VerifyOutput { valid, verification_time_ms })?;
+            Ok(valid)
+        }
+        "setup" => {
+            let (image, transformations) = read_input(&args.input)?;
+            let (width, height) = image.dimensions();
+            let config = prover_config(args, &image, &transformations)?;
+            let k = config.k;
+            let mut system = ZKIMGSystem::new(config);
+
+            let verification_key = system.setup(width, height, &transformations)?;
+            let output = SetupOutput {
+                circuit_id: system.circuit_id(width, height, &transformations)?.to_string(),
+                k,
+                width,
+                height,
+                transformation_chain: transformations,
+                verification_key,
+                metrics: system.last_metrics().clone(),
+            };
+            write_output(args, &output)?;
+            Ok(true)
+        }
+        "metrics" => {
+            let (image, transformations) = read_input(&args.input)?;
+            let mut system = ZKIMGSystem::new(prover_config(args, &image, &transformations)?);
+
+            let proof = system.prove_transformation_chain(&image, &transformations)?;
+            let start = Instant::now();
+            let valid = system.verify_proof(&proof, &proof.public_inputs)?;
+
+            let mut metrics = system.last_metrics().clone();
+            metrics.verification_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+            write_output(args, &MetricsOutput { valid, metrics })?;
+            Ok(valid)
+        }
+        other => Err(anyhow!("Unknown command: {}", other)),
+    }
+}
+
+fn config(args: &Args) -> ZKIMGConfig {
+    let mut config = ZKIMGConfig::default();
+    if let Some(k) = args.k {
+        config.k = k;
+    }
+    config.params_dir = args.params_dir.clone();
+    config.params_hash = args.params_hash;
+    config.vk_registry_dir = args.vk_registry.clone();
+    if args.size_classes {
+        config.size_classes = SizeClass::STANDARD.to_vec();
+        // Every image that fits a class is accepted
+        let largest = SizeClass::STANDARD.iter().map(|class| class.width as usize * class.height as usize).max();
+        config.max_image_size = config.max_image_size.max(largest.unwrap_or(0));
+    }
+    config
+}
+
+/// `config` for proving `transformations` on `image`, with k raised to fit
+/// its size class unless `--k` was given
+fn prover_config(args: &Args, image: &DynamicImage, transformations: &[Transformation]) -> Result<ZKIMGConfig> {
+    let mut config = config(args);
+    if args.size_classes && args.k.is_none() {
+        let (width, height) = image.dimensions();
+        let required = ZKIMGSystem::new(config.clone()).required_k(width, height, transformations)?;
+        config.k = config.k.max(required);
+    }
+    Ok(config)
+}
+
+fn read_input(path: &str) -> Result<(DynamicImage, Vec<Transformation>)> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let input: ProveInput = serde_json::from_str(&contents).context("Invalid input JSON")?;
+
+    let image_bytes = base64::engine::general_purpose::STANDARD
+        .decode(input.image.trim())
+        .context("Image is not valid base64")?;
+    let image = image::load_from_memory(&image_bytes).context("Failed to decode image")?;
+
+    let transformations = input
+        .transformations
+        .into_iter()
+        .map(parse_transformation)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((image, transformations))
+}
+
+/// Accept both the wrapper's `{ "type": "Crop", "x": .. }` objects and the
+/// crate's native serde encoding of `Transformation`
+fn parse_transformation(value: Value) -> Result<Transformation> {
+    let native = match value {
+        Value::Object(mut fields) if fields.contains_key("type") => {
+            let name = match fields.remove("type") {
+                Some(Value::String(name)) => name,
+                other => return Err(anyhow!("Invalid transformation type: {:?}", other)),
+            };
+
+            if fields.is_empty() {
+                Value::String(name)
+            } else if let (1, Some(value)) = (fields.len(), fields.get("value")) {
+                // Newtype variants such as `Contrast(Rational)`
+                serde_json::json!({ name: value })
+            } else {
+                serde_json::json!({ name: fields })
+            }
+        }
+        other => other,
+    };
+
+    serde_json::from_value(native.clone())
+        .with_context(|| format!("Unsupported transformation: {}", native))
+}
+
+fn write_output<T: Serialize>(args: &Args, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    match &args.output {
+        Some(path) => {
+            fs::write(path, json).with_context(|| format!("Failed to write {}", path))?;
+            eprintln!("💾 Output written to {}", path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! Persistent IPA commitment parameters
+//!
+//! `Params::new(k)` derives its generators by hashing to the curve, which
+//! takes seconds at k=17. Params are deterministic, so they are generated
+//! once and kept on disk in halo2's binary format (`Params::write`), in a
+//! cache directory with one `params-k{k}.bin` file per k.
+//!
+//! Generator `i` does not depend on k, so params for a smaller k are the
+//! first `2^k` generators of larger ones with the Lagrange basis recomputed.
+//! The cache downgrades the smallest larger file it holds rather than
+//! generating from scratch. Verifiers can pin params by the SHA-256 of
+//! their encoding (`params_hash`), see `ParamsCache::pinned`.
+
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use ff::{Field, PrimeField};
+use group::{prime::PrimeCurveAffine, Curve, GroupEncoding};
+use halo2_proofs::{
+    arithmetic::best_fft,
+    pasta::{Eq, EqAffine, Fp},
+    poly::commitment::Params,
+};
+use sha2::{Digest, Sha256};
+
+/// Encoded size of one curve point
+const POINT_SIZE: usize = 32;
+
+/// Write `params` in halo2's binary format
+pub fn write_params(params: &Params<EqAffine>, path: &Path) -> Result<()> {
+    // Write to a temporary file of this writer's own first, so concurrent
+    // readers never see a partial file and concurrent writers never share one
+    let partial = path.with_extension(format!("{}-{:016x}.partial", std::process::id(), rand::random::<u64>()));
+    let file = fs::File::create(&partial).with_context(|| format!("Failed to create {}", partial.display()))?;
+    let mut writer = BufWriter::new(file);
+    params.write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&partial, path).with_context(|| {
+        let _ = fs::remove_file(&partial);
+        format!("Failed to write {}", path.display())
+    })
+}
+
+/// Read params written by `write_params`
+pub fn read_params(path: &Path) -> Result<Params<EqAffine>> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Params::read(&mut BufReader::new(file)).with_context(|| format!("Invalid params file {}", path.display()))
+}
+
+/// Read params, failing unless the file's SHA-256 is `expected`
+pub fn read_pinned_params(path: &Path, expected: &[u8; 32]) -> Result<Params<EqAffine>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let hash: [u8; 32] = Sha256::digest(&bytes).into();
+    if hash != *expected {
+        return Err(anyhow!("Params file {} does not have the pinned hash", path.display()));
+    }
+    Params::read(&mut bytes.as_slice()).with_context(|| format!("Invalid params file {}", path.display()))
+}
+
+/// SHA-256 of the binary encoding of `params`, the hash to pin them by
+pub fn params_hash(params: &Params<EqAffine>) -> Result<[u8; 32]> {
+    let mut bytes = Vec::new();
+    params.write(&mut bytes)?;
+    Ok(Sha256::digest(&bytes).into())
+}
+
+/// Fail unless `params_hash(params)` is `expected`
+pub fn check_params_hash(params: &Params<EqAffine>, expected: &[u8; 32]) -> Result<()> {
+    if params_hash(params)? != *expected {
+        return Err(anyhow!("Params for k={} do not have the pinned hash", params.k()));
+    }
+    Ok(())
+}
+
+/// Parse a SHA-256 hash written as 64 hex digits
+pub fn parse_hash(hex: &str) -> Result<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(anyhow!("Expected a hash of 64 hex digits, got {}", hex));
+    }
+    let mut bytes = [0u8; 32];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair)?;
+        *byte = u8::from_str_radix(pair, 16).with_context(|| format!("Invalid hash {}", hex))?;
+    }
+    Ok(bytes)
+}
+
+/// The params `Params::new(k)` would generate, taken from larger params
+pub fn downgrade(params: &Params<EqAffine>, k: u32) -> Result<Params<EqAffine>> {
+    if k > params.k() {
+        return Err(anyhow!("Cannot downgrade params for k={} to k={}", params.k(), k));
+    }
+    if k == params.k() {
+        return Ok(params.clone());
+    }
+
+    let n = 1usize << k;
+    let g = &params.get_g()[..n];
+
+    // Lagrange basis: inverse FFT of the generators over the 2^k domain
+    let mut omega_inv = Fp::ROOT_OF_UNITY_INV;
+    for _ in k..Fp::S {
+        omega_inv = omega_inv.square();
+    }
+    let mut g_lagrange: Vec<Eq> = g.iter().map(|point| point.to_curve()).collect();
+    best_fft(&mut g_lagrange, omega_inv, k);
+    let n_inv = Fp::TWO_INV.pow_vartime([k as u64]);
+    for point in g_lagrange.iter_mut() {
+        *point *= n_inv;
+    }
+    let mut g_lagrange_affine = vec![EqAffine::identity(); n];
+    Eq::batch_normalize(&g_lagrange, &mut g_lagrange_affine);
+
+    // `w` and `u` close the encoding and do not depend on k
+    let mut encoded = Vec::new();
+    params.write(&mut encoded)?;
+    let w_and_u = &encoded[encoded.len() - 2 * POINT_SIZE..];
+
+    let mut bytes = Vec::with_capacity(4 + (2 * n + 2) * POINT_SIZE);
+    bytes.extend_from_slice(&k.to_le_bytes());
+    for point in g.iter().chain(&g_lagrange_affine) {
+        bytes.extend_from_slice(point.to_bytes().as_ref());
+    }
+    bytes.extend_from_slice(w_and_u);
+    Ok(Params::read(&mut bytes.as_slice())?)
+}
+
+/// Directory of params files, one per k
+#[derive(Clone, Debug)]
+pub struct ParamsCache {
+    dir: PathBuf,
+    pinned: Option<[u8; 32]>,
+}
+
+impl ParamsCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), pinned: None }
+    }
+
+    /// Only return params whose `params_hash` is `hash`, whether they are
+    /// read, downgraded or generated
+    pub fn pinned(mut self, hash: [u8; 32]) -> Self {
+        self.pinned = Some(hash);
+        self
+    }
+
+    /// File holding the params for `k`
+    pub fn path(&self, k: u32) -> PathBuf {
+        self.dir.join(format!("params-k{}.bin", k))
+    }
+
+    /// Params for `k`: read from the cache, downgraded from the smallest
+    /// larger params in it, or generated, storing the result in the latter
+    /// two cases
+    pub fn load(&self, k: u32) -> Result<Params<EqAffine>> {
+        let path = self.path(k);
+        if path.exists() {
+            eprintln!("📂 Loading params for k={} from {}", k, path.display());
+            return self.read(k, self.pinned.as_ref());
+        }
+
+        let params = match (k + 1..32).find(|&larger| self.path(larger).exists()) {
+            Some(larger) => {
+                eprintln!("📂 Downgrading cached params from k={} to k={}", larger, k);
+                downgrade(&self.read(larger, None)?, k)?
+            }
+            None => {
+                eprintln!("🔧 Generating params for k={}", k);
+                Params::new(k)
+            }
+        };
+        if let Some(hash) = &self.pinned {
+            check_params_hash(&params, hash)?;
+        }
+
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        write_params(&params, &path)?;
+        eprintln!("💾 Params for k={} cached at {}", k, path.display());
+        Ok(params)
+    }
+
+    /// Read the cached file for `k`, failing if it holds params for another k
+    fn read(&self, k: u32, pinned: Option<&[u8; 32]>) -> Result<Params<EqAffine>> {
+        let path = self.path(k);
+        let params = match pinned {
+            Some(hash) => read_pinned_params(&path, hash)?,
+            None => read_params(&path)?,
+        };
+        if params.k() != k {
+            return Err(anyhow!("{} holds params for k={}, not k={}", path.display(), params.k(), k));
+        }
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zkimg-params-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn downgraded_params_match_generated_ones() {
+        let large = Params::<EqAffine>::new(6);
+        for k in [4, 5, 6] {
+            assert_eq!(params_hash(&downgrade(&large, k).unwrap()).unwrap(), params_hash(&Params::new(k)).unwrap());
+        }
+        assert!(downgrade(&large, 7).is_err());
+    }
+
+    #[test]
+    fn cache_reuses_and_downgrades_stored_params() {
+        let dir = cache_dir("cache");
+        let cache = ParamsCache::new(&dir);
+
+        let generated = cache.load(5).unwrap();
+        assert!(cache.path(5).exists());
+        let hash = params_hash(&generated).unwrap();
+        assert_eq!(params_hash(&cache.load(5).unwrap()).unwrap(), hash);
+
+        // Downgraded from the k=5 file rather than generated
+        let smaller = cache.load(3).unwrap();
+        assert!(cache.path(3).exists());
+        assert_eq!(params_hash(&smaller).unwrap(), params_hash(&Params::new(3)).unwrap());
+
+        assert!(read_pinned_params(&cache.path(5), &hash).is_ok());
+        assert!(read_pinned_params(&cache.path(3), &hash).is_err());
+
+        // Pinned caches reject other params, read, downgraded or generated
+        let pinned = ParamsCache::new(&dir).pinned(hash);
+        assert!(pinned.load(5).is_ok());
+        assert!(pinned.load(3).is_err());
+        assert!(pinned.load(2).is_err());
+        assert!(!pinned.path(2).exists());
+        assert!(pinned.load(6).is_err());
+        assert_eq!(parse_hash(&hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()).unwrap(), hash);
+        assert!(parse_hash("zz").is_err());
+
+        // A file holding params for another k is rejected
+        fs::copy(cache.path(5), cache.path(4)).unwrap();
+        assert!(cache.load(4).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
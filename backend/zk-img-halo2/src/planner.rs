@@ -0,0 +1,481 @@
+//! Fusion planner
+//!
+//! Rewrites a transformation chain into a cheaper one with the same output:
+//!
+//! - identity steps (full-image crops, same-size resizes, zero shifts and
+//!   tone maps that change nothing) are dropped
+//! - consecutive crops are merged into one
+//! - runs of rotations and flips are composed and written as at most a flip
+//!   followed by a rotation
+//! - runs of brightness and contrast are folded into a single `ToneMap` when
+//!   no intermediate rounding or clamping is lost, checked on all 256 inputs
+//! - adjacent crop + resize and grayscale + contrast become their fused
+//!   variants
+//!
+//! Every rewrite is exact, so the planned chain produces the same image as
+//! the original bit for bit, and the circuit proves the planned chain.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::circuits::ZKIMGCircuit;
+use crate::cost::CircuitCost;
+use crate::transforms::{Rational, RightAngle, ToneMap, MAX_TONE_SHIFT};
+use crate::Transformation;
+
+/// A planned chain together with the layout cost it saves
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FusionPlan {
+    pub original: Vec<Transformation>,
+    pub planned: Vec<Transformation>,
+    /// Cost of proving `original`
+    pub before: CircuitCost,
+    /// Cost of proving `planned`
+    pub after: CircuitCost,
+}
+
+/// Plan `transformations` for a `width` x `height` image and measure both chains
+pub fn plan(width: u32, height: u32, transformations: &[Transformation]) -> Result<FusionPlan> {
+    for transformation in transformations {
+        transformation.validate()?;
+    }
+
+    let planned = optimize(width, height, transformations);
+    let cost = |chain: &[Transformation]| ZKIMGCircuit::blank(width as usize, height as usize, chain.to_vec()).cost();
+
+    Ok(FusionPlan {
+        before: cost(transformations)?,
+        after: cost(&planned)?,
+        original: transformations.to_vec(),
+        planned,
+    })
+}
+
+/// Normalize, then fuse
+pub fn optimize(width: u32, height: u32, transformations: &[Transformation]) -> Vec<Transformation> {
+    fuse(&normalize(width, height, transformations))
+}
+
+/// Apply the exact rewrites until the chain stops shrinking
+pub fn normalize(width: u32, height: u32, transformations: &[Transformation]) -> Vec<Transformation> {
+    let mut chain = normalize_pass(width, height, transformations);
+    loop {
+        let next = normalize_pass(width, height, &chain);
+        if next.len() >= chain.len() {
+            return chain;
+        }
+        chain = next;
+    }
+}
+
+/// Replace adjacent pairs that have a fused variant
+pub fn fuse(transformations: &[Transformation]) -> Vec<Transformation> {
+    let mut fused = Vec::new();
+    let mut i = 0;
+
+    while i < transformations.len() {
+        if let Some(next) = transformations.get(i + 1) {
+            match (&transformations[i], next) {
+                (
+                    Transformation::Crop { x, y, width, height },
+                    Transformation::Resize { width: resize_width, height: resize_height, filter },
+                ) => {
+                    fused.push(Transformation::CropResize {
+                        crop_x: *x,
+                        crop_y: *y,
+                        crop_width: *width,
+                        crop_height: *height,
+                        resize_width: *resize_width,
+                        resize_height: *resize_height,
+                        filter: *filter,
+                    });
+                    i += 2;
+                    continue;
+                }
+                (Transformation::Grayscale, Transformation::Contrast(contrast)) => {
+                    fused.push(Transformation::GrayscaleContrast { contrast: *contrast });
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        fused.push(transformations[i].clone());
+        i += 1;
+    }
+
+    fused
+}
+
+fn normalize_pass(width: u32, height: u32, chain: &[Transformation]) -> Vec<Transformation> {
+    let mut out: Vec<Transformation> = Vec::with_capacity(chain.len());
+    let mut size = (width, height);
+    let mut i = 0;
+
+    while i < chain.len() {
+        let step = &chain[i];
+
+        if Symmetry::of(step).is_some() {
+            let mut symmetry = Symmetry::default();
+            while let Some(next) = chain.get(i).and_then(Symmetry::of) {
+                symmetry = symmetry.then(next);
+                i += 1;
+            }
+            for transformation in symmetry.transformations() {
+                size = transformation.output_size(size.0, size.1);
+                out.push(transformation);
+            }
+            continue;
+        }
+
+        if let Some(mut tone) = tone_of(step) {
+            let mut folded = 1;
+            i += 1;
+            while let Some(folded_tone) = chain.get(i).and_then(tone_of).and_then(|next| fold(&tone, &next)) {
+                tone = folded_tone;
+                folded += 1;
+                i += 1;
+            }
+
+            if tone.table() != IDENTITY_TABLE {
+                out.push(if folded == 1 { step.clone() } else { Transformation::ToneMap(tone) });
+            }
+            continue;
+        }
+
+        i += 1;
+        if is_identity(step, size) {
+            continue;
+        }
+        size = step.output_size(size.0, size.1);
+
+        if let (
+            Transformation::Crop { x, y, width, height },
+            Some(Transformation::Crop { x: outer_x, y: outer_y, width: outer_width, height: outer_height }),
+        ) = (step, out.last())
+        {
+            let fits = *x as u64 + *width as u64 <= *outer_width as u64
+                && *y as u64 + *height as u64 <= *outer_height as u64;
+            if fits {
+                let merged = Transformation::Crop {
+                    x: outer_x + x,
+                    y: outer_y + y,
+                    width: *width,
+                    height: *height,
+                };
+                out.pop();
+                out.push(merged);
+                continue;
+            }
+        }
+
+        out.push(step.clone());
+    }
+
+    out
+}
+
+const IDENTITY_TABLE: [u8; 256] = {
+    let mut table = [0; 256];
+    let mut x = 0;
+    while x < 256 {
+        table[x] = x as u8;
+        x += 1;
+    }
+    table
+};
+
+fn is_identity(transformation: &Transformation, (width, height): (u32, u32)) -> bool {
+    match *transformation {
+        Transformation::Crop { x, y, width: w, height: h } => (x, y, w, h) == (0, 0, width, height),
+        Transformation::Resize { width: w, height: h, .. } => (w, h) == (width, height),
+        Transformation::CropResize {
+            crop_x,
+            crop_y,
+            crop_width,
+            crop_height,
+            resize_width,
+            resize_height,
+            ..
+        } => {
+            (crop_x, crop_y, crop_width, crop_height) == (0, 0, width, height)
+                && (resize_width, resize_height) == (width, height)
+        }
+        Transformation::Translate { dx: 0, dy: 0, .. } => true,
+        _ => false,
+    }
+}
+
+fn tone_of(transformation: &Transformation) -> Option<ToneMap> {
+    match transformation {
+        Transformation::Contrast(contrast) => Some(ToneMap::contrast(*contrast)),
+        Transformation::Brightness(brightness) => Some(ToneMap::brightness(*brightness)),
+        Transformation::ToneMap(tone) => Some(*tone),
+        _ => None,
+    }
+}
+
+/// `second` after `first` as one tone map, if that is exact
+///
+/// A map with unit contrast adds a whole number, which commutes with the
+/// rounding of the other map; two non-trivial contrasts round twice and are
+/// left alone. Intermediate clamping is checked by comparing all outputs.
+fn fold(first: &ToneMap, second: &ToneMap) -> Option<ToneMap> {
+    let limit = |value: i32| value.clamp(-MAX_TONE_SHIFT, MAX_TONE_SHIFT);
+    let candidate = if first.contrast == Rational::ONE {
+        ToneMap {
+            shift: limit(first.shift + first.offset + second.shift),
+            ..*second
+        }
+    } else if second.contrast == Rational::ONE {
+        ToneMap {
+            offset: limit(first.offset + second.shift + second.offset),
+            ..*first
+        }
+    } else {
+        return None;
+    };
+
+    let (first, second, folded) = (first.table(), second.table(), candidate.table());
+    (0..256)
+        .all(|x| folded[x] == second[first[x] as usize])
+        .then_some(candidate)
+}
+
+/// Symmetry of the rectangle: an optional horizontal flip followed by
+/// `turns` clockwise quarter turns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Symmetry {
+    flip: bool,
+    turns: u8,
+}
+
+impl Symmetry {
+    fn of(transformation: &Transformation) -> Option<Self> {
+        match transformation {
+            Transformation::Rotate { degrees } => {
+                let turns = match RightAngle::from_degrees(*degrees).ok()? {
+                    RightAngle::Deg0 => 0,
+                    RightAngle::Deg90 => 1,
+                    RightAngle::Deg180 => 2,
+                    RightAngle::Deg270 => 3,
+                };
+                Some(Self { flip: false, turns })
+            }
+            Transformation::FlipHorizontal => Some(Self { flip: true, turns: 0 }),
+            // A vertical flip is a horizontal one turned half way round
+            Transformation::FlipVertical => Some(Self { flip: true, turns: 2 }),
+            _ => None,
+        }
+    }
+
+    /// `self` followed by `next`
+    fn then(self, next: Self) -> Self {
+        // Flipping reverses the direction of the rotations before it
+        if next.flip {
+            Self {
+                flip: !self.flip,
+                turns: (next.turns + 4 - self.turns) % 4,
+            }
+        } else {
+            Self {
+                flip: self.flip,
+                turns: (self.turns + next.turns) % 4,
+            }
+        }
+    }
+
+    fn transformations(self) -> Vec<Transformation> {
+        let rotate = Transformation::Rotate {
+            degrees: self.turns as f32 * 90.0,
+        };
+        match (self.flip, self.turns) {
+            (false, 0) => vec![],
+            (false, _) => vec![rotate],
+            (true, 0) => vec![Transformation::FlipHorizontal],
+            (true, 2) => vec![Transformation::FlipVertical],
+            (true, _) => vec![Transformation::FlipHorizontal, rotate],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::ResizeFilter;
+    use image::{DynamicImage, RgbImage};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn test_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 53 + y * 7) as u8, (y * 61 + x * 3) as u8, ((x * y) * 29 + 11) as u8])
+        }))
+    }
+
+    fn apply(image: &DynamicImage, chain: &[Transformation]) -> RgbImage {
+        chain
+            .iter()
+            .try_fold(image.clone(), |image, transformation| transformation.apply(&image))
+            .unwrap()
+            .to_rgb8()
+    }
+
+    fn ratio(numerator: i64, denominator: i64) -> Rational {
+        Rational::new(numerator, denominator).unwrap()
+    }
+
+    fn random_rational(rng: &mut StdRng, magnitude: i64) -> Rational {
+        let denominator = rng.gen_range(1..=12);
+        ratio(rng.gen_range(-magnitude * denominator..=magnitude * denominator), denominator)
+    }
+
+    /// A random transformation valid for a `width` x `height` input
+    fn random_step(rng: &mut StdRng, (width, height): (u32, u32)) -> Transformation {
+        match rng.gen_range(0..12) {
+            0 => {
+                let (w, h) = (rng.gen_range(1..=width), rng.gen_range(1..=height));
+                Transformation::Crop {
+                    x: rng.gen_range(0..=width - w),
+                    y: rng.gen_range(0..=height - h),
+                    width: w,
+                    height: h,
+                }
+            }
+            1 => Transformation::Crop { x: 0, y: 0, width, height },
+            2 => Transformation::Resize {
+                width: rng.gen_range(1..=7),
+                height: rng.gen_range(1..=7),
+                filter: if rng.gen() { ResizeFilter::Bilinear } else { ResizeFilter::Nearest },
+            },
+            3 => Transformation::Rotate {
+                degrees: 90.0 * rng.gen_range(-4..=4) as f32,
+            },
+            4 => Transformation::FlipHorizontal,
+            5 => Transformation::FlipVertical,
+            6 => Transformation::Translate {
+                dx: rng.gen_range(-2..=2),
+                dy: rng.gen_range(-2..=2),
+                fill: [3, 140, 250],
+            },
+            7 => Transformation::Brightness(random_rational(rng, 1)),
+            8 => Transformation::Contrast(random_rational(rng, 3)),
+            9 => Transformation::ToneMap(ToneMap {
+                shift: rng.gen_range(-255..=255),
+                contrast: random_rational(rng, 3),
+                offset: rng.gen_range(-255..=255),
+            }),
+            10 => Transformation::Grayscale,
+            _ => Transformation::Resize {
+                width,
+                height,
+                filter: ResizeFilter::Bilinear,
+            },
+        }
+    }
+
+    fn random_chain(rng: &mut StdRng, size: (u32, u32)) -> Vec<Transformation> {
+        let mut size = size;
+        (0..rng.gen_range(0..10))
+            .map(|_| {
+                let step = random_step(rng, size);
+                size = step.output_size(size.0, size.1);
+                step
+            })
+            .collect()
+    }
+
+    #[test]
+    fn planned_chains_match_native_output() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..400 {
+            let (width, height) = (rng.gen_range(1..=6), rng.gen_range(1..=6));
+            let image = test_image(width, height);
+            let chain = random_chain(&mut rng, (width, height));
+
+            let planned = optimize(width, height, &chain);
+            assert!(planned.len() <= chain.len(), "{:?} grew into {:?}", chain, planned);
+            assert_eq!(apply(&image, &planned), apply(&image, &chain), "{:?} planned as {:?}", chain, planned);
+        }
+    }
+
+    #[test]
+    fn symmetries_compose_like_the_images() {
+        let image = test_image(5, 3);
+        let steps = [
+            Transformation::Rotate { degrees: 90.0 },
+            Transformation::Rotate { degrees: 180.0 },
+            Transformation::Rotate { degrees: 270.0 },
+            Transformation::FlipHorizontal,
+            Transformation::FlipVertical,
+        ];
+
+        for first in &steps {
+            for second in &steps {
+                for third in &steps {
+                    let chain = vec![first.clone(), second.clone(), third.clone()];
+                    let planned = normalize(5, 3, &chain);
+                    assert!(planned.len() <= 2, "{:?}", planned);
+                    assert_eq!(apply(&image, &planned), apply(&image, &chain), "{:?}", chain);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_shorten_the_chain() {
+        let chain = [
+            Transformation::Crop { x: 1, y: 1, width: 6, height: 5 },
+            Transformation::Crop { x: 2, y: 0, width: 3, height: 3 },
+            Transformation::FlipHorizontal,
+            Transformation::FlipHorizontal,
+            Transformation::Rotate { degrees: 90.0 },
+            Transformation::Rotate { degrees: 270.0 },
+            Transformation::Translate { dx: 0, dy: 0, fill: [0; 3] },
+            Transformation::Contrast(ratio(1, 2)),
+            Transformation::Brightness(ratio(1, 10)),
+            Transformation::Brightness(ratio(-1, 20)),
+            Transformation::Resize { width: 3, height: 3, filter: ResizeFilter::Nearest },
+        ];
+
+        let planned = normalize(8, 8, &chain);
+        assert!(matches!(
+            planned.as_slice(),
+            [Transformation::Crop { x: 3, y: 1, width: 3, height: 3 }, Transformation::ToneMap(_)]
+        ));
+
+        let image = test_image(8, 8);
+        assert_eq!(apply(&image, &planned), apply(&image, &chain));
+    }
+
+    #[test]
+    fn lossy_tone_folds_are_refused() {
+        // Two contrasts round twice
+        let halves = [Transformation::Contrast(ratio(1, 2)), Transformation::Contrast(ratio(1, 2))];
+        assert_eq!(normalize(4, 4, &halves).len(), 2);
+
+        // Inverting after a brightness that saturates depends on the clamp
+        let clamped = [Transformation::Brightness(ratio(1, 2)), Transformation::Contrast(ratio(-1, 1))];
+        let image = test_image(4, 4);
+        assert_eq!(apply(&image, &normalize(4, 4, &clamped)), apply(&image, &clamped));
+    }
+
+    #[test]
+    fn plan_reports_lower_cost() {
+        let chain = [
+            Transformation::Crop { x: 0, y: 0, width: 5, height: 4 },
+            Transformation::Crop { x: 1, y: 1, width: 3, height: 3 },
+            Transformation::Resize { width: 4, height: 4, filter: ResizeFilter::Bilinear },
+            Transformation::Contrast(ratio(1, 3)),
+            Transformation::Brightness(ratio(1, 5)),
+        ];
+
+        let plan = plan(6, 5, &chain).unwrap();
+        assert!(matches!(
+            plan.planned.as_slice(),
+            [Transformation::CropResize { crop_x: 1, crop_y: 1, .. }, Transformation::ToneMap(_)]
+        ));
+        assert!(plan.after.advice_cells < plan.before.advice_cells);
+        assert!(plan.after.gate_rows < plan.before.gate_rows);
+    }
+}
@@ -1,18 +1,33 @@
 //! ZK-IMG Proof System using halo2
 //!
 //! Implements the proof generation and verification as described in Section 6 and 8
+//!
+//! halo2_proofs 0.3.5 has no key serialization, so the key files this module
+//! saves and loads are `keys` headers, not caches: loading one reruns keygen
+//! after checking the circuit against the header.
 
 use halo2_proofs::{
     pasta::{Fp, EqAffine},
-    plonk::{keygen_pk, keygen_vk, create_proof, verify_proof, ProvingKey, VerifyingKey, Circuit},
+    plonk::{
+        keygen_pk, keygen_vk, create_proof, verify_proof, BatchVerifier, ProvingKey, VerifyingKey, Circuit, SingleVerifier,
+    },
     poly::commitment::Params,
     transcript::{Blake2bWrite, Blake2bRead, Challenge255},
 };
-use halo2curves::pasta::pallas;
+use ff::PrimeField;
+use image::DynamicImage;
 use rand::rngs::OsRng;
-use std::fs;
+use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
 
+use crate::circuits::{HDImageCircuit, ZKIMGCircuit};
+use crate::keys::{KeyFileHeader, KeyKind, KeyedCircuit};
+use crate::params::ParamsCache;
+use crate::tiling::{Tile, TileLayout};
+use crate::transforms::image_to_field_elements;
+
 /// ZK-IMG Proof System
 pub struct ZKIMGProofSystem {
     params: Params<EqAffine>,
@@ -22,7 +37,7 @@ pub struct ZKIMGProofSystem {
 impl ZKIMGProofSystem {
     /// Create new proof system with given circuit size
     pub fn new(k: u32) -> Result<Self> {
-        println!("🔧 Initializing ZK-IMG proof system with k={}", k);
+        eprintln!("🔧 Initializing ZK-IMG proof system with k={}", k);
 
         // Generate trusted setup parameters
         let params: Params<EqAffine> = Params::new(k);
@@ -30,17 +45,40 @@ impl ZKIMGProofSystem {
         Ok(Self { params, k })
     }
 
+    /// Proof system over existing params, such as ones read from disk
+    pub fn from_params(params: Params<EqAffine>) -> Self {
+        let k = params.k();
+        Self { params, k }
+    }
+
+    /// Proof system with the params for `k` from `cache`, generating and
+    /// storing them only if the cache cannot provide them
+    pub fn cached(k: u32, cache: &ParamsCache) -> Result<Self> {
+        eprintln!("🔧 Initializing ZK-IMG proof system with k={}", k);
+        Ok(Self::from_params(cache.load(k)?))
+    }
+
+    /// Circuit size parameter this system was created with
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    /// Commitment parameters shared by prover and verifier
+    pub fn params(&self) -> &Params<EqAffine> {
+        &self.params
+    }
+
     /// Generate proving and verifying keys for a circuit
     pub fn setup<C: Circuit<Fp>>(
         &self,
         circuit: &C,
     ) -> Result<(ProvingKey<EqAffine>, VerifyingKey<EqAffine>)> {
-        println!("🔑 Generating proving and verifying keys...");
+        eprintln!("🔑 Generating proving and verifying keys...");
 
         let vk = keygen_vk(&self.params, circuit)?;
         let pk = keygen_pk(&self.params, vk.clone(), circuit)?;
 
-        println!("✅ Keys generated successfully");
+        eprintln!("✅ Keys generated successfully");
         Ok((pk, vk))
     }
 
@@ -51,10 +89,10 @@ impl ZKIMGProofSystem {
         circuit: C,
         public_inputs: &[Fp],
     ) -> Result<Vec<u8>> {
-        println!("🧮 Generating ZK proof...");
+        eprintln!("🧮 Generating ZK proof...");
 
         let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-        let proof = create_proof(
+        create_proof(
             &self.params,
             pk,
             &[circuit],
@@ -65,7 +103,7 @@ impl ZKIMGProofSystem {
 
         let proof_bytes = transcript.finalize();
 
-        println!("✅ Proof generated: {} bytes", proof_bytes.len());
+        eprintln!("✅ Proof generated: {} bytes", proof_bytes.len());
         Ok(proof_bytes)
     }
 
@@ -76,62 +114,158 @@ impl ZKIMGProofSystem {
         proof: &[u8],
         public_inputs: &[Fp],
     ) -> Result<bool> {
-        println!("🔍 Verifying ZK proof...");
+        eprintln!("🔍 Verifying ZK proof...");
 
         let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+        let strategy = SingleVerifier::new(&self.params);
         let result = verify_proof(
             &self.params,
             vk,
+            strategy,
             &[&[public_inputs]],
             &mut transcript,
         );
 
         match result {
             Ok(_) => {
-                println!("✅ Proof verification successful");
+                eprintln!("✅ Proof verification successful");
                 Ok(true)
             }
             Err(_) => {
-                println!("❌ Proof verification failed");
+                eprintln!("❌ Proof verification failed");
                 Ok(false)
             }
         }
     }
 
-    /// Save proving key to file
-    pub fn save_proving_key(&self, pk: &ProvingKey<EqAffine>, path: &str) -> Result<()> {
-        let pk_bytes = serde_json::to_vec(pk)?;
-        fs::write(path, pk_bytes)?;
-        println!("💾 Proving key saved to {}", path);
-        Ok(())
+    /// Verify many proofs against the same verifying key
+    ///
+    /// All proofs are checked together with one final multi-scalar
+    /// multiplication. Only if that check fails is each proof verified on its
+    /// own, to tell which ones are invalid. Returns one result per
+    /// `(proof, public inputs)` pair, in order.
+    pub fn verify_batch(&self, vk: &VerifyingKey<EqAffine>, proofs: &[(Vec<u8>, Vec<Fp>)]) -> Result<Vec<bool>> {
+        if proofs.is_empty() {
+            return Ok(vec![]);
+        }
+        eprintln!("🔍 Batch verifying {} ZK proofs...", proofs.len());
+
+        let mut batch = BatchVerifier::new();
+        for (proof, public_inputs) in proofs {
+            batch.add_proof(vec![vec![public_inputs.clone()]], proof.clone());
+        }
+        if batch.finalize(&self.params, vk) {
+            eprintln!("✅ Batch of {} proofs verified", proofs.len());
+            return Ok(vec![true; proofs.len()]);
+        }
+
+        eprintln!("❌ Batch verification failed, checking proofs one by one");
+        let results = proofs
+            .iter()
+            .map(|(proof, public_inputs)| self.verify(vk, proof, public_inputs))
+            .collect::<Result<Vec<_>>>()?;
+        eprintln!("❌ {} of {} proofs are invalid", results.iter().filter(|valid| !**valid).count(), proofs.len());
+        Ok(results)
     }
 
-    /// Load proving key from file
-    pub fn load_proving_key(&self, path: &str) -> Result<ProvingKey<EqAffine>> {
-        let pk_bytes = fs::read(path)?;
-        let pk: ProvingKey<EqAffine> = serde_json::from_slice(&pk_bytes)?;
-        println!("📂 Proving key loaded from {}", path);
+    /// Save the proving key file of `circuit`
+    ///
+    /// halo2_proofs 0.3.5 cannot serialize keys, so this writes the `keys`
+    /// header that `load_proving_key` checks the circuit against before it
+    /// reruns keygen.
+    pub fn save_proving_key<C: KeyedCircuit>(&self, circuit: &C, path: &str) -> Result<()> {
+        KeyFileHeader::new(KeyKind::Proving, &self.params, circuit)?.write(path)
+    }
+
+    /// Regenerate the proving key of `circuit`, failing unless the key file
+    /// at `path` was saved for `circuit` at this `k`
+    pub fn load_proving_key<C: KeyedCircuit>(&self, path: &str, circuit: &C) -> Result<ProvingKey<EqAffine>> {
+        let header = KeyFileHeader::read(path)?;
+        header.check(KeyKind::Proving, self.k, circuit)?;
+        header.check_fingerprint(&self.params, circuit)?;
+
+        let (pk, _) = self.setup(circuit)?;
         Ok(pk)
     }
 
-    /// Save verifying key to file
-    pub fn save_verifying_key(&self, vk: &VerifyingKey<EqAffine>, path: &str) -> Result<()> {
-        let vk_bytes = serde_json::to_vec(vk)?;
-        fs::write(path, vk_bytes)?;
-        println!("💾 Verifying key saved to {}", path);
-        Ok(())
+    /// Save the verifying key file of `circuit`, a `keys` header like
+    /// `save_proving_key` writes
+    pub fn save_verifying_key<C: KeyedCircuit>(&self, circuit: &C, path: &str) -> Result<()> {
+        KeyFileHeader::new(KeyKind::Verifying, &self.params, circuit)?.write(path)
     }
 
-    /// Load verifying key from file
-    pub fn load_verifying_key(&self, path: &str) -> Result<VerifyingKey<EqAffine>> {
-        let vk_bytes = fs::read(path)?;
-        let vk: VerifyingKey<EqAffine> = serde_json::from_slice(&vk_bytes)?;
-        println!("📂 Verifying key loaded from {}", path);
-        Ok(vk)
+    /// Regenerate the verifying key of `circuit`, failing unless the key file
+    /// at `path` was saved for `circuit` at this `k`
+    pub fn load_verifying_key<C: KeyedCircuit>(&self, path: &str, circuit: &C) -> Result<VerifyingKey<EqAffine>> {
+        let header = KeyFileHeader::read(path)?;
+        header.check(KeyKind::Verifying, self.k, circuit)?;
+        header.check_fingerprint(&self.params, circuit)?;
+
+        Ok(keygen_vk(&self.params, circuit)?)
+    }
+}
+
+/// Compact reference to the verifying key a proof was created against.
+///
+/// halo2_proofs 0.3 cannot serialize a `VerifyingKey`, so proofs carry the
+/// circuit size and image shape needed to regenerate it, plus the key's
+/// `keys::vk_fingerprint` to check the regenerated key's circuit against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyingKeyRef {
+    pub k: u32,
+    pub width: u32,
+    pub height: u32,
+    pub fingerprint: [u8; 32],
+}
+
+impl VerifyingKeyRef {
+    /// Encoded size: k, width, height (u32 LE each) followed by the fingerprint
+    pub const SIZE: usize = 12 + 32;
+
+    pub fn new(k: u32, width: u32, height: u32, fingerprint: [u8; 32]) -> Self {
+        Self {
+            k,
+            width,
+            height,
+            fingerprint,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::SIZE);
+        bytes.extend_from_slice(&self.k.to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.fingerprint);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::SIZE {
+            return Err(anyhow!(
+                "Invalid verifying key reference: expected {} bytes, got {}",
+                Self::SIZE,
+                bytes.len()
+            ));
+        }
+
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+        };
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(&bytes[12..]);
+
+        Ok(Self {
+            k: read_u32(0),
+            width: read_u32(4),
+            height: read_u32(8),
+            fingerprint,
+        })
     }
 }
 
 /// Performance metrics tracker (as reported in paper)
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProofMetrics {
     pub setup_time_ms: f64,
     pub proving_time_ms: f64,
@@ -141,6 +275,12 @@ pub struct ProofMetrics {
     pub pk_size_bytes: usize,
 }
 
+impl Default for ProofMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ProofMetrics {
     pub fn new() -> Self {
         Self {
@@ -176,6 +316,12 @@ pub struct HDProcessor {
     pub max_tiles: usize,
 }
 
+impl Default for HDProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HDProcessor {
     pub fn new() -> Self {
         Self {
@@ -185,58 +331,253 @@ impl HDProcessor {
     }
 
     /// Split HD image into tiles for efficient processing
-    pub fn tile_hd_image(&self, width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
-        let mut tiles = Vec::new();
-
-        for y in (0..height).step_by(self.tile_size) {
-            for x in (0..width).step_by(self.tile_size) {
-                let tile_width = std::cmp::min(self.tile_size as u32, width - x);
-                let tile_height = std::cmp::min(self.tile_size as u32, height - y);
+    ///
+    /// Returns `(x, y, width, height)` of every tile, or an error if the image
+    /// needs more than `max_tiles` of them.
+    pub fn tile_hd_image(&self, width: u32, height: u32) -> Result<Vec<(u32, u32, u32, u32)>> {
+        let layout = TileLayout::new(width, height, self.tile_size as u32, 0, self.max_tiles)?;
+
+        eprintln!("🎯 HD image tiled into {} tiles", layout.tiles.len());
+        Ok(layout
+            .tiles
+            .iter()
+            .map(|tile| (tile.core.x, tile.core.y, tile.core.width, tile.core.height))
+            .collect())
+    }
 
-                tiles.push((x, y, tile_width, tile_height));
+    /// Tile layout for proving `transformations` on a `width` x `height` image,
+    /// with the halo the chain needs
+    pub fn layout(&self, width: u32, height: u32, transformations: &[crate::Transformation]) -> Result<TileLayout> {
+        TileLayout::for_chain(width, height, self.tile_size as u32, self.max_tiles, transformations)
+    }
 
-                if tiles.len() >= self.max_tiles {
-                    break;
-                }
-            }
-            if tiles.len() >= self.max_tiles {
-                break;
+    /// Prove `transformations` on `image` tile by tile and aggregate the tile proofs
+    pub fn prove_tiles(
+        &self,
+        proof_system: &ZKIMGProofSystem,
+        image: &DynamicImage,
+        transformations: &[crate::Transformation],
+    ) -> Result<AggregatedTileProof> {
+        let hd = HDImageCircuit::new(
+            image_to_field_elements(image),
+            transformations.to_vec(),
+            self.tile_size as u32,
+            self.max_tiles,
+        )?;
+        let circuits = hd.process_hd_tiles()?;
+
+        // Tiles of the same size share their keys
+        let mut keys: HashMap<String, ProvingKey<EqAffine>> = HashMap::new();
+        let mut tile_proofs = Vec::with_capacity(circuits.len());
+        for (tile, circuit) in hd.layout.tiles.iter().zip(circuits) {
+            let shape = format!("{}x{}:{:?}", tile.input.width, tile.input.height, circuit.transformations);
+            if !keys.contains_key(&shape) {
+                let (pk, _) = proof_system.setup(&circuit)?;
+                keys.insert(shape.clone(), pk);
             }
+
+            let public_inputs = circuit.public_inputs()?;
+            let proof_bytes = proof_system.prove(&keys[&shape], circuit, &public_inputs)?;
+            tile_proofs.push(TileProof {
+                tile: *tile,
+                proof_bytes,
+                public_inputs,
+            });
         }
 
-        println!("🎯 HD image tiled into {} tiles", tiles.len());
-        tiles
+        self.aggregate_tile_proofs(hd.layout, transformations, proof_system.k(), tile_proofs)
     }
 
     /// Aggregate proofs from multiple tiles
-    pub fn aggregate_tile_proofs(&self, tile_proofs: Vec<Vec<u8>>) -> Result<Vec<u8>> {
-        // Implement Merkle tree aggregation of tile proofs
-        // This is a simplified version - full implementation would use proper Merkle tree
-
+    ///
+    /// Tile proofs must be in `layout.tiles` order. The result commits to every
+    /// tile's coordinates, proof and image digests in one Merkle tree, and to
+    /// the input and output images through the tile commitments of `layout`.
+    pub fn aggregate_tile_proofs(
+        &self,
+        layout: TileLayout,
+        transformations: &[crate::Transformation],
+        k: u32,
+        tile_proofs: Vec<TileProof>,
+    ) -> Result<AggregatedTileProof> {
         if tile_proofs.is_empty() {
             return Err(anyhow!("No tile proofs to aggregate"));
         }
+        if tile_proofs.len() != layout.tiles.len() {
+            return Err(anyhow!("Expected {} tile proofs, got {}", layout.tiles.len(), tile_proofs.len()));
+        }
+        for (index, (proof, tile)) in tile_proofs.iter().zip(&layout.tiles).enumerate() {
+            if proof.tile != *tile || proof.public_inputs.len() != 3 {
+                return Err(anyhow!("Tile proof {} does not belong to tile {:?}", index, tile.core));
+            }
+        }
+
+        let (input_root, output_root) = tile_roots(&layout, &tile_proofs)?;
+        let proof_root = tile_proof_root(&tile_proofs);
+
+        eprintln!("🔗 Aggregated {} tile proofs", tile_proofs.len());
+        Ok(AggregatedTileProof {
+            layout,
+            transformations: transformations.to_vec(),
+            k,
+            tiles: tile_proofs,
+            input_root,
+            output_root,
+            proof_root,
+        })
+    }
 
-        if tile_proofs.len() == 1 {
-            return Ok(tile_proofs[0].clone());
+    /// Check every tile proof and every commitment of an aggregated proof
+    ///
+    /// A single tampered, missing or reordered tile fails the whole image.
+    /// `proof_system` must hold the params the tiles were proven with.
+    pub fn verify_tile_proofs(&self, proof_system: &ZKIMGProofSystem, aggregated: &AggregatedTileProof) -> Result<bool> {
+        if aggregated.k != proof_system.k() {
+            eprintln!("❌ Tiles were proven with k={}, not k={}", aggregated.k, proof_system.k());
+            return Ok(false);
+        }
+        let layout = &aggregated.layout;
+        let expected = TileLayout::for_chain(
+            layout.width,
+            layout.height,
+            layout.tile_size,
+            self.max_tiles,
+            &aggregated.transformations,
+        )?;
+        if expected != *layout {
+            eprintln!("❌ Tile layout does not match the transformation chain");
+            return Ok(false);
+        }
+        if aggregated.tiles.len() != layout.tiles.len()
+            || aggregated.tiles.iter().zip(&layout.tiles).any(|(proof, tile)| proof.tile != *tile)
+        {
+            eprintln!("❌ Tile proofs do not match the tile index");
+            return Ok(false);
         }
 
-        // Simple concatenation for now (would be Merkle root in full implementation)
-        let mut aggregated = Vec::new();
-        for proof in tile_proofs {
-            aggregated.extend_from_slice(&proof);
+        if aggregated.proof_root != tile_proof_root(&aggregated.tiles)
+            || tile_roots(layout, &aggregated.tiles).ok() != Some((aggregated.input_root, aggregated.output_root))
+        {
+            eprintln!("❌ Tile commitments do not match the tile proofs");
+            return Ok(false);
         }
 
-        println!("🔗 Aggregated {} tile proofs into {} bytes", tile_proofs.len(), aggregated.len());
-        Ok(aggregated)
+        let mut keys: HashMap<String, VerifyingKey<EqAffine>> = HashMap::new();
+        for (index, tile_proof) in aggregated.tiles.iter().enumerate() {
+            let tile = tile_proof.tile;
+            let chain = tile.chain(&aggregated.transformations);
+            if tile_proof.public_inputs.get(2) != Some(&crate::Transformation::chain_digest(&chain)?) {
+                eprintln!("❌ Tile {} was not proven for the aggregated chain", index);
+                return Ok(false);
+            }
+
+            let shape = format!("{}x{}:{:?}", tile.input.width, tile.input.height, chain);
+            if !keys.contains_key(&shape) {
+                let circuit = ZKIMGCircuit::blank(tile.input.width as usize, tile.input.height as usize, chain);
+                keys.insert(shape.clone(), keygen_vk(proof_system.params(), &circuit)?);
+            }
+
+            if !proof_system.verify(&keys[&shape], &tile_proof.proof_bytes, &tile_proof.public_inputs)? {
+                eprintln!("❌ Proof of tile {} at ({}, {}) failed", index, tile.core.x, tile.core.y);
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
     }
 }
 
+/// Proof of one tile, as produced by `HDProcessor::prove_tiles`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TileProof {
+    pub tile: Tile,
+    pub proof_bytes: Vec<u8>,
+    /// [input hash, output hash, chain digest] of the tile's `ZKIMGCircuit`
+    pub public_inputs: Vec<Fp>,
+}
+
+/// Tile proofs of one image, bound together by their commitments
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AggregatedTileProof {
+    /// Index of the tiles: `tiles[i]` proves `layout.tiles[i]`
+    pub layout: TileLayout,
+    pub transformations: Vec<crate::Transformation>,
+    pub k: u32,
+    pub tiles: Vec<TileProof>,
+    /// `layout.commitment` over the tile input hashes
+    pub input_root: Fp,
+    /// `layout.commitment` over the tile output hashes
+    pub output_root: Fp,
+    /// SHA-256 Merkle root over `tile_proof_leaf` of every tile
+    pub proof_root: [u8; 32],
+}
+
+/// Input and output tile commitments of the proven tiles
+fn tile_roots(layout: &TileLayout, tiles: &[TileProof]) -> Result<(Fp, Fp)> {
+    let digest = |i: usize| -> Result<Vec<Fp>> {
+        tiles
+            .iter()
+            .map(|tile| tile.public_inputs.get(i).copied().ok_or_else(|| anyhow!("Tile proof is missing public inputs")))
+            .collect()
+    };
+    Ok((layout.commitment(&digest(0)?)?, layout.commitment(&digest(1)?)?))
+}
+
+/// Merkle leaf of one tile: its position in the index, its regions, the
+/// hash of its proof and its public inputs
+pub fn tile_proof_leaf(index: usize, tile_proof: &TileProof) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((index as u64).to_le_bytes());
+    for region in [tile_proof.tile.core, tile_proof.tile.input] {
+        for value in [region.x, region.y, region.width, region.height] {
+            hasher.update(value.to_le_bytes());
+        }
+    }
+    hasher.update(Sha256::digest(&tile_proof.proof_bytes));
+    for input in &tile_proof.public_inputs {
+        hasher.update(input.to_repr());
+    }
+    hasher.finalize().into()
+}
+
+/// SHA-256 Merkle root over the tile leaves, carrying an unpaired node up unchanged
+pub fn tile_proof_root(tiles: &[TileProof]) -> [u8; 32] {
+    let mut level: Vec<[u8; 32]> = tiles
+        .iter()
+        .enumerate()
+        .map(|(index, tile)| tile_proof_leaf(index, tile))
+        .collect();
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha256::new().chain_update(left).chain_update(right).finalize().into(),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+
+    level.first().copied().unwrap_or([0; 32])
+}
+
 /// Recursive proof system for unlimited transformations (Section 8.3)
+///
+/// Not available: recursion needs each step's circuit to verify the previous
+/// step's proof, and halo2_proofs 0.3.5 has no in-circuit verifier for its
+/// IPA proofs over the Pasta cycle. Single steps can still be proven with a
+/// `RecursiveZKIMGCircuit`, and whole chains with a `ZKIMGCircuit`.
 pub struct RecursiveProofSystem {
     pub max_chain_length: usize,
 }
 
+impl Default for RecursiveProofSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RecursiveProofSystem {
     pub fn new() -> Self {
         Self {
@@ -246,12 +587,91 @@ impl RecursiveProofSystem {
 
     /// Generate recursive proof for transformation chain
     pub fn prove_chain(&self, transformations: &[crate::Transformation]) -> Result<Vec<u8>> {
-        // Implement recursive proving as described in paper
-        // This allows proving arbitrarily long transformation chains
+        eprintln!("🔄 Generating recursive proof for {} transformations", transformations.len());
+        Err(anyhow!("Recursive proofs are not supported: halo2_proofs 0.3.5 cannot verify a proof inside a circuit"))
+    }
+}
 
-        println!("🔄 Generating recursive proof for {} transformations", transformations.len());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transformation;
+    use image::RgbImage;
+
+    #[test]
+    fn tampered_tile_fails_the_whole_image() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 2, |x, y| {
+            image::Rgb([x as u8 * 50, y as u8 * 90 + 10, (x + y) as u8 * 30])
+        }));
+        let processor = HDProcessor { tile_size: 2, max_tiles: 2 };
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+
+        let aggregated = processor.prove_tiles(&proof_system, &image, &[Transformation::Grayscale]).unwrap();
+        assert_eq!(aggregated.tiles.len(), 2);
+        assert!(processor.verify_tile_proofs(&proof_system, &aggregated).unwrap());
+
+        // A corrupted proof, even with the tree recomputed around it
+        let mut corrupted = aggregated.clone();
+        corrupted.tiles[1].proof_bytes[40] ^= 1;
+        assert!(!processor.verify_tile_proofs(&proof_system, &corrupted).unwrap());
+        corrupted.proof_root = tile_proof_root(&corrupted.tiles);
+        assert!(!processor.verify_tile_proofs(&proof_system, &corrupted).unwrap());
+
+        // Tiles swapped between positions
+        let mut swapped = aggregated.clone();
+        swapped.tiles.swap(0, 1);
+        assert!(!processor.verify_tile_proofs(&proof_system, &swapped).unwrap());
+
+        // A claimed output commitment the tiles do not add up to
+        let mut forged = aggregated;
+        forged.output_root = forged.input_root;
+        assert!(!processor.verify_tile_proofs(&proof_system, &forged).unwrap());
+    }
+
+    #[test]
+    fn recursive_chains_are_reported_unsupported() {
+        let err = RecursiveProofSystem::new().prove_chain(&[Transformation::Grayscale]).unwrap_err();
+        assert!(err.to_string().contains("not supported"), "{}", err);
+    }
+
+    #[test]
+    fn batch_verification_reports_each_invalid_proof() {
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let chain = vec![Transformation::Grayscale];
+        let (pk, vk) = proof_system.setup(&ZKIMGCircuit::blank(2, 2, chain.clone())).unwrap();
+
+        let proofs: Vec<(Vec<u8>, Vec<Fp>)> = (0..3u8)
+            .map(|seed| {
+                let image = DynamicImage::ImageRgb8(RgbImage::from_fn(2, 2, |x, y| {
+                    image::Rgb([seed * 60 + x as u8, y as u8 * 90, 40])
+                }));
+                let circuit = ZKIMGCircuit::new(image_to_field_elements(&image), chain.clone()).unwrap();
+                let public_inputs = circuit.public_inputs().unwrap();
+                (proof_system.prove(&pk, circuit, &public_inputs).unwrap(), public_inputs)
+            })
+            .collect();
+
+        assert_eq!(proof_system.verify_batch(&vk, &proofs).unwrap(), vec![true; 3]);
+        assert!(proof_system.verify_batch(&vk, &[]).unwrap().is_empty());
+
+        // Another image's output claimed for the second proof, and a corrupted third
+        let mut tampered = proofs.clone();
+        tampered[1].1[1] = proofs[0].1[1];
+        tampered[2].0[40] ^= 1;
+        assert_eq!(proof_system.verify_batch(&vk, &tampered).unwrap(), vec![true, false, false]);
+    }
 
-        // Placeholder implementation - would use recursive SNARKs
-        Err(anyhow!("Recursive proof implementation in progress"))
+    #[test]
+    fn aggregation_rejects_an_incomplete_tile_set() {
+        let processor = HDProcessor { tile_size: 2, max_tiles: 4 };
+        let layout = TileLayout::new(4, 2, 2, 0, 4).unwrap();
+        let tile = TileProof {
+            tile: layout.tiles[0],
+            proof_bytes: vec![],
+            public_inputs: vec![Fp::zero(); 3],
+        };
+
+        assert!(processor.aggregate_tile_proofs(layout.clone(), &[], 11, vec![]).is_err());
+        assert!(processor.aggregate_tile_proofs(layout, &[], 11, vec![tile]).is_err());
     }
 }
@@ -1,192 +1,203 @@
+//! One step of a recursive transformation chain (Section 8.3)
+//!
+//! A `RecursiveZKIMGCircuit` applies a single transformation with the same
+//! chips as `ZKIMGCircuit` and exposes, in instance-column order:
+//!
+//! 0. the hash of the original image the chain started from,
+//! 1. the hash of the previous step's output, which the circuit constrains
+//!    to be the commitment to this step's input,
+//! 2. the hash of this step's output,
+//! 3. the recursion depth,
+//! 4. the digest of this step's transformation.
+//!
+//! At depth 0 there is no previous step: the input is the original image, so
+//! instances 0 and 1 are both constrained to the input commitment and the
+//! depth to 0. Deeper steps carry the original hash and their depth through.
+//! The circuit does not verify the previous step's proof, which halo2_proofs
+//! 0.3.5 cannot do in-circuit (see `RecursiveProofSystem`), so a verifier of
+//! a chain of steps must check every proof and link instance 1 of each step
+//! to instance 2 of the one before, with consecutive depths.
+//!
+//! `ProofAggregator` accumulates the inner product checks of proofs of
+//! independent images, see `accumulation`.
+
+use anyhow::{anyhow, Result};
 use halo2_proofs::{
     circuit::{Layouter, SimpleFloorPlanner, Value},
-    plonk::{Circuit, ConstraintSystem, Error},
-    poly::Rotation,
+    pasta::{EqAffine, Fp},
+    plonk::{Circuit, Column, ConstraintSystem, Error, Fixed, VerifyingKey},
 };
-use halo2_gadgets::poseidon::{Hash, Pow5Chip, Pow5Config};
-use crate::circuits::ZKIMGCircuit;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::accumulation::{self, FoldProof, IpaAccumulator};
+use crate::circuits::{ZKIMGCircuit, ZKIMGCircuitConfig};
+use crate::cost::ConstantColumns;
+use crate::keys::{KeyShape, KeyedCircuit};
+use crate::proof_system::{VerifyingKeyRef, ZKIMGProofSystem};
+use crate::{proof_key_shape, proof_verifying_key, Transformation, ZKIMGProof};
 
-/// Recursive circuit that verifies a previous proof and applies a new transformation
+/// Recursive circuit that links to a previous step and applies a new transformation
 #[derive(Clone, Debug)]
 pub struct RecursiveZKIMGCircuit {
-    /// The previous proof to verify
-    pub previous_proof: Option<Vec<u8>>,
-    /// The previous image hash
-    pub previous_hash: Option<[u8; 32]>,
-    /// The new image data
-    pub image_data: Vec<Vec<u8>>,
+    /// This step's input image, [height][width][3]
+    pub image_pixels: Vec<Vec<Vec<Fp>>>,
     /// The transformation to apply
-    pub transformation: crate::Transformation,
+    pub transformation: Transformation,
+    /// Hash of the image the chain started from
+    pub original_hash: Fp,
+    /// Output hash of the previous step, the commitment to `image_pixels`
+    pub previous_hash: Fp,
+    /// Hash of this step's output
+    pub output_hash: Fp,
     /// Depth of recursion
     pub recursion_depth: u32,
 }
 
 impl RecursiveZKIMGCircuit {
+    /// Step applying `transformation` at `recursion_depth`, computing the hashes natively
+    ///
+    /// The first step must start from the original image itself.
     pub fn new(
-        previous_proof: Option<Vec<u8>>,
-        previous_hash: Option<[u8; 32]>,
-        image_data: Vec<Vec<u8>>,
-        transformation: crate::Transformation,
+        image_pixels: Vec<Vec<Vec<Fp>>>,
+        transformation: Transformation,
+        original_hash: Fp,
         recursion_depth: u32,
-    ) -> Self {
+    ) -> Result<Self> {
+        let step = ZKIMGCircuit::new(image_pixels, vec![transformation.clone()])?;
+        if recursion_depth == 0 && step.input_hash != original_hash {
+            return Err(anyhow!("The first step must start from the original image"));
+        }
+
+        Ok(Self {
+            image_pixels: step.image_pixels,
+            transformation,
+            original_hash,
+            previous_hash: step.input_hash,
+            output_hash: step.output_hash,
+            recursion_depth,
+        })
+    }
+
+    /// Circuit with the layout of a step on an image of the given size but no witness data
+    pub fn blank(width: usize, height: usize, transformation: Transformation, recursion_depth: u32) -> Self {
         Self {
-            previous_proof,
-            previous_hash,
-            image_data,
+            image_pixels: vec![vec![vec![Fp::zero(); 3]; width]; height],
             transformation,
+            original_hash: Fp::zero(),
+            previous_hash: Fp::zero(),
+            output_hash: Fp::zero(),
             recursion_depth,
         }
     }
+
+    /// Public inputs in instance-column order:
+    /// [original hash, previous hash, output hash, depth, transformation digest]
+    pub fn public_inputs(&self) -> Result<Vec<Fp>> {
+        Ok(vec![
+            self.original_hash,
+            self.previous_hash,
+            self.output_hash,
+            Fp::from(self.recursion_depth as u64),
+            Transformation::chain_digest(std::slice::from_ref(&self.transformation))?,
+        ])
+    }
+
+    fn step(&self) -> ZKIMGCircuit<Fp> {
+        let mut step = ZKIMGCircuit::blank(0, 0, vec![self.transformation.clone()]);
+        step.image_pixels = self.image_pixels.clone();
+        step
+    }
 }
 
-impl Circuit<pasta_curves::Fp> for RecursiveZKIMGCircuit {
-    type Config = RecursiveConfig;
+impl Circuit<Fp> for RecursiveZKIMGCircuit {
+    type Config = ZKIMGCircuitConfig<Fp>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self {
-            previous_proof: None,
-            previous_hash: None,
-            image_data: vec![],
-            transformation: self.transformation.clone(),
-            recursion_depth: self.recursion_depth,
-        }
+        // The layout depends on the image size and on whether this is the first step
+        let (width, height) = self.step().dimensions();
+        Self::blank(width, height, self.transformation.clone(), self.recursion_depth)
     }
 
-    fn configure(meta: &mut ConstraintSystem<pasta_curves::Fp>) -> Self::Config {
-        // Configure columns for recursive proof verification
-        let proof_verification = meta.advice_column();
-        let instance = meta.instance_column();
-        
-        meta.enable_equality(proof_verification);
-        meta.enable_equality(instance);
-
-        // Configure Poseidon for hashing
-        let state = (0..3).map(|_| meta.advice_column()).collect::<Vec<_>>();
-        let pow5_config = Pow5Chip::configure(
-            meta,
-            state.try_into().unwrap(),
-            meta.advice_column(),
-            meta.fixed_column(),
-            meta.fixed_column(),
-        );
-
-        // Configure the base image circuit
-        let image_config = ZKIMGCircuit::configure_columns(meta);
-
-        RecursiveConfig {
-            proof_verification,
-            instance,
-            pow5_config,
-            image_config,
-        }
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        ZKIMGCircuit::configure(meta)
     }
 
-    fn synthesize(
-        &self,
-        config: Self::Config,
-        mut layouter: impl Layouter<pasta_curves::Fp>,
-    ) -> Result<(), Error> {
-        // Step 1: Verify the previous proof (if recursion_depth > 0)
-        if self.recursion_depth > 0 {
-            layouter.namespace(|| "verify previous proof").assign_region(
-                || "proof verification",
-                |mut region| {
-                    // In a real implementation, this would verify the previous proof
-                    // using proof aggregation techniques
-                    region.assign_advice(
-                        || "proof valid",
-                        config.proof_verification,
-                        0,
-                        || Value::known(pasta_curves::Fp::from(1u64)),
-                    )?;
-                    Ok(())
-                },
-            )?;
-
-            // Verify the previous hash matches
-            if let Some(prev_hash) = self.previous_hash {
-                layouter.namespace(|| "verify previous hash").assign_region(
-                    || "hash verification",
-                    |mut region| {
-                        // Verify that the previous transformation's output hash
-                        // matches what we expect
-                        // This ensures chain integrity
-                        Ok(())
-                    },
-                )?;
-            }
-        }
-
-        // Step 2: Apply the current transformation
-        let transformed_data = layouter.namespace(|| "apply transformation").assign_region(
-            || "transformation",
-            |mut region| {
-                // Apply the transformation to the image data
-                match &self.transformation {
-                    crate::Transformation::Crop { x, y, width, height } => {
-                        // Apply cropping logic
-                        Ok(self.image_data.clone()) // Simplified
-                    }
-                    crate::Transformation::Resize { width, height } => {
-                        // Apply resizing logic
-                        Ok(self.image_data.clone()) // Simplified
-                    }
-                    _ => Ok(self.image_data.clone()),
-                }
-            },
-        )?;
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        let [input_hash, output_hash, digest] = self.step().lay_out(&config, &mut layouter)?;
 
-        // Step 3: Hash the transformed image
-        let pow5_chip = Pow5Chip::construct(config.pow5_config);
-        let hasher = Hash::<_, _, 3, 2>::init(
-            pow5_chip,
-            layouter.namespace(|| "init poseidon"),
-        )?;
+        // This step continues from the previous step's output
+        layouter.constrain_instance(input_hash.cell(), config.instance, 1)?;
+        layouter.constrain_instance(output_hash.cell(), config.instance, 2)?;
+        layouter.constrain_instance(digest.cell(), config.instance, 4)?;
 
-        let output_hash = layouter.namespace(|| "hash output").assign_region(
-            || "poseidon hash",
+        let (original, depth) = layouter.assign_region(
+            || "recursion state",
             |mut region| {
-                // Hash the transformed image data
-                // This becomes the public output that can be verified
-                Ok([0u8; 32]) // Placeholder
-            },
-        )?;
+                if self.recursion_depth == 0 {
+                    let depth = region.assign_advice_from_constant(|| "depth", config.pixels[0], 0, Fp::zero())?;
+                    return Ok((input_hash.clone(), depth));
+                }
 
-        // Step 4: Expose public inputs/outputs
-        layouter.namespace(|| "expose public").assign_region(
-            || "public exposure",
-            |mut region| {
-                // Expose:
-                // - Original image hash (from first transformation)
-                // - Current output hash
-                // - Transformation parameters
-                // - Recursion depth
-                region.assign_advice(
-                    || "recursion depth",
-                    config.instance,
+                let original = region.assign_advice(
+                    || "original hash",
+                    config.pixels[0],
+                    0,
+                    || Value::known(self.original_hash),
+                )?;
+                let depth = region.assign_advice(
+                    || "depth",
+                    config.pixels[1],
                     0,
-                    || Value::known(pasta_curves::Fp::from(self.recursion_depth as u64)),
+                    || Value::known(Fp::from(self.recursion_depth as u64)),
                 )?;
-                Ok(())
+                Ok((original, depth))
             },
         )?;
+        layouter.constrain_instance(original.cell(), config.instance, 0)?;
+        layouter.constrain_instance(depth.cell(), config.instance, 3)?;
 
         Ok(())
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct RecursiveConfig {
-    proof_verification: halo2_proofs::plonk::Column<halo2_proofs::plonk::Advice>,
-    instance: halo2_proofs::plonk::Column<halo2_proofs::plonk::Instance>,
-    pow5_config: Pow5Config<pasta_curves::Fp, 3, 2>,
-    image_config: crate::circuits::ImageConfig,
+impl ConstantColumns for RecursiveZKIMGCircuit {
+    fn constant_columns(config: &Self::Config) -> Vec<Column<Fixed>> {
+        vec![config.constants]
+    }
+}
+
+impl KeyedCircuit for RecursiveZKIMGCircuit {
+    fn circuit_id(&self) -> &'static str {
+        // The first step lays out its recursion state differently
+        if self.recursion_depth == 0 {
+            "zkimg-recursive-first"
+        } else {
+            "zkimg-recursive-step"
+        }
+    }
+
+    fn key_shape(&self) -> Result<KeyShape> {
+        self.step().key_shape()
+    }
 }
 
 /// Aggregates multiple proofs into a single proof
+///
+/// Proofs of independent images, such as a whole shoot, are verified up to
+/// their inner product checks and those checks folded into one accumulator
+/// (see `accumulation`), so the aggregate is decided by a single
+/// multiexponentiation. All proofs must share the `k` of the proof system
+/// they are aggregated and verified with.
 pub struct ProofAggregator {
-    proofs: Vec<Vec<u8>>,
+    proofs: Vec<ZKIMGProof>,
+}
+
+impl Default for ProofAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProofAggregator {
@@ -194,28 +205,255 @@ impl ProofAggregator {
         Self { proofs: vec![] }
     }
 
-    pub fn add_proof(&mut self, proof: Vec<u8>) {
+    pub fn add_proof(&mut self, proof: ZKIMGProof) {
         self.proofs.push(proof);
     }
 
-    /// Aggregate all proofs into a single succinct proof
-    pub fn aggregate(&self) -> Result<Vec<u8>, Error> {
-        // In a real implementation, this would use:
-        // 1. Halo2's accumulation scheme
-        // 2. Or a recursive SNARK construction
-        // 3. Or proof-carrying data techniques
-        
-        // For now, return a mock aggregated proof
-        Ok(vec![0u8; 192]) // Typical proof size
+    /// Aggregate all proofs into one artifact
+    ///
+    /// Fails if any proof does not verify.
+    pub fn aggregate(&self, proof_system: &ZKIMGProofSystem) -> Result<AggregatedProof> {
+        let k = common_k(&self.proofs)?;
+        if k != proof_system.k() {
+            return Err(anyhow!("Proofs were created with k={}, not k={}", k, proof_system.k()));
+        }
+        let mut keys = HashMap::new();
+
+        let accumulators = self
+            .proofs
+            .iter()
+            .enumerate()
+            .map(|(index, proof)| {
+                accumulate_proof(proof_system, &mut keys, proof, None)?
+                    .ok_or_else(|| anyhow!("Proof {} does not verify", index))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let fold = accumulation::fold(proof_system.params(), &accumulators)?;
+
+        eprintln!("🔗 Aggregated {} proofs", self.proofs.len());
+        Ok(AggregatedProof {
+            k,
+            proofs: self.proofs.clone(),
+            accumulators,
+            fold,
+        })
     }
 
-    /// Verify an aggregated proof
+    /// Verify an aggregated proof against the public inputs expected of each
+    /// of its proofs, in order
+    ///
+    /// Rejects the whole aggregate if any proof is invalid or proves anything
+    /// other than its expected public inputs.
     pub fn verify_aggregated(
         &self,
-        aggregated_proof: &[u8],
-        public_inputs: &[pasta_curves::Fp],
-    ) -> Result<bool, Error> {
-        // Verify the aggregated proof
-        Ok(true) // Mock verification
+        proof_system: &ZKIMGProofSystem,
+        aggregated: &AggregatedProof,
+        public_inputs: &[Vec<Fp>],
+    ) -> Result<bool> {
+        if aggregated.proofs.len() != public_inputs.len() || aggregated.accumulators.len() != public_inputs.len() {
+            eprintln!("❌ Expected {} proofs, the aggregate has {}", public_inputs.len(), aggregated.proofs.len());
+            return Ok(false);
+        }
+        if aggregated.k != proof_system.k() || common_k(&aggregated.proofs)? != aggregated.k {
+            eprintln!("❌ Proofs were not created with k={}", proof_system.k());
+            return Ok(false);
+        }
+
+        let mut keys = HashMap::new();
+        for (index, ((proof, accumulator), expected)) in
+            aggregated.proofs.iter().zip(&aggregated.accumulators).zip(public_inputs).enumerate()
+        {
+            if proof.public_inputs != *expected {
+                eprintln!("❌ Proof {} does not prove the expected public inputs", index);
+                return Ok(false);
+            }
+
+            let verified = accumulate_proof(proof_system, &mut keys, proof, Some(accumulator.g))?;
+            if verified.as_ref() != Some(accumulator) {
+                eprintln!("❌ Proof {} failed", index);
+                return Ok(false);
+            }
+        }
+
+        let folded = accumulation::verify_fold(proof_system.params(), &aggregated.accumulators, &aggregated.fold);
+        if !folded.is_ok_and(|accumulator| accumulator.decide(proof_system.params())) {
+            eprintln!("❌ Aggregated accumulator does not hold");
+            return Ok(false);
+        }
+
+        eprintln!("✅ Aggregate of {} proofs verified", aggregated.proofs.len());
+        Ok(true)
+    }
+}
+
+/// Proofs bound together by one folded accumulator, as produced by
+/// `ProofAggregator::aggregate`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AggregatedProof {
+    pub k: u32,
+    pub proofs: Vec<ZKIMGProof>,
+    /// Deferred inner product check of each proof
+    pub accumulators: Vec<IpaAccumulator>,
+    /// Fold of `accumulators`, decided once
+    pub fold: FoldProof,
+}
+
+/// The `k` every proof was created with
+fn common_k(proofs: &[ZKIMGProof]) -> Result<u32> {
+    let mut ks = proofs.iter().map(|proof| VerifyingKeyRef::from_bytes(&proof.verification_key).map(|vk| vk.k));
+    let k = ks.next().ok_or_else(|| anyhow!("No proofs to aggregate"))??;
+    for other in ks {
+        if other? != k {
+            return Err(anyhow!("Aggregated proofs must share one k"));
+        }
+    }
+    Ok(k)
+}
+
+/// Check `proof` against its own chain and key up to the inner product
+/// argument, or `None` if it fails
+fn accumulate_proof(
+    proof_system: &ZKIMGProofSystem,
+    keys: &mut HashMap<String, (VerifyingKey<EqAffine>, [u8; 32])>,
+    proof: &ZKIMGProof,
+    g: Option<EqAffine>,
+) -> Result<Option<IpaAccumulator>> {
+    let vk_ref = VerifyingKeyRef::from_bytes(&proof.verification_key)?;
+    if proof.public_inputs.get(2) != Some(&Transformation::chain_digest(&proof.transformation_chain)?) {
+        return Ok(None);
+    }
+
+    let shape = proof_key_shape(proof)?;
+    if !keys.contains_key(&shape) {
+        keys.insert(shape.clone(), proof_verifying_key(proof_system, proof)?);
+    }
+    let (vk, fingerprint) = &keys[&shape];
+    if vk_ref.fingerprint != *fingerprint {
+        return Ok(None);
+    }
+
+    Ok(accumulation::accumulate(proof_system.params(), vk, &proof.proof_bytes, &proof.public_inputs, g).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::image_to_field_elements;
+    use halo2_proofs::dev::MockProver;
+    use image::{DynamicImage, RgbImage};
+
+    fn satisfied(circuit: &RecursiveZKIMGCircuit, public_inputs: Vec<Fp>) -> bool {
+        MockProver::run(12, circuit, vec![public_inputs]).unwrap().verify().is_ok()
+    }
+
+    fn test_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| {
+            image::Rgb([x as u8 * 80, y as u8 * 100 + 20, 250 - x as u8 * 40])
+        }))
+    }
+
+    #[test]
+    fn steps_chain_from_the_original_hash() {
+        let image = test_image();
+        let pixels = image_to_field_elements(&image);
+        let original = ZKIMGCircuit::native_image_hash(&pixels);
+
+        let first = RecursiveZKIMGCircuit::new(pixels, Transformation::Grayscale, original, 0).unwrap();
+        assert!(satisfied(&first, first.public_inputs().unwrap()));
+
+        let grayscale = Transformation::Grayscale.apply(&image).unwrap();
+        let second =
+            RecursiveZKIMGCircuit::new(image_to_field_elements(&grayscale), Transformation::Blur, original, 1).unwrap();
+        assert_eq!(second.previous_hash, first.output_hash);
+        assert!(satisfied(&second, second.public_inputs().unwrap()));
+
+        // Claiming to continue from another step's output
+        let mut unlinked = second.public_inputs().unwrap();
+        unlinked[1] = original;
+        assert!(!satisfied(&second, unlinked));
+    }
+
+    #[test]
+    fn first_step_must_start_at_depth_zero_from_the_original() {
+        let pixels = image_to_field_elements(&test_image());
+        let original = ZKIMGCircuit::native_image_hash(&pixels);
+        assert!(RecursiveZKIMGCircuit::new(pixels.clone(), Transformation::Grayscale, Fp::from(7), 0).is_err());
+
+        let first = RecursiveZKIMGCircuit::new(pixels, Transformation::Grayscale, original, 0).unwrap();
+        let mut forged = first.public_inputs().unwrap();
+        forged[0] = Fp::from(7);
+        assert!(!satisfied(&first, forged));
+
+        let mut deeper = first.public_inputs().unwrap();
+        deeper[3] = Fp::from(4);
+        assert!(!satisfied(&first, deeper));
+    }
+
+    #[test]
+    fn aggregate_rejects_any_invalid_proof() {
+        let mut system = crate::ZKIMGSystem::new(crate::ZKIMGConfig { k: 11, ..Default::default() });
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let mut aggregator = ProofAggregator::new();
+        assert!(aggregator.aggregate(&proof_system).is_err());
+
+        let images = [test_image(), test_image().fliph()];
+        let proofs: Vec<_> = images
+            .iter()
+            .map(|image| system.prove_transformation_chain(image, &[Transformation::Grayscale]).unwrap())
+            .collect();
+        let public_inputs: Vec<_> = proofs.iter().map(|proof| proof.public_inputs.clone()).collect();
+        for proof in &proofs {
+            aggregator.add_proof(proof.clone());
+        }
+
+        let aggregated = aggregator.aggregate(&proof_system).unwrap();
+        assert!(aggregator.verify_aggregated(&proof_system, &aggregated, &public_inputs).unwrap());
+
+        // Params for another k
+        let other_k = ZKIMGProofSystem::new(12).unwrap();
+        assert!(aggregator.aggregate(&other_k).is_err());
+        assert!(!aggregator.verify_aggregated(&other_k, &aggregated, &public_inputs).unwrap());
+
+        // Public inputs of another proof, or too few of them
+        let swapped = [public_inputs[1].clone(), public_inputs[0].clone()];
+        assert!(!aggregator.verify_aggregated(&proof_system, &aggregated, &swapped).unwrap());
+        assert!(!aggregator.verify_aggregated(&proof_system, &aggregated, &public_inputs[..1]).unwrap());
+
+        // A corrupted proof cannot be aggregated, nor slipped into an aggregate
+        let mut corrupted = aggregated.clone();
+        corrupted.proofs[1].proof_bytes[40] ^= 1;
+        assert!(!aggregator.verify_aggregated(&proof_system, &corrupted, &public_inputs).unwrap());
+        let mut bad = ProofAggregator::new();
+        bad.add_proof(proofs[0].clone());
+        bad.add_proof(corrupted.proofs[1].clone());
+        assert!(bad.aggregate(&proof_system).is_err());
+
+        // An inner product claim swapped for another proof's
+        let mut forged = aggregated;
+        forged.accumulators[1] = forged.accumulators[0].clone();
+        assert!(!aggregator.verify_aggregated(&proof_system, &forged, &public_inputs).unwrap());
+    }
+
+    #[test]
+    fn aggregate_accepts_size_class_proofs() {
+        let mut system = crate::ZKIMGSystem::new(crate::ZKIMGConfig {
+            k: 11,
+            size_classes: vec![crate::size_class::SizeClass { width: 4, height: 4 }],
+            ..Default::default()
+        });
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let mut aggregator = ProofAggregator::new();
+
+        let images = [test_image(), test_image().crop_imm(0, 0, 1, 2)];
+        let mut public_inputs = Vec::new();
+        for image in &images {
+            let proof = system.prove_transformation_chain(image, &[Transformation::Grayscale]).unwrap();
+            assert!(proof.size_class.is_some());
+            public_inputs.push(proof.public_inputs.clone());
+            aggregator.add_proof(proof);
+        }
+
+        let aggregated = aggregator.aggregate(&proof_system).unwrap();
+        assert!(aggregator.verify_aggregated(&proof_system, &aggregated, &public_inputs).unwrap());
     }
 }
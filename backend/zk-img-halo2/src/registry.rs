@@ -0,0 +1,267 @@
+//! Registry of verifying keys by circuit shape
+//!
+//! Every image size and transformation chain is its own circuit with its own
+//! verifying key. A verifier should not take the prover's word for which
+//! circuit a proof belongs to, so the keys it accepts are registered ahead
+//! of time, under a `CircuitId` derived from the circuit's k, image size and
+//! chain. A registry directory holds two files per circuit:
+//!
+//! - `{id}.json`: the `RegistryEntry` the key is regenerated from,
+//! - `{id}.vk`: the verifying key file (see `keys`), a header that pins the
+//!   regenerated key to the one registered.
+//!
+//! Padded circuits (see `size_class`) have IDs of their own, derived from
+//! their size class in place of the image size.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use ff::PrimeField;
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::VerifyingKey,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::circuits::ZKIMGCircuit;
+use crate::params::parse_hash;
+use crate::keys::vk_fingerprint;
+use crate::proof_system::ZKIMGProofSystem;
+use crate::size_class::{PaddedZKIMGCircuit, SizeClass};
+use crate::Transformation;
+
+/// Stable identifier of a `ZKIMGCircuit` shape
+///
+/// SHA-256 over a domain tag, k, the image size and the encoded chain, so it
+/// only changes when the circuit does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CircuitId(pub [u8; 32]);
+
+impl CircuitId {
+    pub fn new(k: u32, width: u32, height: u32, transformations: &[Transformation]) -> Result<Self> {
+        Self::digest(b"zkimg-circuit-v1", k, width, height, transformations)
+    }
+
+    /// ID of the `PaddedZKIMGCircuit` of a size class
+    pub fn padded(k: u32, class: SizeClass, transformations: &[Transformation]) -> Result<Self> {
+        Self::digest(b"zkimg-padded-circuit-v1", k, class.width, class.height, transformations)
+    }
+
+    fn digest(domain: &[u8], k: u32, width: u32, height: u32, transformations: &[Transformation]) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        hasher.update(domain);
+        hasher.update(k.to_le_bytes());
+        hasher.update(width.to_le_bytes());
+        hasher.update(height.to_le_bytes());
+        let encoded = Transformation::encode_chain(transformations)?;
+        hasher.update((encoded.len() as u64).to_le_bytes());
+        for element in encoded {
+            hasher.update(element.to_repr());
+        }
+        Ok(Self(hasher.finalize().into()))
+    }
+
+    /// Parse the lowercase hex form produced by `Display`
+    pub fn parse(hex: &str) -> Result<Self> {
+        Ok(Self(parse_hash(hex).with_context(|| format!("Invalid circuit ID {}", hex))?))
+    }
+}
+
+impl fmt::Display for CircuitId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a registered key was generated for
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub k: u32,
+    pub width: u32,
+    pub height: u32,
+    pub transformations: Vec<Transformation>,
+    /// Size class of a padded circuit, whose size `width` and `height` then are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_class: Option<SizeClass>,
+}
+
+impl RegistryEntry {
+    pub fn id(&self) -> Result<CircuitId> {
+        match self.size_class {
+            Some(class) if (class.width, class.height) != (self.width, self.height) => {
+                Err(anyhow!("Padded entry must have the size of its class"))
+            }
+            Some(class) => CircuitId::padded(self.k, class, &self.transformations),
+            None => CircuitId::new(self.k, self.width, self.height, &self.transformations),
+        }
+    }
+
+    fn circuit(&self) -> ZKIMGCircuit<Fp> {
+        ZKIMGCircuit::blank(self.width as usize, self.height as usize, self.transformations.clone())
+    }
+
+    /// `vk_fingerprint` of the verifying key of the entry's circuit
+    pub fn fingerprint(&self, proof_system: &ZKIMGProofSystem) -> Result<[u8; 32]> {
+        match self.size_class {
+            Some(class) => {
+                vk_fingerprint(proof_system.params(), &PaddedZKIMGCircuit::blank(class, self.transformations.clone()))
+            }
+            None => vk_fingerprint(proof_system.params(), &self.circuit()),
+        }
+    }
+
+    fn save(&self, proof_system: &ZKIMGProofSystem, path: &str) -> Result<()> {
+        match self.size_class {
+            Some(class) => {
+                proof_system.save_verifying_key(&PaddedZKIMGCircuit::blank(class, self.transformations.clone()), path)
+            }
+            None => proof_system.save_verifying_key(&self.circuit(), path),
+        }
+    }
+
+    fn load(&self, proof_system: &ZKIMGProofSystem, path: &str) -> Result<VerifyingKey<EqAffine>> {
+        match self.size_class {
+            Some(class) => {
+                proof_system.load_verifying_key(path, &PaddedZKIMGCircuit::blank(class, self.transformations.clone()))
+            }
+            None => proof_system.load_verifying_key(path, &self.circuit()),
+        }
+    }
+}
+
+/// Directory of registered verifying keys
+#[derive(Clone, Debug)]
+pub struct VkRegistry {
+    dir: PathBuf,
+}
+
+impl VkRegistry {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Register the verifying key of `entry`'s circuit, returning its ID
+    pub fn register(&self, proof_system: &ZKIMGProofSystem, entry: &RegistryEntry) -> Result<CircuitId> {
+        if entry.k != proof_system.k() {
+            return Err(anyhow!("Entry is for k={}, proof system uses k={}", entry.k, proof_system.k()));
+        }
+        let id = entry.id()?;
+
+        fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        entry.save(proof_system, &self.key_path(&id))?;
+        let json = serde_json::to_string_pretty(entry)?;
+        let entry_path = self.entry_path(&id);
+        fs::write(&entry_path, json).with_context(|| format!("Failed to write {}", entry_path.display()))?;
+
+        eprintln!("📇 Registered verifying key {}", id);
+        Ok(id)
+    }
+
+    pub fn contains(&self, id: &CircuitId) -> bool {
+        self.entry_path(id).exists()
+    }
+
+    /// The registered entry and verifying key for `id`
+    ///
+    /// Fails if nothing is registered under `id` or the stored files do not
+    /// describe the circuit `id` names.
+    pub fn lookup(
+        &self,
+        proof_system: &ZKIMGProofSystem,
+        id: &CircuitId,
+    ) -> Result<(RegistryEntry, VerifyingKey<EqAffine>)> {
+        let entry_path = self.entry_path(id);
+        if !entry_path.exists() {
+            return Err(anyhow!("No verifying key registered for circuit {}", id));
+        }
+        let json = fs::read_to_string(&entry_path).with_context(|| format!("Failed to read {}", entry_path.display()))?;
+        let entry: RegistryEntry =
+            serde_json::from_str(&json).with_context(|| format!("Invalid registry entry {}", entry_path.display()))?;
+        if entry.id()? != *id {
+            return Err(anyhow!("Registry entry {} describes a different circuit", entry_path.display()));
+        }
+
+        let vk = entry.load(proof_system, &self.key_path(id))?;
+        Ok((entry, vk))
+    }
+
+    fn entry_path(&self, id: &CircuitId) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn key_path(&self, id: &CircuitId) -> String {
+        self.dir.join(format!("{}.vk", id)).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::Rational;
+
+    #[test]
+    fn circuit_ids_follow_the_circuit_shape() {
+        let chain = [Transformation::Grayscale];
+        let id = CircuitId::new(11, 4, 4, &chain).unwrap();
+        assert_eq!(CircuitId::parse(&id.to_string()).unwrap(), id);
+
+        assert_ne!(CircuitId::new(12, 4, 4, &chain).unwrap(), id);
+        assert_ne!(CircuitId::new(11, 4, 5, &chain).unwrap(), id);
+        assert_ne!(CircuitId::new(11, 4, 4, &[Transformation::Blur]).unwrap(), id);
+        let contrast = |n| [Transformation::Contrast(Rational::new(n, 2).unwrap())];
+        assert_ne!(CircuitId::new(11, 4, 4, &contrast(3)).unwrap(), CircuitId::new(11, 4, 4, &contrast(5)).unwrap());
+        assert!(CircuitId::parse("xyz").is_err());
+
+        // A padded circuit differs from the plain one of its class size
+        let class = SizeClass { width: 4, height: 4 };
+        assert_ne!(CircuitId::padded(11, class, &chain).unwrap(), id);
+    }
+
+    #[test]
+    fn registered_keys_are_found_by_id() {
+        let dir = std::env::temp_dir().join(format!("zkimg-registry-{}", std::process::id()));
+        let registry = VkRegistry::new(&dir);
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let entry = RegistryEntry {
+            k: 11,
+            width: 2,
+            height: 2,
+            transformations: vec![Transformation::Grayscale],
+            size_class: None,
+        };
+
+        let id = registry.register(&proof_system, &entry).unwrap();
+        assert!(registry.contains(&id));
+        let (found, _) = registry.lookup(&proof_system, &id).unwrap();
+        assert_eq!(found.id().unwrap(), id);
+
+        // The key file of another circuit is not accepted under this entry
+        let blur = RegistryEntry {
+            transformations: vec![Transformation::Blur],
+            ..entry.clone()
+        };
+        let blur_id = registry.register(&proof_system, &blur).unwrap();
+        let key = fs::read(registry.key_path(&id)).unwrap();
+        fs::copy(registry.key_path(&blur_id), registry.key_path(&id)).unwrap();
+        assert!(registry.lookup(&proof_system, &id).is_err());
+        fs::write(registry.key_path(&id), key).unwrap();
+
+        // Unregistered, and an entry edited to describe another circuit
+        let other = CircuitId::new(11, 2, 2, &[Transformation::FlipHorizontal]).unwrap();
+        assert!(registry.lookup(&proof_system, &other).is_err());
+        let edited = RegistryEntry {
+            transformations: vec![Transformation::Blur],
+            ..entry
+        };
+        fs::write(registry.entry_path(&id), serde_json::to_string(&edited).unwrap()).unwrap();
+        assert!(registry.lookup(&proof_system, &id).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
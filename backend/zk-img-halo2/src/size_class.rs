@@ -0,0 +1,372 @@
+//! Size classes: one key for many image sizes
+//!
+//! `ZKIMGCircuit` lays out cells per pixel, so every image size is its own
+//! circuit with its own keys. A `PaddedZKIMGCircuit` instead lays out a fixed
+//! canvas, its `SizeClass`, and places the image in its top-left corner:
+//!
+//! - the real width and height are public inputs, turned into a mask of the
+//!   canvas by `chips::mask`;
+//! - the input canvas must be zero outside the mask, and the output canvas is
+//!   masked back to zero after the chain;
+//! - both commitments are `chips::commitment` digests of the zero-padded
+//!   canvas, bound to the real size instead of the canvas size.
+//!
+//! Keys then depend only on the class and the chain, so a handful of them
+//! cover every upload. Padding only commutes with per-pixel steps, whose
+//! output pixel depends on the input pixel alone: geometric steps, 3x3
+//! convolutions and white balance would read the padding, and are rejected.
+//! `ZKIMGSystem` proves such chains with an exact-size key instead.
+//!
+//! Every proof pays for the whole canvas, about 11 rows per canvas pixel plus
+//! those of the chain, so classes need a far larger k than the default. The
+//! smallest k of each standard class, as `PaddedZKIMGCircuit::cost` measures
+//! it (rows grow linearly with the canvas):
+//!
+//! | class        | no steps | `Grayscale` |
+//! |--------------|----------|-------------|
+//! | 256x256      | 20       | 21          |
+//! | 512x512      | 22       | 23          |
+//! | 1280x720     | 24       | 25          |
+//! | 1920x1080    | 25       | 26          |
+//!
+//! Longer chains need more; `ZKIMGSystem::required_k` measures a given one.
+
+use anyhow::{anyhow, Result};
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner},
+    pasta::Fp,
+    plonk::{Circuit, Column, ConstraintSystem, Error, Fixed},
+};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+use crate::chips::{commitment::native_image_commitment, ChainDigestChip, ImageCommitmentChip, MaskChip, MaskConfig};
+use crate::circuits::{ZKIMGCircuit, ZKIMGCircuitConfig};
+use crate::cost::{CircuitCost, ConstantColumns};
+use crate::keys::{KeyShape, KeyedCircuit};
+use crate::transforms::{field_elements_to_image, image_to_field_elements};
+use crate::Transformation;
+
+/// Instance rows of the real width and height, after the `ZKIMGCircuit` inputs
+const WIDTH_ROW: usize = 3;
+const HEIGHT_ROW: usize = 4;
+
+/// Canvas images are padded to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SizeClass {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SizeClass {
+    pub const SQUARE_256: Self = Self { width: 256, height: 256 };
+    pub const SQUARE_512: Self = Self { width: 512, height: 512 };
+    pub const HD_720: Self = Self { width: 1280, height: 720 };
+    pub const HD_720_PORTRAIT: Self = Self { width: 720, height: 1280 };
+    pub const HD_1080: Self = Self { width: 1920, height: 1080 };
+    pub const HD_1080_PORTRAIT: Self = Self { width: 1080, height: 1920 };
+
+    /// Square thumbnails, then 720p and 1080p in either orientation
+    pub const STANDARD: [Self; 6] = [
+        Self::SQUARE_256,
+        Self::SQUARE_512,
+        Self::HD_720,
+        Self::HD_720_PORTRAIT,
+        Self::HD_1080,
+        Self::HD_1080_PORTRAIT,
+    ];
+
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        width <= self.width && height <= self.height
+    }
+
+    /// The smallest of `classes` a `width` x `height` image fits
+    pub fn smallest_fitting(classes: &[Self], width: u32, height: u32) -> Result<Self> {
+        classes
+            .iter()
+            .filter(|class| class.fits(width, height))
+            .min_by_key(|class| class.width as u64 * class.height as u64)
+            .copied()
+            .ok_or_else(|| anyhow!("A {}x{} image does not fit any size class", width, height))
+    }
+
+    /// `pixels` ([height][width][3]) in the top-left corner of a zero canvas
+    pub fn pad(&self, pixels: &[Vec<Vec<Fp>>]) -> Result<Vec<Vec<Vec<Fp>>>> {
+        let (width, height) = dimensions(pixels);
+        if !self.fits(width as u32, height as u32) {
+            return Err(anyhow!(
+                "A {}x{} image does not fit the {}x{} size class",
+                width,
+                height,
+                self.width,
+                self.height
+            ));
+        }
+
+        let mut canvas = vec![vec![vec![Fp::zero(); 3]; self.width as usize]; self.height as usize];
+        for (canvas_row, row) in canvas.iter_mut().zip(pixels) {
+            canvas_row[..width].clone_from_slice(row);
+        }
+        Ok(canvas)
+    }
+
+    /// Native counterpart of the padded commitment: the zero-padded canvas
+    /// bound to the real size of `pixels`
+    pub fn commitment(&self, pixels: &[Vec<Vec<Fp>>]) -> Result<Fp> {
+        let (width, height) = dimensions(pixels);
+        let canvas = self.pad(pixels)?;
+        let channels: Vec<Fp> = canvas.iter().flatten().flatten().copied().collect();
+        Ok(native_image_commitment(width, height, &channels))
+    }
+}
+
+/// Fail unless every step of `transformations` is per-pixel, so the chain
+/// can be proven on a padded canvas
+pub fn check_paddable(transformations: &[Transformation]) -> Result<()> {
+    for transformation in transformations {
+        match transformation {
+            Transformation::ToYCbCr
+            | Transformation::ToRGB
+            | Transformation::Grayscale
+            | Transformation::Contrast(_)
+            | Transformation::Brightness(_)
+            | Transformation::GrayscaleContrast { .. }
+            | Transformation::ToneMap(_) => {}
+            other => return Err(anyhow!("{:?} cannot be proven on a padded image", other)),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct PaddedZKIMGCircuitConfig {
+    pub circuit: ZKIMGCircuitConfig<Fp>,
+    pub mask: MaskConfig,
+}
+
+/// `ZKIMGCircuit` over an image padded to its size class
+///
+/// Public inputs are `[input hash, output hash, chain digest, width, height]`
+/// with the hashes as described in the module documentation.
+#[derive(Clone)]
+pub struct PaddedZKIMGCircuit {
+    pub class: SizeClass,
+    pub width: u32,
+    pub height: u32,
+    /// The padded canvas, holding the padded hashes
+    pub canvas: ZKIMGCircuit<Fp>,
+}
+
+impl Circuit<Fp> for PaddedZKIMGCircuit {
+    type Config = PaddedZKIMGCircuitConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::blank(self.class, self.canvas.transformations.clone())
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let circuit = ZKIMGCircuit::configure(meta);
+        let mask = MaskChip::configure(meta, circuit.pixels, circuit.accumulator);
+        PaddedZKIMGCircuitConfig { circuit, mask }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error> {
+        let circuit = &config.circuit;
+        circuit.bytes.load(&mut layouter)?;
+        let mask_chip = MaskChip::construct(config.mask.clone());
+        let commitment_chip = ImageCommitmentChip::construct(circuit.commitment_config.clone());
+
+        // The real size, from the public inputs
+        let (width, height) = layouter.assign_region(
+            || "real size",
+            |mut region| {
+                let width =
+                    region.assign_advice_from_instance(|| "width", circuit.instance, WIDTH_ROW, circuit.pixels[0], 0)?;
+                let height =
+                    region.assign_advice_from_instance(|| "height", circuit.instance, HEIGHT_ROW, circuit.pixels[1], 0)?;
+                Ok((width, height))
+            },
+        )?;
+        let masks = mask_chip.masks(layouter.namespace(|| "masks"), self.canvas.dimensions(), &width, &height)?;
+        let dimensions = mask_chip.encode_dimensions(layouter.namespace(|| "real dimensions"), &width, &height)?;
+
+        // Zero-padded input
+        let input_image = self.canvas.load_image(circuit, &mut layouter)?;
+        mask_chip.check_padding(layouter.namespace(|| "input padding"), &input_image, &masks)?;
+        let input_hash = commitment_chip.commit_with_dimensions(
+            layouter.namespace(|| "input commitment"),
+            &input_image,
+            dimensions.clone(),
+        )?;
+
+        // The chain runs on the whole canvas; its padding is zeroed again after
+        let (transformed_image, chain) = self.canvas.apply_transformations(circuit, &mut layouter, &input_image)?;
+        let output_image = mask_chip.apply(layouter.namespace(|| "output padding"), &transformed_image, &masks)?;
+        let output_hash = commitment_chip.commit_with_dimensions(
+            layouter.namespace(|| "output commitment"),
+            &output_image,
+            dimensions,
+        )?;
+
+        let chain_digest = ChainDigestChip::construct(circuit.poseidon_config.clone(), circuit.pixels[0])
+            .digest(layouter.namespace(|| "chain digest"), &chain)?;
+
+        layouter.constrain_instance(input_hash.cell(), circuit.instance, 0)?;
+        layouter.constrain_instance(output_hash.cell(), circuit.instance, 1)?;
+        layouter.constrain_instance(chain_digest.cell(), circuit.instance, 2)?;
+        Ok(())
+    }
+}
+
+impl ConstantColumns for PaddedZKIMGCircuit {
+    fn constant_columns(config: &Self::Config) -> Vec<Column<Fixed>> {
+        vec![config.circuit.constants]
+    }
+}
+
+impl KeyedCircuit for PaddedZKIMGCircuit {
+    fn circuit_id(&self) -> &'static str {
+        "zkimg-padded"
+    }
+
+    fn key_shape(&self) -> Result<KeyShape> {
+        Ok(KeyShape {
+            width: self.class.width,
+            height: self.class.height,
+            chain_digest: Transformation::chain_digest(&self.canvas.transformations)?,
+        })
+    }
+}
+
+impl PaddedZKIMGCircuit {
+    /// Build a circuit proving `transformations` on `image_pixels` padded to `class`
+    pub fn new(class: SizeClass, image_pixels: Vec<Vec<Vec<Fp>>>, transformations: Vec<Transformation>) -> Result<Self> {
+        check_paddable(&transformations)?;
+        let (width, height) = dimensions(&image_pixels);
+
+        let output = transformations
+            .iter()
+            .try_fold(field_elements_to_image(&image_pixels), |image, transformation| transformation.apply(&image))?;
+        let input_hash = class.commitment(&image_pixels)?;
+        let output_hash = class.commitment(&image_to_field_elements(&output))?;
+
+        Ok(Self {
+            class,
+            width: width as u32,
+            height: height as u32,
+            canvas: ZKIMGCircuit {
+                image_pixels: class.pad(&image_pixels)?,
+                transformations,
+                input_hash,
+                output_hash,
+                _marker: PhantomData,
+            },
+        })
+    }
+
+    /// Circuit with the layout of `class` but no witness data
+    pub fn blank(class: SizeClass, transformations: Vec<Transformation>) -> Self {
+        Self {
+            class,
+            width: class.width,
+            height: class.height,
+            canvas: ZKIMGCircuit::blank(class.width as usize, class.height as usize, transformations),
+        }
+    }
+
+    /// Public inputs in instance-column order:
+    /// [input hash, output hash, chain digest, width, height]
+    pub fn public_inputs(&self) -> Result<Vec<Fp>> {
+        let mut public_inputs = self.canvas.public_inputs()?;
+        public_inputs.extend([Fp::from(self.width as u64), Fp::from(self.height as u64)]);
+        Ok(public_inputs)
+    }
+
+    /// Layout cost of proving this chain on this size class
+    pub fn cost(&self) -> Result<CircuitCost> {
+        Ok(CircuitCost::measure(self)?)
+    }
+}
+
+fn dimensions(pixels: &[Vec<Vec<Fp>>]) -> (usize, usize) {
+    (pixels.first().map(|row| row.len()).unwrap_or(0), pixels.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof_system::ZKIMGProofSystem;
+    use crate::transforms::Rational;
+    use image::{DynamicImage, RgbImage};
+
+    const CLASS: SizeClass = SizeClass { width: 4, height: 4 };
+
+    fn pixels(width: u32, height: u32) -> Vec<Vec<Vec<Fp>>> {
+        let image = RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8 * 50 + 20, y as u8 * 60, 200]));
+        image_to_field_elements(&DynamicImage::ImageRgb8(image))
+    }
+
+    fn chain() -> Vec<Transformation> {
+        vec![Transformation::Grayscale, Transformation::Brightness(Rational::new(1, 3).unwrap())]
+    }
+
+    #[test]
+    fn images_take_the_smallest_class_they_fit() {
+        let fit = |width, height| SizeClass::smallest_fitting(&SizeClass::STANDARD, width, height).unwrap();
+        assert_eq!(fit(100, 256), SizeClass::SQUARE_256);
+        assert_eq!(fit(300, 200), SizeClass::SQUARE_512);
+        assert_eq!(fit(1280, 600), SizeClass::HD_720);
+        assert_eq!(fit(720, 1280), SizeClass::HD_720_PORTRAIT);
+        assert_eq!(fit(1000, 1000), SizeClass::HD_1080);
+        assert!(SizeClass::smallest_fitting(&SizeClass::STANDARD, 2000, 10).is_err());
+
+        assert!(check_paddable(&chain()).is_ok());
+        assert!(check_paddable(&[Transformation::Blur]).is_err());
+        assert!(check_paddable(&[Transformation::FlipHorizontal]).is_err());
+        assert!(check_paddable(&[Transformation::WhiteBalance]).is_err());
+    }
+
+    #[test]
+    fn one_key_proves_every_size_in_its_class() {
+        let proof_system = ZKIMGProofSystem::new(11).unwrap();
+        let (pk, vk) = proof_system.setup(&PaddedZKIMGCircuit::blank(CLASS, chain())).unwrap();
+
+        for (width, height) in [(4, 4), (3, 2), (1, 4)] {
+            let circuit = PaddedZKIMGCircuit::new(CLASS, pixels(width, height), chain()).unwrap();
+            let public_inputs = circuit.public_inputs().unwrap();
+            let proof = proof_system.prove(&pk, circuit, &public_inputs).unwrap();
+            assert!(proof_system.verify(&vk, &proof, &public_inputs).unwrap());
+
+            // The proof attests to the real size
+            let mut resized = public_inputs.clone();
+            resized[3] = Fp::from(width as u64 - 1);
+            assert!(!proof_system.verify(&vk, &proof, &resized).unwrap());
+        }
+    }
+
+    #[test]
+    fn padding_must_be_zero_and_masked() {
+        use halo2_proofs::dev::MockProver;
+
+        let circuit = PaddedZKIMGCircuit::new(CLASS, pixels(3, 2), chain()).unwrap();
+        let public_inputs = circuit.public_inputs().unwrap();
+        assert_eq!(MockProver::run(11, &circuit, vec![public_inputs.clone()]).unwrap().verify(), Ok(()));
+
+        // Data hidden in the padding of the input, even when committed to
+        let mut hidden = circuit.clone();
+        hidden.canvas.image_pixels[3][3][0] = Fp::from(9);
+        let channels: Vec<Fp> = hidden.canvas.image_pixels.iter().flatten().flatten().copied().collect();
+        let mut committed = public_inputs.clone();
+        committed[0] = native_image_commitment(3, 2, &channels);
+        assert!(MockProver::run(11, &hidden, vec![committed]).unwrap().verify().is_err());
+
+        // A size larger than the canvas
+        let mut wide = public_inputs;
+        wide[3] = Fp::from(5);
+        assert!(MockProver::run(11, &circuit, vec![wide]).unwrap().verify().is_err());
+
+        assert!(PaddedZKIMGCircuit::new(CLASS, pixels(5, 2), chain()).is_err());
+        assert!(PaddedZKIMGCircuit::new(CLASS, pixels(3, 2), vec![Transformation::Sharpen]).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Tiled proving of large images (Section 8.1)
+//!
+//! An image is split into a grid of `tile_size` square tiles, each proven by
+//! its own `ZKIMGCircuit` over the tile's input region: the tile grown by a
+//! halo of one pixel per 3x3 convolution in the chain, clipped at the image
+//! edges, followed by a crop back to the tile. A convolution replicates the
+//! edge pixels of its input, so each one spoils the outermost ring of the
+//! halo, but after `halo` of them the tile itself is exactly what the full
+//! image gives. At the image edges the halo is clipped just like the full
+//! image is.
+//!
+//! Only chains of local steps can be tiled: per-pixel maps and 3x3
+//! convolutions. Geometric steps move pixels between tiles and white balance
+//! depends on the whole image, so they are rejected.
+//!
+//! Tiles are committed by a Poseidon Merkle tree whose leaves are
+//! `Poseidon(tile digest, x << 32 | y)` in row-major tile order, and whose
+//! root is bound to the image dimensions like `chips::commitment` binds its
+//! root. The input and output commitments of an image are these roots over
+//! the tile input and output digests.
+
+use anyhow::{anyhow, Result};
+use halo2_proofs::pasta::Fp;
+use serde::{Deserialize, Serialize};
+
+use crate::chips::commitment::{encode_dimensions, native_hash_pair, native_merkle_root};
+use crate::Transformation;
+
+/// Rectangle of an image, in pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The pixels of `image` ([height][width][3]) inside this region
+    pub fn extract<T: Clone>(&self, image: &[Vec<Vec<T>>]) -> Vec<Vec<Vec<T>>> {
+        let (x, y) = (self.x as usize, self.y as usize);
+        image[y..y + self.height as usize]
+            .iter()
+            .map(|row| row[x..x + self.width as usize].to_vec())
+            .collect()
+    }
+}
+
+/// A tile and the input region its proof reads
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    /// Part of the output this tile proves
+    pub core: Region,
+    /// `core` grown by the halo and clipped to the image
+    pub input: Region,
+}
+
+impl Tile {
+    /// Chain proven for this tile: `transformations`, then a crop from the
+    /// input region back to the core when they differ
+    pub fn chain(&self, transformations: &[Transformation]) -> Vec<Transformation> {
+        let mut chain = transformations.to_vec();
+        if self.input != self.core {
+            chain.push(Transformation::Crop {
+                x: self.core.x - self.input.x,
+                y: self.core.y - self.input.y,
+                width: self.core.width,
+                height: self.core.height,
+            });
+        }
+        chain
+    }
+}
+
+/// How an image is split into tiles
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileLayout {
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    pub halo: u32,
+    /// Row-major
+    pub tiles: Vec<Tile>,
+}
+
+impl TileLayout {
+    /// Split a `width` x `height` image, failing if it needs more than `max_tiles` tiles
+    pub fn new(width: u32, height: u32, tile_size: u32, halo: u32, max_tiles: usize) -> Result<Self> {
+        if tile_size == 0 {
+            return Err(anyhow!("Tile size must be positive"));
+        }
+
+        let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+        let count = columns as usize * rows as usize;
+        if count > max_tiles {
+            return Err(anyhow!(
+                "A {}x{} image needs {} tiles of {}px, more than the limit of {}",
+                width,
+                height,
+                count,
+                tile_size,
+                max_tiles
+            ));
+        }
+
+        let mut tiles = Vec::with_capacity(count);
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (column * tile_size, row * tile_size);
+                let core = Region {
+                    x,
+                    y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                };
+
+                let (left, top) = (x.saturating_sub(halo), y.saturating_sub(halo));
+                let right = (core.x + core.width).saturating_add(halo).min(width);
+                let bottom = (core.y + core.height).saturating_add(halo).min(height);
+                let input = Region {
+                    x: left,
+                    y: top,
+                    width: right - left,
+                    height: bottom - top,
+                };
+
+                tiles.push(Tile { core, input });
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            tile_size,
+            halo,
+            tiles,
+        })
+    }
+
+    /// Layout with the halo `transformations` need
+    pub fn for_chain(
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        max_tiles: usize,
+        transformations: &[Transformation],
+    ) -> Result<Self> {
+        Self::new(width, height, tile_size, halo(transformations)?, max_tiles)
+    }
+
+    /// Commitment to one digest per tile, in tile order
+    pub fn commitment(&self, digests: &[Fp]) -> Result<Fp> {
+        if digests.len() != self.tiles.len() {
+            return Err(anyhow!("Expected {} tile digests, got {}", self.tiles.len(), digests.len()));
+        }
+
+        let leaves = self
+            .tiles
+            .iter()
+            .zip(digests)
+            .map(|(tile, &digest)| native_hash_pair(digest, tile_position(tile)))
+            .collect();
+        Ok(native_hash_pair(
+            native_merkle_root(leaves),
+            encode_dimensions(self.width as usize, self.height as usize),
+        ))
+    }
+}
+
+/// Halo needed to prove `transformations` tile by tile: one pixel per convolution
+pub fn halo(transformations: &[Transformation]) -> Result<u32> {
+    let mut halo = 0;
+    for transformation in transformations {
+        match transformation {
+            Transformation::ToYCbCr
+            | Transformation::ToRGB
+            | Transformation::Grayscale
+            | Transformation::Contrast(_)
+            | Transformation::Brightness(_)
+            | Transformation::GrayscaleContrast { .. }
+            | Transformation::ToneMap(_) => {}
+            Transformation::Sharpen | Transformation::Blur | Transformation::Convolve(_) => halo += 1,
+            other => return Err(anyhow!("{:?} cannot be proven tile by tile", other)),
+        }
+    }
+    Ok(halo)
+}
+
+/// `x << 32 | y` of the tile's top-left corner
+fn tile_position(tile: &Tile) -> Fp {
+    Fp::from(((tile.core.x as u64) << 32) | tile.core.y as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::Rational;
+
+    #[test]
+    fn tiles_cover_the_image_with_clipped_halos() {
+        let layout = TileLayout::new(10, 7, 4, 2, 16).unwrap();
+        assert_eq!(layout.tiles.len(), 6);
+
+        let covered: u32 = layout.tiles.iter().map(|tile| tile.core.width * tile.core.height).sum();
+        assert_eq!(covered, 70);
+
+        let corner = layout.tiles[0];
+        assert_eq!(corner.input, Region { x: 0, y: 0, width: 6, height: 6 });
+        let last = layout.tiles[5];
+        assert_eq!(last.core, Region { x: 8, y: 4, width: 2, height: 3 });
+        assert_eq!(last.input, Region { x: 6, y: 2, width: 4, height: 5 });
+    }
+
+    #[test]
+    fn too_many_tiles_is_an_error() {
+        // 720p in 256px tiles needs 15
+        assert!(TileLayout::new(1280, 720, 256, 0, 15).is_ok());
+        assert!(TileLayout::new(1280, 720, 256, 0, 14).is_err());
+        assert!(TileLayout::new(4, 4, 0, 0, 1).is_err());
+    }
+
+    #[test]
+    fn halo_counts_convolutions_and_rejects_global_steps() {
+        let contrast = Transformation::Contrast(Rational::new(3, 2).unwrap());
+        assert_eq!(halo(&[Transformation::Blur, contrast, Transformation::Sharpen]).unwrap(), 2);
+        assert!(halo(&[Transformation::WhiteBalance]).is_err());
+        assert!(halo(&[Transformation::FlipHorizontal]).is_err());
+    }
+
+    #[test]
+    fn commitment_binds_tile_order() {
+        let layout = TileLayout::new(8, 4, 4, 0, 2).unwrap();
+        let (a, b) = (Fp::from(1), Fp::from(2));
+        assert_ne!(layout.commitment(&[a, b]).unwrap(), layout.commitment(&[b, a]).unwrap());
+        assert!(layout.commitment(&[a]).is_err());
+    }
+}
@@ -3,29 +3,426 @@
 //! Based on Section 7.3 of the paper: "Image Operations"
 //! Implements efficient circuits for various image transformations
 
-use image::{DynamicImage, GenericImageView, RgbImage, Pixel, RgbaImage};
+use image::{DynamicImage, GenericImageView, RgbImage, Pixel};
+use ff::PrimeField;
 use halo2_proofs::pasta::Fp;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+
+/// Interpolation used by `Transformation::Resize`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+/// Integer per-channel map `clamp(floor((Σ weights[i] * x[i] + bias) / divisor), 0, 255)`
+///
+/// Shared by the native transforms and `chips::LinearChip`, so both produce
+/// bit-identical output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntegerWeights<const N: usize> {
+    pub weights: [i64; N],
+    pub bias: i64,
+    pub divisor: u64,
+}
+
+impl<const N: usize> IntegerWeights<N> {
+    pub fn apply(&self, inputs: [u8; N]) -> u8 {
+        let total = self
+            .weights
+            .iter()
+            .zip(inputs)
+            .fold(self.bias, |acc, (&weight, input)| acc + weight * input as i64);
+
+        total.div_euclid(self.divisor as i64).clamp(0, 255) as u8
+    }
+
+    /// Whether `LinearChip` can prove this map on every byte input: its
+    /// quotient and clamp distance are range checked to 16 bits, and its
+    /// remainder gap to 32 bits
+    pub fn fits_range_checks(&self) -> bool {
+        const LIMIT: i128 = 1 << 16;
+        let divisor = self.divisor as i128;
+        let sum = |pick: fn(i64) -> i64| self.weights.iter().map(|&weight| pick(weight) as i128 * 255).sum::<i128>();
+        let (lowest, highest) = (self.bias as i128 + sum(|w| w.min(0)), self.bias as i128 + sum(|w| w.max(0)));
+        if divisor == 0 || divisor > crate::chips::range::U32_BOUND as i128 || lowest < i64::MIN as i128 {
+            return false;
+        }
+
+        let offset = crate::chips::linear::clamp_offset(lowest as i64, self.divisor) as i128;
+        let (low, high) = ((lowest + offset * divisor) / divisor, (highest + offset * divisor) / divisor);
+        high < LIMIT && offset - low <= LIMIT && high - offset - 256 < LIMIT
+    }
+}
+
+/// Clockwise rotation by a whole number of quarter turns
+///
+/// The only rotations that map pixels onto pixels, and so the only ones a
+/// permutation can prove.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RightAngle {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("rotation by {0} degrees is not supported, only multiples of 90 can be proven")]
+pub struct UnsupportedAngle(pub f32);
+
+impl RightAngle {
+    pub fn from_degrees(degrees: f32) -> Result<Self, UnsupportedAngle> {
+        if !degrees.is_finite() || degrees % 90.0 != 0.0 {
+            return Err(UnsupportedAngle(degrees));
+        }
+
+        Ok(match ((degrees / 90.0) as i64).rem_euclid(4) {
+            0 => Self::Deg0,
+            1 => Self::Deg90,
+            2 => Self::Deg180,
+            _ => Self::Deg270,
+        })
+    }
+
+    /// Dimensions of a rotated `width` x `height` image
+    pub fn output_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Deg0 | Self::Deg180 => (width, height),
+            Self::Deg90 | Self::Deg270 => (height, width),
+        }
+    }
+
+    /// Source pixel of output `(x, y)` when rotating a `width` x `height` image
+    pub fn source(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::Deg0 => (x, y),
+            Self::Deg90 => (y, height - 1 - x),
+            Self::Deg180 => (width - 1 - x, height - 1 - y),
+            Self::Deg270 => (width - 1 - y, x),
+        }
+    }
+}
+
+/// Largest denominator a `Rational` parameter may use
+pub const MAX_DENOMINATOR: u32 = 1 << 12;
+/// Largest magnitude of a `Rational` parameter
+pub const MAX_MAGNITUDE: i32 = 64;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RationalError {
+    #[error("denominator must be between 1 and {MAX_DENOMINATOR}, got {0}")]
+    Denominator(i64),
+    #[error("magnitude must be at most {MAX_MAGNITUDE}, got {0}")]
+    Magnitude(f64),
+    #[error("{0} is not a finite number")]
+    NotFinite(f64),
+}
+
+/// Exact `numerator / denominator` parameter for adjustments
+///
+/// Deserializes from the legacy float encoding (`1.5`), rounded to the
+/// nearest multiple of `1 / MAX_DENOMINATOR`, as well as from
+/// `{ "numerator": 3, "denominator": 2 }`, which is also how it serializes.
+/// Values are kept in lowest terms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RationalRepr")]
+pub struct Rational {
+    numerator: i32,
+    denominator: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RationalRepr {
+    Float(f64),
+    Ratio { numerator: i64, denominator: i64 },
+}
+
+impl TryFrom<RationalRepr> for Rational {
+    type Error = RationalError;
+
+    fn try_from(repr: RationalRepr) -> Result<Self, Self::Error> {
+        match repr {
+            RationalRepr::Float(value) => Self::from_f64(value),
+            RationalRepr::Ratio { numerator, denominator } => Self::new(numerator, denominator),
+        }
+    }
+}
+
+impl Rational {
+    pub const ZERO: Self = Self { numerator: 0, denominator: 1 };
+    pub const ONE: Self = Self { numerator: 1, denominator: 1 };
+
+    pub fn new(numerator: i64, denominator: i64) -> Result<Self, RationalError> {
+        if denominator <= 0 || denominator > MAX_DENOMINATOR as i64 {
+            return Err(RationalError::Denominator(denominator));
+        }
+        if numerator.abs() > MAX_MAGNITUDE as i64 * denominator {
+            return Err(RationalError::Magnitude(numerator as f64 / denominator as f64));
+        }
+
+        let divisor = gcd(numerator.unsigned_abs(), denominator as u64) as i64;
+        Ok(Self {
+            numerator: (numerator / divisor) as i32,
+            denominator: (denominator / divisor) as u32,
+        })
+    }
+
+    /// Nearest rational with denominator `MAX_DENOMINATOR`, in lowest terms
+    pub fn from_f64(value: f64) -> Result<Self, RationalError> {
+        if !value.is_finite() {
+            return Err(RationalError::NotFinite(value));
+        }
+        if value.abs() > MAX_MAGNITUDE as f64 {
+            return Err(RationalError::Magnitude(value));
+        }
+
+        Self::new((value * MAX_DENOMINATOR as f64).round() as i64, MAX_DENOMINATOR as i64)
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.numerator as i64
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.denominator as i64
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.max(1)
+}
+
+/// 3x3 integer convolution kernel, applied as `Σ weight * pixel / divisor`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ConvolutionKernel {
+    /// Row-major weights, `weights[dy + 1][dx + 1]`
+    pub weights: [[i32; 3]; 3],
+    pub divisor: NonZeroU32,
+}
+
+/// Convolution kernel whose totals exceed what the circuit can range check
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("convolution kernel {0:?} cannot be proven, its totals exceed the circuit's 16-bit range checks")]
+pub struct UnprovableKernel(pub ConvolutionKernel);
+
+impl ConvolutionKernel {
+    /// Gaussian blur approximation, normalized by its weight sum
+    pub const BLUR: Self = Self {
+        weights: [[1, 2, 1], [2, 4, 2], [1, 2, 1]],
+        divisor: NonZeroU32::new(16).unwrap(),
+    };
+
+    pub const SHARPEN: Self = Self {
+        weights: [[0, -1, 0], [-1, 5, -1], [0, -1, 0]],
+        divisor: NonZeroU32::MIN,
+    };
+
+    /// Kernel with the given weights, if the circuit can prove it
+    pub fn new(weights: [[i32; 3]; 3], divisor: NonZeroU32) -> Result<Self, UnprovableKernel> {
+        let kernel = Self { weights, divisor };
+        kernel.check()?;
+        Ok(kernel)
+    }
+
+    /// Fail unless the circuit can prove this kernel on every image
+    pub fn check(&self) -> Result<(), UnprovableKernel> {
+        if !self.integer_weights().fits_range_checks() {
+            return Err(UnprovableKernel(*self));
+        }
+        Ok(())
+    }
+
+    /// Per-channel weights with round-half-up bias
+    pub fn integer_weights(&self) -> IntegerWeights<9> {
+        let divisor = self.divisor.get() as u64;
+        let mut weights = [0i64; 9];
+        for (weight, &value) in weights.iter_mut().zip(self.weights.iter().flatten()) {
+            *weight = value as i64;
+        }
+
+        IntegerWeights {
+            weights,
+            bias: (divisor / 2) as i64,
+            divisor,
+        }
+    }
+
+    /// Source coordinates of the 3x3 neighbourhood of `(x, y)`, edges replicated
+    pub fn neighbours(x: usize, y: usize, width: usize, height: usize) -> [(usize, usize); 9] {
+        let clamp = |value: usize, offset: usize, len: usize| (value + offset).saturating_sub(1).min(len - 1);
+        let mut neighbours = [(0, 0); 9];
+        for (i, neighbour) in neighbours.iter_mut().enumerate() {
+            *neighbour = (clamp(x, i % 3, width), clamp(y, i / 3, height));
+        }
+        neighbours
+    }
+}
+
+/// Largest `|shift|` or `|offset|` of a `ToneMap`; anything further saturates
+pub const MAX_TONE_SHIFT: i32 = 255;
+
+/// Per-channel tone curve `contrast * (x + shift - 127.5) + 127.5 + offset`,
+/// rounded half up and clamped
+///
+/// Brightness and contrast are both tone maps, and `planner` folds runs of
+/// them into a single one when that gives the same output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ToneMap {
+    pub shift: i32,
+    pub contrast: Rational,
+    pub offset: i32,
+}
+
+impl ToneMap {
+    pub const IDENTITY: Self = Self {
+        shift: 0,
+        contrast: Rational::ONE,
+        offset: 0,
+    };
+
+    pub fn contrast(contrast: Rational) -> Self {
+        Self { contrast, ..Self::IDENTITY }
+    }
+
+    /// `x + 255 * brightness` rounded half up, i.e. a whole offset
+    pub fn brightness(brightness: Rational) -> Self {
+        let (num, den) = (brightness.numerator(), brightness.denominator());
+        let offset = (510 * num + den).div_euclid(2 * den);
+        Self {
+            offset: offset.clamp(-MAX_TONE_SHIFT as i64, MAX_TONE_SHIFT as i64) as i32,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.shift.abs() <= MAX_TONE_SHIFT && self.offset.abs() <= MAX_TONE_SHIFT
+    }
+
+    pub fn integer_weights(&self) -> IntegerWeights<1> {
+        let (num, den) = (self.contrast.numerator(), self.contrast.denominator());
+        IntegerWeights {
+            weights: [2 * num],
+            bias: 2 * num * self.shift as i64 + 255 * (den - num) + den + 2 * den * self.offset as i64,
+            divisor: 2 * den as u64,
+        }
+    }
+
+    /// Output for every input byte
+    pub fn table(&self) -> [u8; 256] {
+        let weights = self.integer_weights();
+        std::array::from_fn(|x| weights.apply([x as u8]))
+    }
+}
 
 /// Physical transformations (Section 7.3.1)
 pub mod physical {
     use super::*;
 
     pub fn crop(image: &DynamicImage, x: u32, y: u32, width: u32, height: u32) -> DynamicImage {
-        image.crop(x, y, width, height)
+        image.crop_imm(x, y, width, height)
+    }
+
+    /// Fractional bits of the bilinear interpolation weights
+    pub const RESIZE_WEIGHT_BITS: u32 = 8;
+    const RESIZE_WEIGHT_ONE: u64 = 1 << RESIZE_WEIGHT_BITS;
+
+    /// Source samples of one output coordinate for bilinear interpolation
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct BilinearTap {
+        pub lo: usize,
+        pub hi: usize,
+        /// Weight of `hi` in units of `2^-RESIZE_WEIGHT_BITS`
+        pub weight: u64,
+    }
+
+    /// Source index sampled by nearest-neighbour for output index `dst`
+    pub fn nearest_source(dst: usize, src_len: usize, dst_len: usize) -> usize {
+        // Map pixel centres: (dst + 0.5) * src_len / dst_len
+        (((2 * dst + 1) * src_len) / (2 * dst_len)).min(src_len - 1)
+    }
+
+    /// Source indices and weight used by bilinear interpolation for output index `dst`
+    pub fn bilinear_tap(dst: usize, src_len: usize, dst_len: usize) -> BilinearTap {
+        // (dst + 0.5) * src_len / dst_len - 0.5, clamped at the left edge
+        let den = 2 * dst_len;
+        let num = ((2 * dst + 1) * src_len).saturating_sub(dst_len);
+        let lo = (num / den).min(src_len - 1);
+
+        if lo + 1 >= src_len {
+            return BilinearTap { lo, hi: lo, weight: 0 };
+        }
+        let weight = ((num % den) as u64 * RESIZE_WEIGHT_ONE) / den as u64;
+        BilinearTap { lo, hi: lo + 1, weight }
     }
 
-    pub fn resize(image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
-        image.resize(width, height, image::imageops::FilterType::Lanczos3)
+    /// Weights of the `(lo, lo)`, `(hi, lo)`, `(lo, hi)`, `(hi, hi)` samples,
+    /// summing to `2^(2 * RESIZE_WEIGHT_BITS)`
+    pub fn bilinear_weights(x: BilinearTap, y: BilinearTap) -> [u64; 4] {
+        let (wx, wy) = (x.weight, y.weight);
+        let (ix, iy) = (RESIZE_WEIGHT_ONE - wx, RESIZE_WEIGHT_ONE - wy);
+        [ix * iy, wx * iy, ix * wy, wx * wy]
     }
 
-    pub fn rotate(image: &DynamicImage, degrees: f32) -> DynamicImage {
-        match degrees as i32 {
-            90 => image.rotate90(),
-            180 => image.rotate180(),
-            270 => image.rotate270(),
-            _ => image.clone(), // Arbitrary rotation not implemented
+    /// Weighted sum of four samples, rounded half up
+    pub fn bilinear_mix(samples: [u8; 4], weights: [u64; 4]) -> u8 {
+        let shift = 2 * RESIZE_WEIGHT_BITS;
+        let sum: u64 = samples.iter().zip(weights).map(|(&s, w)| s as u64 * w).sum();
+        ((sum + (1 << (shift - 1))) >> shift) as u8
+    }
+
+    /// Deterministic integer resize, bit-identical to the resize chip
+    pub fn resize(image: &DynamicImage, width: u32, height: u32, filter: ResizeFilter) -> DynamicImage {
+        let source = image.to_rgb8();
+        let (src_width, src_height) = (source.width() as usize, source.height() as usize);
+        let mut resized = RgbImage::new(width, height);
+        if src_width == 0 || src_height == 0 {
+            return DynamicImage::ImageRgb8(resized);
+        }
+
+        let (dst_width, dst_height) = (width as usize, height as usize);
+        for y in 0..dst_height {
+            for x in 0..dst_width {
+                let pixel = match filter {
+                    ResizeFilter::Nearest => *source.get_pixel(
+                        nearest_source(x, src_width, dst_width) as u32,
+                        nearest_source(y, src_height, dst_height) as u32,
+                    ),
+                    ResizeFilter::Bilinear => {
+                        let tx = bilinear_tap(x, src_width, dst_width);
+                        let ty = bilinear_tap(y, src_height, dst_height);
+                        let weights = bilinear_weights(tx, ty);
+                        let corners = [(tx.lo, ty.lo), (tx.hi, ty.lo), (tx.lo, ty.hi), (tx.hi, ty.hi)]
+                            .map(|(sx, sy)| *source.get_pixel(sx as u32, sy as u32));
+
+                        image::Rgb([0, 1, 2].map(|c| bilinear_mix(corners.map(|p| p[c]), weights)))
+                    }
+                };
+                resized.put_pixel(x as u32, y as u32, pixel);
+            }
         }
+
+        DynamicImage::ImageRgb8(resized)
+    }
+
+    /// Clockwise rotation; angles that are not a multiple of 90 degrees are rejected
+    pub fn rotate(image: &DynamicImage, degrees: f32) -> Result<DynamicImage, UnsupportedAngle> {
+        Ok(match RightAngle::from_degrees(degrees)? {
+            RightAngle::Deg0 => image.clone(),
+            RightAngle::Deg90 => image.rotate90(),
+            RightAngle::Deg180 => image.rotate180(),
+            RightAngle::Deg270 => image.rotate270(),
+        })
     }
 
     pub fn flip_horizontal(image: &DynamicImage) -> DynamicImage {
@@ -36,76 +433,95 @@ pub mod physical {
         image.flipv()
     }
 
-    pub fn translate(image: &DynamicImage, dx: i32, dy: i32) -> DynamicImage {
-        // Create new image with translated content
-        let (width, height) = image.dimensions();
-        let mut new_image = DynamicImage::new_rgba8(width, height);
+    /// Source pixel of output `(x, y)` after shifting by `(dx, dy)`, or `None`
+    /// where the output is filled
+    pub fn translate_source(x: usize, y: usize, dx: i32, dy: i32, width: usize, height: usize) -> Option<(usize, usize)> {
+        let source_x = usize::try_from(x as i64 - dx as i64).ok().filter(|&sx| sx < width)?;
+        let source_y = usize::try_from(y as i64 - dy as i64).ok().filter(|&sy| sy < height)?;
+        Some((source_x, source_y))
+    }
 
-        for y in 0..height {
-            for x in 0..width {
-                let src_x = x as i32 - dx;
-                let src_y = y as i32 - dy;
+    /// Shift the image by `(dx, dy)`, filling vacated pixels with `fill`
+    pub fn translate(image: &DynamicImage, dx: i32, dy: i32, fill: [u8; 3]) -> DynamicImage {
+        let source = image.to_rgb8();
+        let (width, height) = (source.width() as usize, source.height() as usize);
 
-                if src_x >= 0 && src_x < width as i32 && src_y >= 0 && src_y < height as i32 {
-                    let pixel = image.get_pixel(src_x as u32, src_y as u32);
-                    new_image.put_pixel(x, y, pixel);
-                }
+        let translated = RgbImage::from_fn(source.width(), source.height(), |x, y| {
+            match translate_source(x as usize, y as usize, dx, dy, width, height) {
+                Some((sx, sy)) => *source.get_pixel(sx as u32, sy as u32),
+                None => image::Rgb(fill),
             }
-        }
+        });
 
-        new_image
+        DynamicImage::ImageRgb8(translated)
     }
 }
 
 /// Color space conversions (Section 7.3.2)
+///
+/// JFIF (full-range BT.601) conversions with 16-bit fixed-point weights.
+/// Chroma rounds with `2^15 - 1` so pure blue/red saturate at 255 rather
+/// than overflowing.
 pub mod colorspace {
     use super::*;
 
-    pub fn rgb_to_ycbcr(image: &DynamicImage) -> DynamicImage {
-        let mut ycbcr_image = DynamicImage::new_rgb8(image.width(), image.height());
-
-        for y in 0..image.height() {
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y);
-                let rgb = pixel.to_rgb();
-
-                // YCbCr conversion formulas
-                let r = rgb[0] as f32;
-                let g = rgb[1] as f32;
-                let b = rgb[2] as f32;
+    const ONE: i64 = 1 << 16;
+    const HALF: i64 = 1 << 15;
+
+    /// Luma from (R, G, B)
+    pub const LUMA: IntegerWeights<3> = IntegerWeights {
+        weights: [19595, 38470, 7471],
+        bias: HALF,
+        divisor: ONE as u64,
+    };
+    /// Blue-difference chroma from (R, G, B)
+    pub const CB: IntegerWeights<3> = IntegerWeights {
+        weights: [-11059, -21709, 32768],
+        bias: 128 * ONE + HALF - 1,
+        divisor: ONE as u64,
+    };
+    /// Red-difference chroma from (R, G, B)
+    pub const CR: IntegerWeights<3> = IntegerWeights {
+        weights: [32768, -27439, -5329],
+        bias: 128 * ONE + HALF - 1,
+        divisor: ONE as u64,
+    };
+    /// Red from (Y, Cb, Cr)
+    pub const RED: IntegerWeights<3> = IntegerWeights {
+        weights: [ONE, 0, 91881],
+        bias: HALF - 128 * 91881,
+        divisor: ONE as u64,
+    };
+    /// Green from (Y, Cb, Cr)
+    pub const GREEN: IntegerWeights<3> = IntegerWeights {
+        weights: [ONE, -22554, -46802],
+        bias: HALF + 128 * (22554 + 46802),
+        divisor: ONE as u64,
+    };
+    /// Blue from (Y, Cb, Cr)
+    pub const BLUE: IntegerWeights<3> = IntegerWeights {
+        weights: [ONE, 116130, 0],
+        bias: HALF - 128 * 116130,
+        divisor: ONE as u64,
+    };
 
-                let y = (0.299 * r + 0.587 * g + 0.114 * b) as u8;
-                let cb = (-0.1687 * r - 0.3313 * g + 0.5 * b + 128.0) as u8;
-                let cr = (0.5 * r - 0.4187 * g - 0.0813 * b + 128.0) as u8;
-
-                ycbcr_image.put_pixel(x, y, image::Rgb([y, cb, cr]));
-            }
-        }
-
-        ycbcr_image
+    pub fn rgb_to_ycbcr(image: &DynamicImage) -> DynamicImage {
+        map_pixels(image, [LUMA, CB, CR])
     }
 
     pub fn ycbcr_to_rgb(image: &DynamicImage) -> DynamicImage {
-        let mut rgb_image = DynamicImage::new_rgb8(image.width(), image.height());
-
-        for y in 0..image.height() {
-            for x in 0..image.width() {
-                let pixel = image.get_pixel(x, y);
-                let ycbcr = pixel.to_rgb();
-
-                let y_val = ycbcr[0] as f32;
-                let cb = ycbcr[1] as f32 - 128.0;
-                let cr = ycbcr[2] as f32 - 128.0;
-
-                let r = (y_val + 1.402 * cr).clamp(0.0, 255.0) as u8;
-                let g = (y_val - 0.344136 * cb - 0.714136 * cr).clamp(0.0, 255.0) as u8;
-                let b = (y_val + 1.772 * cb).clamp(0.0, 255.0) as u8;
+        map_pixels(image, [RED, GREEN, BLUE])
+    }
 
-                rgb_image.put_pixel(x, y, image::Rgb([r, g, b]));
-            }
+    /// Apply one weight set per output channel to every pixel
+    fn map_pixels(image: &DynamicImage, channels: [IntegerWeights<3>; 3]) -> DynamicImage {
+        let mut mapped = image.to_rgb8();
+        for pixel in mapped.pixels_mut() {
+            let input = pixel.0;
+            pixel.0 = channels.map(|weights| weights.apply(input));
         }
 
-        rgb_image
+        DynamicImage::ImageRgb8(mapped)
     }
 }
 
@@ -113,153 +529,141 @@ pub mod colorspace {
 pub mod filters {
     use super::*;
 
+    /// Integer BT.601 luma, replicated into every channel
     pub fn grayscale(image: &DynamicImage) -> DynamicImage {
-        image.grayscale()
+        let mut gray = image.to_rgb8();
+        for pixel in gray.pixels_mut() {
+            let luma = colorspace::LUMA.apply(pixel.0);
+            pixel.0 = [luma; 3];
+        }
+
+        DynamicImage::ImageRgb8(gray)
     }
 
     pub fn sharpen(image: &DynamicImage) -> DynamicImage {
-        // Simple sharpening using convolution
-        let kernel = [
-            [0, -1, 0],
-            [-1, 5, -1],
-            [0, -1, 0]
-        ];
-        apply_convolution(image, &kernel)
+        convolve(image, &ConvolutionKernel::SHARPEN)
     }
 
     pub fn blur(image: &DynamicImage) -> DynamicImage {
-        // Gaussian blur approximation
-        let kernel = [
-            [1, 2, 1],
-            [2, 4, 2],
-            [1, 2, 1]
-        ];
-        apply_convolution(image, &kernel)
-    }
-
-    pub fn contrast(image: &DynamicImage, factor: f32) -> DynamicImage {
-        let mut contrasted = image.clone();
-        let (width, height) = image.dimensions();
-
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel(x, y);
-                let mut rgb = pixel.to_rgb();
-
-                for i in 0..3 {
-                    let val = rgb[i] as f32 / 255.0;
-                    let contrasted_val = ((val - 0.5) * factor + 0.5).clamp(0.0, 1.0);
-                    rgb[i] = (contrasted_val * 255.0) as u8;
-                }
-
-                contrasted.put_pixel(x, y, image::Rgb(rgb));
-            }
-        }
-
-        contrasted
+        convolve(image, &ConvolutionKernel::BLUR)
     }
 
-    pub fn brightness(image: &DynamicImage, factor: f32) -> DynamicImage {
-        let mut brightened = image.clone();
-        let (width, height) = image.dimensions();
-
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel(x, y);
-                let mut rgb = pixel.to_rgb();
-
-                for i in 0..3 {
-                    rgb[i] = ((rgb[i] as f32 + factor * 255.0) as u32).clamp(0, 255) as u8;
-                }
-
-                brightened.put_pixel(x, y, image::Rgb(rgb));
-            }
+    /// `(x - 127.5) * contrast + 127.5`, rounded half up and clamped
+    pub fn contrast_weights(contrast: Rational) -> IntegerWeights<1> {
+        let (num, den) = (contrast.numerator(), contrast.denominator());
+        IntegerWeights {
+            weights: [2 * num],
+            bias: 255 * (den - num) + den,
+            divisor: 2 * den as u64,
         }
-
-        brightened
     }
 
-    pub fn white_balance(image: &DynamicImage) -> DynamicImage {
-        // Simple white balance using gray world assumption
-        let (width, height) = image.dimensions();
-        let mut r_sum = 0u64;
-        let mut g_sum = 0u64;
-        let mut b_sum = 0u64;
-
-        // Calculate average color values
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel(x, y);
-                let rgb = pixel.to_rgb();
-                r_sum += rgb[0] as u64;
-                g_sum += rgb[1] as u64;
-                b_sum += rgb[2] as u64;
-            }
+    /// `x + 255 * brightness`, rounded half up and clamped
+    pub fn brightness_weights(brightness: Rational) -> IntegerWeights<1> {
+        let (num, den) = (brightness.numerator(), brightness.denominator());
+        IntegerWeights {
+            weights: [2 * den],
+            bias: 510 * num + den,
+            divisor: 2 * den as u64,
         }
+    }
 
-        let total_pixels = (width * height) as f32;
-        let avg_r = r_sum as f32 / total_pixels;
-        let avg_g = g_sum as f32 / total_pixels;
-        let avg_b = b_sum as f32 / total_pixels;
+    pub fn tone_map(image: &DynamicImage, tone: &ToneMap) -> DynamicImage {
+        map_channels(image, [tone.integer_weights(); 3])
+    }
 
-        // Calculate correction factors
-        let avg_gray = (avg_r + avg_g + avg_b) / 3.0;
-        let r_factor = avg_gray / avg_r;
-        let g_factor = avg_gray / avg_g;
-        let b_factor = avg_gray / avg_b;
+    pub fn contrast(image: &DynamicImage, contrast: Rational) -> DynamicImage {
+        map_channels(image, [contrast_weights(contrast); 3])
+    }
 
-        // Apply white balance
-        let mut balanced = image.clone();
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = image.get_pixel(x, y);
-                let mut rgb = pixel.to_rgb();
+    pub fn brightness(image: &DynamicImage, brightness: Rational) -> DynamicImage {
+        map_channels(image, [brightness_weights(brightness); 3])
+    }
 
-                rgb[0] = ((rgb[0] as f32 * r_factor).clamp(0.0, 255.0)) as u8;
-                rgb[1] = ((rgb[1] as f32 * g_factor).clamp(0.0, 255.0)) as u8;
-                rgb[2] = ((rgb[2] as f32 * b_factor).clamp(0.0, 255.0)) as u8;
+    /// Fractional bits of the white-balance gains
+    pub const WHITE_BALANCE_GAIN_BITS: u32 = 8;
 
-                balanced.put_pixel(x, y, image::Rgb(rgb));
+    /// Per-channel averages `floor(Σ channel / pixels)`
+    pub fn channel_averages(image: &RgbImage) -> [u64; 3] {
+        let count = image.pixels().len() as u64;
+        if count == 0 {
+            return [0; 3];
+        }
+
+        let mut sums = [0u64; 3];
+        for pixel in image.pixels() {
+            for (sum, &channel) in sums.iter_mut().zip(&pixel.0) {
+                *sum += channel as u64;
             }
         }
+        sums.map(|sum| sum / count)
+    }
 
-        balanced
+    /// Gray-world gains `avg_gray / avg_c` in `WHITE_BALANCE_GAIN_BITS` fixed point
+    ///
+    /// `avg_gray` is the mean of the channel averages; a channel whose average
+    /// is zero is left unchanged.
+    pub fn white_balance_gains(averages: [u64; 3]) -> [u64; 3] {
+        let total: u64 = averages.iter().sum();
+        averages.map(|average| white_balance_gain(average, total))
     }
 
-    fn apply_convolution(image: &DynamicImage, kernel: &[[i32; 3]; 3]) -> DynamicImage {
-        let (width, height) = image.dimensions();
-        let mut convolved = DynamicImage::new_rgb8(width, height);
+    /// Gain of one channel given its average and the sum of all three averages
+    pub fn white_balance_gain(average: u64, total: u64) -> u64 {
+        match average {
+            0 => 1 << WHITE_BALANCE_GAIN_BITS,
+            _ => (total << WHITE_BALANCE_GAIN_BITS) / (3 * average),
+        }
+    }
 
-        for y in 1..height - 1 {
-            for x in 1..width - 1 {
-                let mut r_sum = 0i32;
-                let mut g_sum = 0i32;
-                let mut b_sum = 0i32;
+    /// Multiply a channel by its gain, rounded half up and clamped
+    pub fn white_balance_weights(gain: u64) -> IntegerWeights<1> {
+        IntegerWeights {
+            weights: [gain as i64],
+            bias: 1 << (WHITE_BALANCE_GAIN_BITS - 1),
+            divisor: 1 << WHITE_BALANCE_GAIN_BITS,
+        }
+    }
 
-                for ky in -1..=1 {
-                    for kx in -1..=1 {
-                        let pixel = image.get_pixel(
-                            (x as i32 + kx) as u32,
-                            (y as i32 + ky) as u32
-                        );
-                        let rgb = pixel.to_rgb();
-                        let kernel_val = kernel[(ky + 1) as usize][(kx + 1) as usize];
+    /// Gray-world white balance
+    pub fn white_balance(image: &DynamicImage) -> DynamicImage {
+        let gains = white_balance_gains(channel_averages(&image.to_rgb8()));
+        map_channels(image, gains.map(white_balance_weights))
+    }
 
-                        r_sum += rgb[0] as i32 * kernel_val;
-                        g_sum += rgb[1] as i32 * kernel_val;
-                        b_sum += rgb[2] as i32 * kernel_val;
-                    }
-                }
+    /// Apply one single-input map per channel to every pixel
+    fn map_channels(image: &DynamicImage, channels: [IntegerWeights<1>; 3]) -> DynamicImage {
+        let mut mapped = image.to_rgb8();
+        for pixel in mapped.pixels_mut() {
+            for (channel, weights) in pixel.0.iter_mut().zip(&channels) {
+                *channel = weights.apply([*channel]);
+            }
+        }
 
-                let r = r_sum.clamp(0, 255) as u8;
-                let g = g_sum.clamp(0, 255) as u8;
-                let b = b_sum.clamp(0, 255) as u8;
+        DynamicImage::ImageRgb8(mapped)
+    }
 
-                convolved.put_pixel(x, y, image::Rgb([r, g, b]));
+    /// Integer 3x3 convolution, bit-identical to `chips::ConvolutionChip`
+    ///
+    /// Borders replicate the nearest edge pixel, and each channel is rounded
+    /// half up and clamped to `[0, 255]`.
+    pub fn convolve(image: &DynamicImage, kernel: &ConvolutionKernel) -> DynamicImage {
+        let source = image.to_rgb8();
+        let (width, height) = source.dimensions();
+        let weights = kernel.integer_weights();
+        let mut convolved = RgbImage::new(width, height);
+
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let neighbours = ConvolutionKernel::neighbours(x, y, width as usize, height as usize)
+                    .map(|(sx, sy)| *source.get_pixel(sx as u32, sy as u32));
+                let pixel = [0, 1, 2].map(|c| weights.apply(neighbours.map(|p| p[c])));
+
+                convolved.put_pixel(x as u32, y as u32, image::Rgb(pixel));
             }
         }
 
-        convolved
+        DynamicImage::ImageRgb8(convolved)
     }
 }
 
@@ -267,13 +671,14 @@ pub mod filters {
 pub mod fused {
     use super::*;
 
-    pub fn crop_resize(image: &DynamicImage, crop_x: u32, crop_y: u32, crop_w: u32, crop_h: u32, resize_w: u32, resize_h: u32) -> DynamicImage {
+    #[allow(clippy::too_many_arguments)]
+    pub fn crop_resize(image: &DynamicImage, crop_x: u32, crop_y: u32, crop_w: u32, crop_h: u32, resize_w: u32, resize_h: u32, filter: ResizeFilter) -> DynamicImage {
         // Fuse crop + resize into single operation
         let cropped = physical::crop(image, crop_x, crop_y, crop_w, crop_h);
-        physical::resize(&cropped, resize_w, resize_h)
+        physical::resize(&cropped, resize_w, resize_h, filter)
     }
 
-    pub fn grayscale_contrast(image: &DynamicImage, contrast: f32) -> DynamicImage {
+    pub fn grayscale_contrast(image: &DynamicImage, contrast: Rational) -> DynamicImage {
         // Fuse grayscale + contrast
         let gray = filters::grayscale(image);
         filters::contrast(&gray, contrast)
@@ -308,17 +713,85 @@ pub fn image_to_field_elements(image: &DynamicImage) -> Vec<Vec<Vec<Fp>>> {
 pub fn field_elements_to_image(field_image: &[Vec<Vec<Fp>>]) -> DynamicImage {
     let height = field_image.len();
     let width = field_image.first().map(|row| row.len()).unwrap_or(0);
-    let mut image = DynamicImage::new_rgb8(width as u32, height as u32);
+    let mut image = RgbImage::new(width as u32, height as u32);
 
     for (y, row) in field_image.iter().enumerate() {
         for (x, pixel) in row.iter().enumerate() {
-            let r = pixel[0].get_lower_32() as u8;
-            let g = pixel[1].get_lower_32() as u8;
-            let b = pixel[2].get_lower_32() as u8;
+            let r = pixel[0].to_repr()[0];
+            let g = pixel[1].to_repr()[0];
+            let b = pixel[2].to_repr()[0];
 
             image.put_pixel(x as u32, y as u32, image::Rgb([r, g, b]));
         }
     }
 
-    image
+    DynamicImage::ImageRgb8(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transformation;
+
+    #[test]
+    fn rational_accepts_legacy_floats() {
+        let contrast: Transformation = serde_json::from_str(r#"{"Contrast":1.5}"#).unwrap();
+        assert!(matches!(contrast, Transformation::Contrast(value) if value == Rational::new(3, 2).unwrap()));
+
+        let brightness: Transformation = serde_json::from_str(r#"{"Brightness":-0.1}"#).unwrap();
+        assert!(matches!(brightness, Transformation::Brightness(value) if value == Rational::new(-205, 2048).unwrap()));
+    }
+
+    #[test]
+    fn rational_round_trips_as_ratio() {
+        let value = Rational::new(-6, 4).unwrap();
+        let json = serde_json::to_string(&value).unwrap();
+
+        assert_eq!(json, r#"{"numerator":-3,"denominator":2}"#);
+        assert_eq!(serde_json::from_str::<Rational>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn rational_rejects_out_of_range_values() {
+        assert_eq!(Rational::new(1, 0), Err(RationalError::Denominator(0)));
+        assert_eq!(Rational::new(1, -2), Err(RationalError::Denominator(-2)));
+        assert!(matches!(Rational::new(65, 1), Err(RationalError::Magnitude(_))));
+        assert!(matches!(Rational::from_f64(f64::NAN), Err(RationalError::NotFinite(_))));
+        assert!(serde_json::from_str::<Rational>("100.0").is_err());
+    }
+
+    #[test]
+    fn right_angles_match_image_rotation() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 0])));
+        for degrees in [0.0, 90.0, 180.0, 270.0, -90.0, 450.0] {
+            let angle = RightAngle::from_degrees(degrees).unwrap();
+            let rotated = physical::rotate(&image, degrees).unwrap().to_rgb8();
+            assert_eq!(angle.output_size(3, 2), (rotated.width() as usize, rotated.height() as usize));
+
+            for (x, y, pixel) in rotated.enumerate_pixels() {
+                let (sx, sy) = angle.source(x as usize, y as usize, 3, 2);
+                assert_eq!([pixel[0], pixel[1]], [sx as u8, sy as u8]);
+            }
+        }
+    }
+
+    #[test]
+    fn translate_fills_vacated_pixels() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| image::Rgb([x as u8, y as u8, 9])));
+        let translated = physical::translate(&image, 1, -1, [7, 8, 9]).to_rgb8();
+
+        assert_eq!(translated.dimensions(), (3, 2));
+        assert_eq!(translated.get_pixel(0, 0).0, [7, 8, 9]);
+        assert_eq!(translated.get_pixel(1, 0).0, [0, 1, 9]);
+        assert_eq!(translated.get_pixel(2, 0).0, [1, 1, 9]);
+        assert_eq!(translated.get_pixel(2, 1).0, [7, 8, 9]);
+    }
+
+    #[test]
+    fn arbitrary_angles_are_rejected() {
+        let image = DynamicImage::new_rgb8(2, 2);
+        for degrees in [45.0, 89.5, f32::NAN, f32::INFINITY] {
+            assert!(physical::rotate(&image, degrees).is_err());
+        }
+    }
 }